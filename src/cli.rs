@@ -1,7 +1,7 @@
 // src/cli.rs
 
-use crate::constants;
-use clap::{command, crate_version, Parser, ValueEnum};
+use crate::{config::secret::TokenStorage, constants, utils};
+use clap::{crate_version, Args, Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
 /// 定义日志输出级别
@@ -15,6 +15,45 @@ pub enum LogLevel {
     Trace,
 }
 
+/// 定义程序输出格式
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// 面向人的彩色文本
+    #[default]
+    Text,
+    /// 逐行输出的 JSON 事件 (NDJSON)，便于脚本和 CI 解析
+    Json,
+}
+
+/// 定义 M3U8 视频的输出容器格式
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum VideoContainer {
+    /// 直接拼接分片得到的 MPEG-TS 文件
+    #[default]
+    Ts,
+    /// 转封装为 MP4 (不重新编码，仅支持 H.264 + AAC)。
+    /// 转封装时合并好的 TS 和生成中的 MP4 同时存在，需要约两倍视频大小的磁盘空间
+    Mp4,
+}
+
+impl VideoContainer {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Ts => "ts",
+            Self::Mp4 => "mp4",
+        }
+    }
+}
+
+/// 定义下载完成后写入的元数据附属文件格式
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MetadataFormat {
+    /// 每个教材/课程一个 `<标题>.resource.json`，包含标签、教师、章节、更新时间和文件列表
+    Json,
+    /// 每个视频一个 Kodi/Jellyfin 风格的 `.nfo` 文件
+    Nfo,
+}
+
 /// 定义可下载的资源类型
 #[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ResourceType {
@@ -35,6 +74,11 @@ pub enum ResourceType {
     arg_required_else_help = true,
     disable_help_flag = true,
     disable_version_flag = true,
+    disable_help_subcommand = true,
+    propagate_version = true,
+    // 子命令与旧版的模式参数 (--url、-b 等) 不能混用
+    subcommand_negates_reqs = true,
+    args_conflicts_with_subcommands = true,
 )]
 #[command(group(
     clap::ArgGroup::new("mode")
@@ -42,20 +86,23 @@ pub enum ResourceType {
         .args(&["interactive", "url", "id", "batch_file", "token_help"]),
 ))]
 pub struct Cli {
-    // --- 运行模式 (Mode) ---
-    /// 启动交互式会话，逐一输入链接
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    // --- 运行模式 (Mode)，为兼容旧版脚本而保留，等价于对应的子命令 ---
+    /// 启动交互式会话，逐一输入链接 (等同于 `interactive` 子命令)
     #[arg(short, long, action = clap::ArgAction::SetTrue, help_heading = "Mode",
         // 添加参数冲突规则
-        conflicts_with_all = &["select", "video_quality", "audio_format"]
+        conflicts_with_all = &["select", "video_quality", "audio_format", "dry_run", "output_format"]
     )]
     pub interactive: bool,
-    /// 指定要下载的单个资源链接
+    /// 指定要下载的单个资源链接 (等同于 `download <URL>`)
     #[arg(long, help_heading = "Mode")]
     pub url: Option<String>,
-    /// 通过资源ID下载 (需配合 --type 使用)
+    /// 通过资源ID下载 (需配合 --type 使用，等同于 `download <ID>`)
     #[arg(long, help_heading = "Mode", requires = "type")]
     pub id: Option<String>,
-    /// 从文本文件批量下载多个链接或ID (每行一个)
+    /// 从文本文件批量下载多个链接或ID (每行一个，等同于 `batch <FILE>`)
     #[arg(
         short,
         long,
@@ -64,14 +111,56 @@ pub struct Cli {
         requires = "type"
     )]
     pub batch_file: Option<PathBuf>,
-    /// 显示如何获取 Access Token 的指南并退出
+    /// 显示如何获取 Access Token 的指南并退出 (等同于 `token guide`)
     #[arg(long, action = clap::ArgAction::SetTrue, help_heading = "Mode")]
     pub token_help: bool,
 
-    // --- 下载选项 (Options) ---
-    /// [非交互模式] 指定下载项 (例如 '1-5,8', 'all')
-    #[arg(long, default_value_t = constants::DEFAULT_SELECTION.to_string(), value_name = "SELECTION", help_heading = "Options")]
-    pub select: String,
+    #[command(flatten)]
+    pub selection: SelectionOptions,
+    #[command(flatten)]
+    pub options: DownloadOptions,
+
+    // --- 通用选项 (General) ---
+    /// 显示此帮助信息并退出
+    #[arg(short = 'h', long, action = clap::ArgAction::Help, global = true, help_heading = "General")]
+    _help: Option<bool>,
+    /// 显示版本信息并退出
+    #[arg(short = 'V', long, action = clap::ArgAction::Version, global = true, help_heading = "General")]
+    _version: Option<bool>,
+    /// 使用配置文件中的指定 profile (Token、网络设置、保存目录)，也可通过环境变量 SED_DL_PROFILE 设置
+    #[arg(long, value_name = "NAME", global = true, help_heading = "General")]
+    pub profile: Option<String>,
+    /// (隐藏参数) 设置日志文件的输出级别，用于调试
+    #[arg(long, value_enum, default_value_t = LogLevel::Off, global = true, hide = true)]
+    pub log_level: LogLevel,
+}
+
+/// 非交互模式下控制“下载哪些文件”以及输出方式的选项
+#[derive(Args, Debug, Clone)]
+pub struct SelectionOptions {
+    /// [非交互模式] 指定下载项 (例如 '1-5,8', 'all'；默认 'all')
+    #[arg(long, value_name = "SELECTION", help_heading = "Options")]
+    pub select: Option<String>,
+    /// [ID/批量模式] 指定资源类型
+    #[arg(long, value_enum, help_heading = "Options")] // 将类型改为 value_enum
+    pub r#type: Option<ResourceType>, // 将类型从 String 改为 ResourceType
+    /// 选择视频清晰度: 'best'(最高), 'worst'(最低), 或具体值 '720' 等
+    #[arg(short='q', long, default_value_t = constants::DEFAULT_VIDEO_QUALITY.to_string(), help_heading = "Options")]
+    pub video_quality: String,
+    /// [教材模式] 选择音频格式: 'mp3', 'm4a' 等
+    #[arg(long, default_value_t = constants::DEFAULT_AUDIO_FORMAT.to_string(), help_heading = "Options")]
+    pub audio_format: String,
+    /// 只解析并列出将要下载的文件 (路径、链接、大小等)，不实际下载
+    #[arg(long, action = clap::ArgAction::SetTrue, help_heading = "Options")]
+    pub dry_run: bool,
+    /// 设置输出格式: 'text' (默认) 或 'json' (逐行输出结构化事件，不显示进度条)
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text, value_name = "FORMAT", help_heading = "Options")]
+    pub output_format: OutputFormat,
+}
+
+/// 所有下载模式 (包括交互模式) 共用的选项
+#[derive(Args, Debug, Clone)]
+pub struct DownloadOptions {
     /// 按文件扩展名过滤，只下载指定类型的文件 (例如: pdf,mp3)
     #[arg(
         long,
@@ -80,39 +169,350 @@ pub struct Cli {
         help_heading = "Options"
     )]
     pub filter_ext: Option<Vec<String>>,
-    /// [ID/批量模式] 指定资源类型
-    #[arg(long, value_enum, help_heading = "Options")] // 将类型改为 value_enum
-    pub r#type: Option<ResourceType>, // 将类型从 String 改为 ResourceType
     /// 提供访问令牌 (Access Token)，优先级最高
     #[arg(long, value_name = "TOKEN", help_heading = "Options")]
     pub token: Option<String>,
+    /// Token 即将过期时只提示警告并继续下载 (默认在剩余不足 30 分钟时中止)
+    #[arg(long, action = clap::ArgAction::SetTrue, help_heading = "Options")]
+    pub ignore_token_expiry: bool,
     /// 强制重新下载已存在的文件
     #[arg(short, long, action = clap::ArgAction::SetTrue, help_heading = "Options")]
     pub force_redownload: bool,
-    /// 选择视频清晰度: 'best'(最高), 'worst'(最低), 或具体值 '720' 等
-    #[arg(short='q', long, default_value_t = constants::DEFAULT_VIDEO_QUALITY.to_string(), help_heading = "Options")]
-    pub video_quality: String,
-    /// [教材模式] 选择音频格式: 'mp3', 'm4a' 等
-    #[arg(long, default_value_t = constants::DEFAULT_AUDIO_FORMAT.to_string(), help_heading = "Options")]
-    pub audio_format: String,
+    /// 仅对指定资源ID的文件强制重新下载 (多个ID用逗号分隔)
+    #[arg(
+        long,
+        value_name = "IDS",
+        value_delimiter = ',',
+        help_heading = "Options"
+    )]
+    pub force_redownload_ids: Option<Vec<String>>,
     /// 将所有文件下载到输出目录的根路径，不创建额外的子目录
     #[arg(long, action = clap::ArgAction::SetTrue, help_heading = "Options")]
     pub flat: bool,
     /// 设置最大并发下载数
     #[arg(short, long, value_parser = clap::value_parser!(usize), help_heading = "Options")]
     pub workers: Option<usize>,
-    /// 设置文件保存目录
-    #[arg(short, long, value_name = "DIR", default_value_os_t = PathBuf::from(constants::DEFAULT_SAVE_DIR), help_heading = "Options")]
-    pub output: PathBuf,
+    /// 设置文件保存目录 [默认: profile 中的设置，否则为 downloads]
+    #[arg(short, long, value_name = "DIR", help_heading = "Options")]
+    pub output: Option<PathBuf>,
+    /// 文档/课件的格式偏好，按优先级排列 (例如: pptx,pdf)；'*' 匹配任意格式，'all' 下载所有格式
+    #[arg(
+        long,
+        value_name = "FORMATS",
+        value_delimiter = ',',
+        default_value = constants::DEFAULT_DOCUMENT_FORMATS,
+        help_heading = "Options"
+    )]
+    pub document_formats: Vec<String>,
+    /// 设置视频的保存格式: 'ts' (默认) 或 'mp4' (内置转封装，无需 ffmpeg；转封装期间需要约两倍视频大小的磁盘空间)
+    #[arg(long, value_enum, default_value_t = VideoContainer::Ts, value_name = "FORMAT", help_heading = "Options")]
+    pub video_container: VideoContainer,
+    /// 同时下载教材封面和课程视频的缩略图，保存在对应资源旁边
+    #[arg(long, action = clap::ArgAction::SetTrue, help_heading = "Options")]
+    pub with_covers: bool,
+    /// 下载完成后写入元数据附属文件，可多选 (例如: json,nfo)
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMATS",
+        value_delimiter = ',',
+        help_heading = "Options"
+    )]
+    pub write_metadata: Vec<MetadataFormat>,
+    /// 不读取也不写入本地的 API 响应缓存，所有元数据都直接从服务器获取
+    #[arg(long, action = clap::ArgAction::SetTrue, help_heading = "Options")]
+    pub no_cache: bool,
+    /// 将接口响应、M3U8 播放列表和密钥交换记录到指定目录 (Token 会被隐去)，便于离线复现问题
+    #[arg(long, value_name = "DIR", conflicts_with = "replay", help_heading = "Options")]
+    pub record: Option<PathBuf>,
+    /// 从 --record 记录的目录回放接口响应，不再从网络获取元数据
+    #[arg(long, value_name = "DIR", help_heading = "Options")]
+    pub replay: Option<PathBuf>,
+}
 
-    // --- 通用选项 (General) ---
-    /// 显示此帮助信息并退出
-    #[arg(short = 'h', long, action = clap::ArgAction::Help, global = true, help_heading = "General")]
-    _help: Option<bool>,
-    /// 显示版本信息并退出
-    #[arg(short = 'V', long, action = clap::ArgAction::Version, global = true, help_heading = "General")]
-    _version: Option<bool>,
-    /// (隐藏参数) 设置日志文件的输出级别，用于调试
-    #[arg(long, value_enum, default_value_t = LogLevel::Off, global = true, hide = true)]
-    pub log_level: LogLevel,
+/// 教材目录 (browse/search) 的筛选条件，按标签名匹配 (包含即可，不区分大小写)
+#[derive(Args, Debug, Clone, Default)]
+pub struct CatalogFilter {
+    /// 按教材标题或任意标签搜索的关键字
+    #[arg(value_name = "KEYWORD")]
+    pub keyword: Option<String>,
+    /// 学段 (例如: 小学、初中、高中)
+    #[arg(long, value_name = "STAGE", help_heading = "Filters")]
+    pub stage: Option<String>,
+    /// 年级 (例如: 一年级)
+    #[arg(long, value_name = "GRADE", help_heading = "Filters")]
+    pub grade: Option<String>,
+    /// 学科 (例如: 语文)
+    #[arg(long, value_name = "SUBJECT", help_heading = "Filters")]
+    pub subject: Option<String>,
+    /// 版本 (例如: 统编版、人教版)
+    #[arg(long = "edition", value_name = "EDITION", help_heading = "Filters")]
+    pub version: Option<String>,
+    /// 册次 (例如: 上册)
+    #[arg(long, value_name = "VOLUME", help_heading = "Filters")]
+    pub volume: Option<String>,
+}
+
+impl SelectionOptions {
+    /// `--select` 的取值，未指定时为 'all'
+    pub fn selection(&self) -> &str {
+        self.select.as_deref().unwrap_or(constants::DEFAULT_SELECTION)
+    }
+}
+
+impl CatalogFilter {
+    /// 是否没有任何关键字或标签筛选条件
+    pub fn is_empty(&self) -> bool {
+        self.keyword.is_none() && self.dimension_filters().iter().all(|(.., wanted)| wanted.is_none())
+    }
+
+    /// 各标签维度 (维度 ID、名称) 及其筛选条件，按目录层级排列
+    pub fn dimension_filters(&self) -> [(&'static str, &'static str, Option<&str>); 5] {
+        use constants::api::dimensions::*;
+        [
+            (STAGE, "学段", self.stage.as_deref()),
+            (GRADE, "年级", self.grade.as_deref()),
+            (SUBJECT, "学科", self.subject.as_deref()),
+            (VERSION, "版本", self.version.as_deref()),
+            (VOLUME, "册次", self.volume.as_deref()),
+        ]
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// 下载单个资源链接或ID (使用ID时需配合 --type)
+    Download {
+        /// 资源链接或资源ID
+        #[arg(value_name = "URL|ID")]
+        input: String,
+        #[command(flatten)]
+        selection: SelectionOptions,
+        #[command(flatten)]
+        options: DownloadOptions,
+    },
+    /// 从文本文件批量下载多个链接或ID (每行一个)
+    Batch {
+        #[arg(value_name = "FILE", requires = "type")]
+        file: PathBuf,
+        #[command(flatten)]
+        selection: SelectionOptions,
+        #[command(flatten)]
+        options: DownloadOptions,
+    },
+    /// 启动交互式会话，逐一输入链接
+    Interactive {
+        #[command(flatten)]
+        options: DownloadOptions,
+    },
+    /// 解析并列出将要下载的文件，不实际下载 (等同于 --dry-run)
+    #[command(group(
+        clap::ArgGroup::new("list_source")
+            .required(true)
+            .args(&["input", "batch_file"]),
+    ))]
+    List {
+        /// 资源链接或资源ID
+        #[arg(value_name = "URL|ID")]
+        input: Option<String>,
+        /// 从文本文件读取多个链接或ID (每行一个)
+        #[arg(short, long, value_name = "FILE", requires = "type")]
+        batch_file: Option<PathBuf>,
+        #[command(flatten)]
+        selection: SelectionOptions,
+        #[command(flatten)]
+        options: DownloadOptions,
+    },
+    /// 浏览/搜索教材目录，按学段、年级、学科、版本、册次筛选，并可直接下载筛选结果
+    #[command(visible_alias = "search")]
+    Browse {
+        #[command(flatten)]
+        filter: CatalogFilter,
+        /// 逐级交互式选择筛选条件和教材，然后下载
+        #[arg(short, long, action = clap::ArgAction::SetTrue, conflicts_with = "download")]
+        interactive: bool,
+        /// 下载筛选出的教材 (配合 --select 只下载其中的部分条目)
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        download: bool,
+        #[command(flatten)]
+        selection: SelectionOptions,
+        #[command(flatten)]
+        options: DownloadOptions,
+    },
+    /// 按章节目录下载一本教材的全部同步课堂课时
+    Lessons {
+        /// 教材链接或教材ID
+        #[arg(value_name = "URL|ID")]
+        textbook: String,
+        /// 只下载章节标题中包含该关键字的课时 (例如: 第一单元)
+        #[arg(long, value_name = "KEYWORD")]
+        chapter: Option<String>,
+        #[command(flatten)]
+        selection: SelectionOptions,
+        #[command(flatten)]
+        options: DownloadOptions,
+    },
+    /// 管理 Access Token
+    Token {
+        #[command(subcommand)]
+        action: TokenCommand,
+    },
+    /// 查看配置文件
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+    /// 根据下载记录校验已下载的文件是否完整
+    Verify {
+        /// 只校验此目录下的文件 (默认校验所有记录)
+        #[arg(value_name = "DIR")]
+        dir: Option<PathBuf>,
+        /// 同时重新计算 MD5 (较慢)，默认只比较文件大小
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        md5: bool,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum TokenCommand {
+    #[command(flatten)]
+    Local(LocalTokenCommand),
+    /// 验证当前 Token 是否有效
+    Validate {
+        /// 用于探测的资源链接或ID (建议选择需要登录才能下载的资源)
+        #[arg(value_name = "URL|ID")]
+        input: Option<String>,
+        /// 使用资源ID时，指定资源类型
+        #[arg(long, value_enum)]
+        r#type: Option<ResourceType>,
+        /// 要验证的 Token (默认使用当前生效的 Token)
+        #[arg(long, value_name = "TOKEN")]
+        token: Option<String>,
+    },
+}
+
+/// 无需网络客户端、只读写本地配置的 `token` 子命令
+#[derive(Subcommand, Debug, Clone)]
+pub enum LocalTokenCommand {
+    /// 显示如何获取 Access Token 的指南
+    Guide,
+    /// 显示当前生效的 Token、其来源以及有效期
+    Show {
+        /// 显示完整的 Token (默认只显示首尾几个字符)
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        reveal: bool,
+    },
+    /// 保存 Token 到配置文件 (从标准输入读取；在终端中运行时为隐藏输入)
+    Set,
+    /// 从配置文件中删除已保存的 Token
+    Clear,
+    /// 将已保存的 Token 迁移到另一种存储方式 (并更新配置文件中的 token_storage)
+    Migrate {
+        /// 目标存储方式
+        #[arg(value_enum, value_name = "STORAGE")]
+        to: TokenStorage,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ConfigCommand {
+    /// 显示配置文件的路径
+    Path,
+    /// 显示当前配置文件的内容 (Token 会被隐藏)
+    Show,
+}
+
+impl Cli {
+    /// 当前选择的 profile：命令行 `--profile` 优先，其次为环境变量 `SED_DL_PROFILE`。
+    pub fn active_profile(&self) -> Option<String> {
+        self.profile
+            .clone()
+            .or_else(|| std::env::var(constants::PROFILE_ENV_VAR).ok())
+            .filter(|name| !name.is_empty())
+    }
+
+    /// 将子命令形式的参数折叠到旧版的扁平字段上，使下游逻辑只需处理一种形式。
+    /// `token`、`config`、`verify` 等不涉及下载的子命令保留在 `command` 中，由调用方单独分发。
+    pub fn normalize(mut self) -> Self {
+        match self.command.clone() {
+            Some(Command::Download {
+                input,
+                selection,
+                options,
+            }) => {
+                if utils::is_resource_id(&input) {
+                    self.id = Some(input);
+                } else {
+                    self.url = Some(input);
+                }
+                self.selection = selection;
+                self.options = options;
+            }
+            Some(Command::Batch {
+                file,
+                selection,
+                options,
+            }) => {
+                self.batch_file = Some(file);
+                self.selection = selection;
+                self.options = options;
+            }
+            Some(Command::Interactive { options }) => {
+                self.interactive = true;
+                self.options = options;
+            }
+            Some(Command::List {
+                input,
+                batch_file,
+                selection,
+                options,
+            }) => {
+                match input {
+                    Some(input) if utils::is_resource_id(&input) => self.id = Some(input),
+                    Some(input) => self.url = Some(input),
+                    None => self.batch_file = batch_file,
+                }
+                self.selection = SelectionOptions {
+                    dry_run: true,
+                    ..selection
+                };
+                self.options = options;
+            }
+            Some(Command::Browse {
+                interactive,
+                selection,
+                options,
+                ..
+            }) => {
+                // 目录中只有教材，筛选结果以教材 ID 的形式进入批量下载流程
+                self.interactive = interactive;
+                self.selection = SelectionOptions {
+                    r#type: Some(ResourceType::TchMaterial),
+                    ..selection
+                };
+                self.options = options;
+            }
+            Some(Command::Lessons {
+                selection, options, ..
+            }) => {
+                // 每个课时以同步课堂 ID 的形式进入批量下载流程
+                self.selection = SelectionOptions {
+                    r#type: Some(ResourceType::SyncClassroom),
+                    ..selection
+                };
+                self.options = options;
+            }
+            Some(Command::Token {
+                action: TokenCommand::Local(LocalTokenCommand::Guide),
+            }) => self.token_help = true,
+            Some(Command::Token {
+                action: TokenCommand::Validate { r#type, token, .. },
+            }) => {
+                self.selection.r#type = r#type;
+                self.options.token = token;
+            }
+            Some(Command::Token { .. } | Command::Config { .. } | Command::Verify { .. }) | None => {}
+        }
+        self
+    }
 }
@@ -1,9 +1,14 @@
 // src/client.rs
 
+pub mod cache;
+pub mod recorder;
+
 use crate::{config::AppConfig, error::*};
+use cache::{CacheEntry, JsonCache};
+use recorder::TrafficRecorder;
 use anyhow::anyhow;
 use log::{debug, error, trace, warn};
-use reqwest::{header, IntoUrl, Response, StatusCode};
+use reqwest::{header::{self, HeaderMap}, IntoUrl, Response, StatusCode};
 use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
 use reqwest_retry::{
     policies::ExponentialBackoff, DefaultRetryableStrategy, Retryable, RetryableStrategy,
@@ -17,6 +22,8 @@ use tokio::task::block_in_place;
 pub struct RobustClient {
     pub client: ClientWithMiddleware,
     config: Arc<AppConfig>,
+    cache: Option<JsonCache>,
+    recorder: Option<TrafficRecorder>,
 }
 
 impl RobustClient {
@@ -36,17 +43,62 @@ impl RobustClient {
         ))
         .build();
         debug!("RobustClient created with max_retries={}", config.max_retries);
-        Ok(Self { client, config })
+        let cache = config
+            .cache_dir
+            .clone()
+            .map(|dir| JsonCache::new(dir, config.cache_ttl));
+        let recorder = match (&config.replay_dir, &config.record_dir) {
+            (Some(dir), _) => Some(TrafficRecorder::replay(dir.clone())),
+            (None, Some(dir)) => Some(TrafficRecorder::record(dir.clone())),
+            (None, None) => None,
+        };
+        Ok(Self {
+            client,
+            config,
+            cache,
+            recorder,
+        })
+    }
+
+    /// 清理超过最长保留时间的缓存文件 (`max_age_days` 为 0 时不清理)。只在启动时调用一次，预览模式下跳过。
+    pub fn prune_cache(&self) {
+        if let (Some(cache), Some(max_age)) = (&self.cache, self.config.cache_max_age) {
+            cache.prune(max_age);
+        }
     }
 
     pub async fn get<T: IntoUrl>(&self, url: T) -> AppResult<Response> {
-        let url_ref = url.as_str();
-        debug!("HTTP GET: {}", url_ref);
+        self.send_get(url.as_str(), HeaderMap::new()).await
+    }
+
+    /// 以文本形式获取 M3U8 播放列表、密钥服务器响应等；记录/回放模式下同样被记录或回放
+    pub async fn fetch_text(&self, url: &str) -> AppResult<String> {
+        if let Some(recorder) = self.replaying() {
+            return recorder.load(url);
+        }
+        let text = self.get(url).await?.text().await?;
+        self.record(url, &text);
+        Ok(text)
+    }
+
+    fn replaying(&self) -> Option<&TrafficRecorder> {
+        self.recorder.as_ref().filter(|r| r.is_replay())
+    }
+
+    fn record(&self, url: &str, body: &str) {
+        if let Some(recorder) = &self.recorder {
+            recorder.save(url, body);
+        }
+    }
+
+    /// 发送 GET 请求；除成功状态外，携带条件请求头时服务器返回的 304 也视为成功
+    async fn send_get(&self, url: &str, headers: HeaderMap) -> AppResult<Response> {
+        debug!("HTTP GET: {}", url);
 
-        let res = self.client.get(url_ref).send().await?;
+        let res = self.client.get(url).headers(headers).send().await?;
 
         match res.status() {
-            s if s.is_success() => Ok(res),
+            s if s.is_success() || s == StatusCode::NOT_MODIFIED => Ok(res),
             StatusCode::UNAUTHORIZED => { // 401
                 warn!("请求 {} 返回 401: Token 无效或缺失。", res.url());
                 Err(AppError::TokenInvalid)
@@ -67,18 +119,55 @@ impl RobustClient {
         url_template: &str,
         params: &[(&str, &str)],
     ) -> AppResult<T> {
+        // 缓存键保留 {prefix}，各镜像服务器返回的内容相同
+        let mut cache_key = url_template.to_string();
+        for (key, val) in params {
+            cache_key = cache_key.replace(&format!("{{{}}}", key), val);
+        }
+        if let Some(recorder) = self.replaying() {
+            let text = recorder.load(&cache_key)?;
+            return serde_json::from_str::<T>(&text)
+                .map_err(|e| AppError::ApiParseFailed { url: cache_key, source: e });
+        }
+        let cached = self.cache.as_ref().and_then(|c| c.load(&cache_key));
+        if let (Some(cache), Some(entry)) = (&self.cache, &cached)
+            && entry.is_fresh(cache.ttl())
+            && let Ok(data) = serde_json::from_str::<T>(&entry.body)
+        {
+            debug!("API 缓存命中: {}", cache_key);
+            // 缓存命中的响应同样记录，否则回放时会缺少这些请求
+            self.record(&cache_key, &entry.body);
+            return Ok(data);
+        }
+
         let mut last_error: Option<AppError> = None;
         for prefix in &self.config.server_prefixes {
-            let mut url = url_template.replace("{prefix}", prefix);
-            for (key, val) in params {
-                url = url.replace(&format!("{{{}}}", key), val);
-            }
-            match self.get(&url).await {
+            let url = cache_key.replace("{prefix}", prefix);
+            match self.send_get(&url, conditional_headers(cached.as_ref())).await {
                 Ok(res) => {
-                    let text = res.text().await?;
+                    let (text, entry) = match (res.status(), &cached) {
+                        (StatusCode::NOT_MODIFIED, Some(entry)) => {
+                            debug!("服务器确认缓存仍然有效 (304): {}", url);
+                            (entry.body.clone(), entry.clone().revalidated())
+                        }
+                        _ => {
+                            let etag = header_value(res.headers(), header::ETAG);
+                            let last_modified = header_value(res.headers(), header::LAST_MODIFIED);
+                            let text = res.text().await?;
+                            let entry = CacheEntry::new(&cache_key, etag, last_modified, text.clone());
+                            (text, entry)
+                        }
+                    };
                     trace!("原始JSON响应来自 {}: {}", url, text);
+                    // 解析失败的响应同样记录下来，便于复现问题
+                    self.record(&cache_key, &text);
                     match serde_json::from_str::<T>(&text) {
-                        Ok(data) => return Ok(data),
+                        Ok(data) => {
+                            if let Some(cache) = &self.cache {
+                                cache.store(&entry);
+                            }
+                            return Ok(data);
+                        }
                         Err(e) => {
                             warn!("服务器 '{}' 响应成功但JSON解析失败: {:?}. 尝试...", prefix, e);
                             last_error = Some(AppError::ApiParseFailed { url: url.clone(), source: e });
@@ -100,6 +189,25 @@ impl RobustClient {
     }
 }
 
+/// 根据缓存条目生成条件请求头 (If-None-Match / If-Modified-Since)
+fn conditional_headers(entry: Option<&CacheEntry>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let Some(entry) = entry else {
+        return headers;
+    };
+    if let Some(value) = entry.etag.as_deref().and_then(|v| v.parse().ok()) {
+        headers.insert(header::IF_NONE_MATCH, value);
+    }
+    if let Some(value) = entry.last_modified.as_deref().and_then(|v| v.parse().ok()) {
+        headers.insert(header::IF_MODIFIED_SINCE, value);
+    }
+    headers
+}
+
+fn header_value(headers: &HeaderMap, name: header::HeaderName) -> Option<String> {
+    headers.get(name).and_then(|v| v.to_str().ok()).map(str::to_string)
+}
+
 #[derive(Clone)]
 struct RateLimitingRetryStrategy;
 
@@ -0,0 +1,175 @@
+// src/client/cache.rs
+
+use crate::error::AppResult;
+use log::{debug, warn};
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::Write,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// 磁盘上的 API JSON 缓存，每个条目保存为一个以 URL 的 MD5 命名的文件。
+/// 条目在 TTL 内直接使用；过期后携带 ETag/Last-Modified 向服务器重新验证，返回 304 时继续沿用。
+/// 缓存只是加速手段，读写失败都不会影响请求本身。
+#[derive(Debug, Clone)]
+pub struct JsonCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    /// 缓存键：服务器前缀 (`{prefix}`) 尚未替换的 URL，同一资源在各镜像服务器间共享缓存
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// 写入或最近一次重新验证的时间 (Unix 秒)
+    pub stored_at: u64,
+    pub body: String,
+}
+
+impl CacheEntry {
+    pub fn new(url: &str, etag: Option<String>, last_modified: Option<String>, body: String) -> Self {
+        Self {
+            url: url.to_string(),
+            etag,
+            last_modified,
+            stored_at: unix_now(),
+            body,
+        }
+    }
+
+    pub fn is_fresh(&self, ttl: Duration) -> bool {
+        unix_now().saturating_sub(self.stored_at) < ttl.as_secs()
+    }
+
+    /// 服务器确认内容未变化 (304) 后刷新条目的时间
+    pub fn revalidated(mut self) -> Self {
+        self.stored_at = unix_now();
+        self
+    }
+}
+
+impl JsonCache {
+    pub fn new(dir: PathBuf, ttl: Duration) -> Self {
+        Self { dir, ttl }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub fn load(&self, url: &str) -> Option<CacheEntry> {
+        let path = self.entry_path(url);
+        let content = fs::read_to_string(&path).ok()?;
+        match serde_json::from_str::<CacheEntry>(&content) {
+            // 哈希冲突时视为未命中
+            Ok(entry) if entry.url == url => Some(entry),
+            Ok(_) => None,
+            Err(e) => {
+                debug!("缓存条目 {:?} 已损坏，忽略: {}", path, e);
+                None
+            }
+        }
+    }
+
+    pub fn store(&self, entry: &CacheEntry) {
+        if let Err(e) = self.write_entry(entry) {
+            warn!("写入 API 缓存失败 ({}): {}", entry.url, e);
+        }
+    }
+
+    fn write_entry(&self, entry: &CacheEntry) -> AppResult<()> {
+        fs::create_dir_all(&self.dir)?;
+        // 先写入临时文件再替换，避免并发读取到写了一半的条目
+        let mut temp = tempfile::NamedTempFile::new_in(&self.dir)?;
+        temp.write_all(serde_json::to_string(entry)?.as_bytes())?;
+        temp.persist(self.entry_path(&entry.url))?;
+        Ok(())
+    }
+
+    /// 删除超过 `max_age` 未写入 (包括重新验证) 的条目，返回删除的文件数。
+    /// 每次写入都会替换整个文件，因此文件的修改时间即为条目最近一次写入的时间。
+    pub fn prune(&self, max_age: Duration) -> usize {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return 0;
+        };
+        let now = SystemTime::now();
+        let mut removed = 0;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let expired = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .is_ok_and(|modified| now.duration_since(modified).is_ok_and(|age| age > max_age));
+            if expired && path.is_file() {
+                match fs::remove_file(&path) {
+                    Ok(()) => removed += 1,
+                    Err(e) => debug!("删除过期缓存条目 {:?} 失败: {}", path, e),
+                }
+            }
+        }
+        if removed > 0 {
+            debug!("已清理 {} 个过期的 API 缓存条目", removed);
+        }
+        removed
+    }
+
+    fn entry_path(&self, url: &str) -> PathBuf {
+        self.dir.join(format!("{:x}.json", Md5::digest(url.as_bytes())))
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_roundtrip_and_freshness() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = JsonCache::new(dir.path().join("cache"), Duration::from_secs(60));
+        let url = "https://{prefix}.example.com/tree.json";
+        assert!(cache.load(url).is_none());
+
+        let entry = CacheEntry::new(url, Some("\"v1\"".to_string()), None, "{}".to_string());
+        cache.store(&entry);
+        let loaded = cache.load(url).expect("条目应当被写入磁盘");
+        assert_eq!(loaded.etag.as_deref(), Some("\"v1\""));
+        assert!(loaded.is_fresh(cache.ttl()));
+        assert!(cache.load("https://{prefix}.example.com/other.json").is_none());
+
+        let stale = CacheEntry { stored_at: 0, ..loaded };
+        assert!(!stale.is_fresh(cache.ttl()));
+        assert!(stale.revalidated().is_fresh(cache.ttl()));
+    }
+
+    #[test]
+    fn test_prune_removes_only_old_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = JsonCache::new(dir.path().to_path_buf(), Duration::from_secs(60));
+        let old_url = "https://{prefix}.example.com/old.json";
+        let new_url = "https://{prefix}.example.com/new.json";
+        cache.store(&CacheEntry::new(old_url, None, None, "{}".to_string()));
+        cache.store(&CacheEntry::new(new_url, None, None, "{}".to_string()));
+        let long_ago = SystemTime::now() - Duration::from_secs(90 * 24 * 3600);
+        fs::File::options()
+            .write(true)
+            .open(cache.entry_path(old_url))
+            .unwrap()
+            .set_modified(long_ago)
+            .unwrap();
+
+        assert_eq!(cache.prune(Duration::from_secs(30 * 24 * 3600)), 1);
+        assert!(cache.load(old_url).is_none());
+        assert!(cache.load(new_url).is_some());
+    }
+}
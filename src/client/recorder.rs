@@ -0,0 +1,163 @@
+// src/client/recorder.rs
+
+use crate::{error::*, utils};
+use anyhow::anyhow;
+use log::{debug, warn};
+use md5::{Digest, Md5};
+use regex::Regex;
+use serde::Serialize;
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::LazyLock,
+};
+
+const INDEX_FILE_NAME: &str = "index.jsonl";
+const REDACTED: &str = "REDACTED";
+
+static TOKEN_PARAM_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)(access_?token=)[^&"'\s#]+"#).unwrap());
+
+/// 记录或回放接口流量 (`--record` / `--replay`)。
+/// 每个请求的响应原样保存为一个文件，文件名由去掉 Token 后的 URL 的哈希和 URL 末段组成，
+/// 可以直接用作测试夹具；`index.jsonl` 记录 URL 与文件的对应关系。
+#[derive(Debug, Clone)]
+pub struct TrafficRecorder {
+    dir: PathBuf,
+    replay: bool,
+}
+
+#[derive(Serialize)]
+struct IndexEntry<'a> {
+    url: &'a str,
+    file: &'a str,
+}
+
+impl TrafficRecorder {
+    pub fn record(dir: PathBuf) -> Self {
+        Self { dir, replay: false }
+    }
+
+    pub fn replay(dir: PathBuf) -> Self {
+        Self { dir, replay: true }
+    }
+
+    pub fn is_replay(&self) -> bool {
+        self.replay
+    }
+
+    /// 保存一次响应；记录失败只打印警告，不影响请求本身
+    pub fn save(&self, url: &str, body: &str) {
+        if self.replay {
+            return;
+        }
+        if let Err(e) = self.write_exchange(url, body) {
+            warn!("记录接口响应失败 ({}): {}", redact_url(url), e);
+        }
+    }
+
+    /// 读取之前记录的响应，没有记录时返回错误而不是访问网络
+    pub fn load(&self, url: &str) -> AppResult<String> {
+        let url = redact_url(url);
+        let path = self.dir.join(entry_file_name(&url));
+        debug!("回放接口响应: {} <- {:?}", url, path);
+        fs::read_to_string(&path).map_err(|e| {
+            AppError::Other(anyhow!("回放目录中没有请求 {} 的记录 ({:?}): {}", url, path, e))
+        })
+    }
+
+    fn write_exchange(&self, url: &str, body: &str) -> AppResult<()> {
+        let url = redact_url(url);
+        let file = entry_file_name(&url);
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(&file);
+        // 同一 URL 多次请求 (或缓存命中) 时只覆盖响应文件，索引中每个 URL 只出现一次
+        let indexed = path.exists();
+        fs::write(&path, redact_body(body))?;
+        if indexed {
+            debug!("已更新接口响应记录: {} -> {}", url, file);
+            return Ok(());
+        }
+        let mut line = serde_json::to_string(&IndexEntry { url: &url, file: &file })?;
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(INDEX_FILE_NAME))?
+            .write_all(line.as_bytes())?;
+        debug!("已记录接口响应: {} -> {}", url, file);
+        Ok(())
+    }
+}
+
+/// 去掉 URL 中的 Token 参数，使记录与所用 Token 无关
+pub fn redact_url(url: &str) -> String {
+    let Some((base, query)) = url.split_once('?') else {
+        return url.to_string();
+    };
+    let query = query
+        .split('&')
+        .filter(|pair| {
+            let key = pair.split('=').next().unwrap_or_default();
+            !key.eq_ignore_ascii_case("accessToken") && !key.eq_ignore_ascii_case("access_token")
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+    if query.is_empty() {
+        base.to_string()
+    } else {
+        format!("{}?{}", base, query)
+    }
+}
+
+/// 隐去响应内容 (如主播放列表中的码流地址) 里携带的 Token
+fn redact_body(body: &str) -> String {
+    TOKEN_PARAM_RE
+        .replace_all(body, format!("${{1}}{}", REDACTED))
+        .into_owned()
+}
+
+fn entry_file_name(url: &str) -> String {
+    let hash = format!("{:x}", Md5::digest(url.as_bytes()));
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let name = path.rsplit('/').find(|s| !s.is_empty()).unwrap_or("response");
+    format!("{}-{}", &hash[..12], utils::sanitize_filename(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redacts_token_from_urls_and_bodies() {
+        assert_eq!(
+            redact_url("https://x.com/a/index.m3u8?accessToken=secret&v=1"),
+            "https://x.com/a/index.m3u8?v=1"
+        );
+        assert_eq!(redact_url("https://x.com/a/index.m3u8?AccessToken=secret"), "https://x.com/a/index.m3u8");
+        assert_eq!(redact_url("https://{prefix}.x.com/tree.json"), "https://{prefix}.x.com/tree.json");
+        assert_eq!(
+            redact_body("#EXT-X-STREAM-INF:BANDWIDTH=1\nhd/index.m3u8?accessToken=secret\n"),
+            "#EXT-X-STREAM-INF:BANDWIDTH=1\nhd/index.m3u8?accessToken=REDACTED\n"
+        );
+    }
+
+    #[test]
+    fn test_record_then_replay_ignores_token() {
+        let dir = tempfile::tempdir().unwrap();
+        let recorder = TrafficRecorder::record(dir.path().to_path_buf());
+        recorder.save("https://x.com/v/index.m3u8?accessToken=secret", "#EXTM3U\n");
+        recorder.save("https://x.com/v/index.m3u8?accessToken=secret", "#EXTM3U\n");
+
+        let file = entry_file_name("https://x.com/v/index.m3u8");
+        assert!(file.ends_with("-index.m3u8"), "{}", file);
+        let index = fs::read_to_string(dir.path().join(INDEX_FILE_NAME)).unwrap();
+        assert!(!index.contains("secret"));
+        assert_eq!(index.lines().count(), 1, "重复的请求不应在索引中重复出现");
+
+        let replayer = TrafficRecorder::replay(dir.path().to_path_buf());
+        assert_eq!(replayer.load("https://x.com/v/index.m3u8?accessToken=other").unwrap(), "#EXTM3U\n");
+        assert!(replayer.load("https://x.com/v/missing.m3u8").is_err());
+    }
+}
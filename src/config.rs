@@ -1,11 +1,21 @@
 // src/config.rs
 
+pub mod secret;
 pub mod token;
 
-use self::token::load_or_create_external_config;
-use crate::{cli::Cli, constants, error::AppResult};
+use self::{secret::TokenStorage, token::load_or_create_external_config};
+use crate::{
+    cli::Cli,
+    constants,
+    error::{AppError, AppResult},
+    utils,
+};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    time::Duration,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct NetworkConfig {
@@ -15,10 +25,38 @@ pub struct NetworkConfig {
     pub max_retries: Option<u32>,
 }
 
+impl NetworkConfig {
+    /// 以 `self` 中已设置的字段覆盖 `base` 中的对应字段。
+    pub fn merged_over(&self, base: &NetworkConfig) -> NetworkConfig {
+        NetworkConfig {
+            server_prefixes: self.server_prefixes.clone().or_else(|| base.server_prefixes.clone()),
+            connect_timeout_secs: self.connect_timeout_secs.or(base.connect_timeout_secs),
+            timeout_secs: self.timeout_secs.or(base.timeout_secs),
+            max_retries: self.max_retries.or(base.max_retries),
+        }
+    }
+}
+
+/// 一个具名的账号配置 (例如教师账号、学生账号)，通过 `--profile` 或环境变量选择。
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ProfileConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accesstoken: Option<String>,
+    /// 只需填写要覆盖的字段，其余沿用顶层的 `network` 配置
+    #[serde(default)]
+    pub network: NetworkConfig,
+    /// 该账号默认的文件保存目录 (命令行 `-o` 优先)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<PathBuf>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExternalConfig {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub accesstoken: Option<String>,
+    /// Token 的存储方式，决定 `accesstoken` 字段是否实际被使用
+    #[serde(default)]
+    pub token_storage: TokenStorage,
     #[serde(default)]
     pub network: NetworkConfig,
     pub url_templates: HashMap<String, String>,
@@ -26,12 +64,35 @@ pub struct ExternalConfig {
     pub api_endpoints: HashMap<String, ApiEndpointConfig>,
     #[serde(default)]
     pub directory_structure: DirectoryStructureConfig,
+    #[serde(default)]
+    pub filename_templates: FilenameTemplateConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, ProfileConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DirectoryStructureConfig {
     pub textbook_path_order: Vec<String>,
     pub textbook_path_defaults: HashMap<String, String>,
+    /// 精品课程的目录模板，以 `/` 分隔各级目录
+    #[serde(default = "default_course_path_template")]
+    pub course_path_template: String,
+    /// 同步课堂每个课时的目录模板
+    #[serde(default = "default_sync_classroom_path_template")]
+    pub sync_classroom_path_template: String,
+    /// `{chapter_path}` 最多展开的章节层级数 (从上往下保留)，不设置时不限制
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_chapter_depth: Option<usize>,
+}
+
+fn default_course_path_template() -> String {
+    "{textbook_path}/{chapter_path}".to_string()
+}
+
+fn default_sync_classroom_path_template() -> String {
+    "{textbook_path}/{chapter_path}/{lesson}".to_string()
 }
 
 // 为 DirectoryStructureConfig 实现 Default
@@ -50,6 +111,102 @@ impl Default for DirectoryStructureConfig {
                 (VERSION.into(), "未知版本".into()),
                 (VOLUME.into(), "未知册次".into()),
             ]),
+            course_path_template: default_course_path_template(),
+            sync_classroom_path_template: default_sync_classroom_path_template(),
+            max_chapter_depth: None,
+        }
+    }
+}
+
+/// 各类文件的文件名模板。可用占位符：`{title}` (默认命名中的标题部分，
+/// 精品课为课程标题，同步课堂为 "课程标题[课时标题]"，教材为 PDF 原文件名，音频为音频标题)、
+/// `{course}`、`{lesson}`、`{alias}`、`{teacher}`、`{quality}`、`{index}`、`{date}`、`{ext}`。
+/// 字幕模板只用于没有对应视频的字幕；随视频下载的字幕总是与所选视频同名，以便播放器自动加载。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FilenameTemplateConfig {
+    pub video: String,
+    pub document: String,
+    pub subtitle: String,
+    pub cover: String,
+    pub audio: String,
+    pub textbook: String,
+}
+
+impl FilenameTemplateConfig {
+    /// 文件名模板支持的占位符
+    pub const PLACEHOLDERS: [&'static str; 9] = [
+        "title", "course", "lesson", "alias", "teacher", "quality", "index", "date", "ext",
+    ];
+
+    /// 检查所有模板只使用了已知的占位符。未知的占位符不会被替换，
+    /// 拼写错误 (如 `{titel}`) 会让每个文件名都带上原样的花括号，因此在加载配置时直接报错。
+    pub fn validate(&self) -> AppResult<()> {
+        let templates = [
+            ("video", &self.video),
+            ("document", &self.document),
+            ("subtitle", &self.subtitle),
+            ("cover", &self.cover),
+            ("audio", &self.audio),
+            ("textbook", &self.textbook),
+        ];
+        for (key, template) in templates {
+            if let Some(unknown) = utils::template_placeholders(template)
+                .into_iter()
+                .find(|name| !Self::PLACEHOLDERS.contains(name))
+            {
+                let available: Vec<String> =
+                    Self::PLACEHOLDERS.iter().map(|p| format!("{{{}}}", p)).collect();
+                return Err(AppError::UserInputError(format!(
+                    "配置文件中的文件名模板 filename_templates.{} (\"{}\") 包含未知的占位符 {{{}}}。可用的占位符: {}",
+                    key,
+                    template,
+                    unknown,
+                    available.join(", ")
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for FilenameTemplateConfig {
+    fn default() -> Self {
+        Self {
+            video: "{title} - {alias} [{quality}] - [{teacher}].{ext}".into(),
+            document: "{title} - {alias} - [{teacher}].{ext}".into(),
+            subtitle: "{title} - {alias} - [{teacher}].{ext}".into(),
+            cover: "{title} - {alias} - [{teacher}].{ext}".into(),
+            audio: "[{index}] {title}.{ext}".into(),
+            textbook: "{title}.{ext}".into(),
+        }
+    }
+}
+
+/// API JSON 缓存的设置。缓存保存在 `~/.sed-dl/cache` 中，可用 `--no-cache` 临时禁用。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    /// 缓存条目在多少秒内直接使用而不向服务器确认；为 0 时每次都重新验证
+    pub ttl_secs: u64,
+    /// 超过多少天未写入或重新验证的条目在启动时被删除，避免缓存目录无限增长；为 0 时从不清理
+    pub max_age_days: u64,
+}
+
+impl CacheConfig {
+    /// 缓存条目的最长保留时间；`max_age_days` 为 0 时返回 `None` (从不清理)
+    pub fn max_age(&self) -> Option<Duration> {
+        (self.max_age_days > 0).then(|| Duration::from_secs(self.max_age_days.saturating_mul(24 * 3600)))
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ttl_secs: 3600,
+            max_age_days: 30,
         }
     }
 }
@@ -62,6 +219,8 @@ impl ExternalConfig {
             ("COURSE_QUALITY".into(), "https://{prefix}.ykt.cbern.com.cn/zxx/ndrv2/resources/{resource_id}.json".into()),
             ("COURSE_SYNC".into(), "https://{prefix}.ykt.cbern.com.cn/zxx/ndrv2/national_lesson/resources/details/{resource_id}.json".into()),
             ("CHAPTER_TREE".into(), "https://{prefix}.ykt.cbern.com.cn/zxx/ndrv2/national_lesson/trees/{tree_id}.json".into()),
+            ("TEXTBOOK_LESSONS".into(), "https://{prefix}.ykt.cbern.com.cn/zxx/ndrs/national_lesson/teachingmaterials/{resource_id}/resources/parts.json".into()),
+            ("TEXTBOOK_CATALOG".into(), "https://{prefix}.ykt.cbern.com.cn/zxx/ndrs/resources/tch_material/version/data_version.json".into()),
         ]);
 
         // 使用常量
@@ -104,12 +263,29 @@ impl ExternalConfig {
 
         Self {
             accesstoken: None,
+            token_storage: TokenStorage::default(),
             network: network_config,
             url_templates,
             api_endpoints,
             directory_structure: DirectoryStructureConfig::default(),
+            filename_templates: FilenameTemplateConfig::default(),
+            cache: CacheConfig::default(),
+            profiles: BTreeMap::new(),
         }
     }
+
+    /// 查找指定名称的 profile，不存在时返回用户输入错误。
+    pub fn profile(&self, name: &str) -> AppResult<&ProfileConfig> {
+        self.profiles.get(name).ok_or_else(|| {
+            let available = if self.profiles.is_empty() {
+                "配置文件中尚未定义任何 profile".to_string()
+            } else {
+                let names: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+                format!("可用的 profile: {}", names.join(", "))
+            };
+            AppError::UserInputError(format!("未找到名为 '{}' 的 profile ({})。", name, available))
+        })
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -146,31 +322,72 @@ pub struct AppConfig {
     pub api_endpoints: HashMap<String, ApiEndpointConfig>,
     pub url_templates: HashMap<String, String>,
     pub dir_config: DirectoryStructureConfig,
+    pub filename_templates: FilenameTemplateConfig,
+    /// API JSON 缓存目录，禁用缓存时为 `None`
+    pub cache_dir: Option<PathBuf>,
+    pub cache_ttl: Duration,
+    /// 缓存条目的最长保留时间，`None` 表示从不清理
+    pub cache_max_age: Option<Duration>,
+    /// `--record`：记录接口流量的目录
+    pub record_dir: Option<PathBuf>,
+    /// `--replay`：回放接口流量的目录，设置后元数据不再从网络获取
+    pub replay_dir: Option<PathBuf>,
+    /// 当前生效的 profile 名称 (未选择时为 `None`)
+    pub profile: Option<String>,
+    pub output_dir: PathBuf,
 }
 
 impl AppConfig {
     pub fn new(args: &Cli) -> AppResult<Self> {
         let external_config = load_or_create_external_config()?;
-        
+        external_config.filename_templates.validate()?;
+        let profile_name = args.active_profile();
+        let profile = profile_name
+            .as_deref()
+            .map(|name| external_config.profile(name))
+            .transpose()?;
+
+        // 选中的 profile 只覆盖其显式设置的网络参数
+        let network = match profile {
+            Some(p) => p.network.merged_over(&external_config.network),
+            None => external_config.network.clone(),
+        };
+        let output_dir = args
+            .options
+            .output
+            .clone()
+            .or_else(|| profile.and_then(|p| p.output.clone()))
+            .unwrap_or_else(|| PathBuf::from(constants::DEFAULT_SAVE_DIR));
+
         // 现在这里的逻辑是正确的，因为不再需要转换
         let api_endpoints = external_config.api_endpoints;
+        // 旧版配置文件中没有后续新增的 URL 模板，用内置默认值补齐
+        let mut url_templates = ExternalConfig::default_app_config().url_templates;
+        url_templates.extend(external_config.url_templates);
+        let cache_dir = (external_config.cache.enabled && !args.options.no_cache)
+            .then(dirs::home_dir)
+            .flatten()
+            .map(|home| home.join(constants::CONFIG_DIR_NAME).join(constants::CACHE_DIR_NAME));
 
         Ok(Self {
-            max_workers: args.workers.unwrap_or(5),
-            default_audio_format: args.audio_format.clone(),
-            server_prefixes: external_config
-                .network
-                .server_prefixes
-                .unwrap_or_default(),
+            max_workers: args.options.workers.unwrap_or(5),
+            default_audio_format: args.selection.audio_format.clone(),
+            server_prefixes: network.server_prefixes.unwrap_or_default(),
             user_agent: constants::USER_AGENT.into(),
-            connect_timeout: Duration::from_secs(
-                external_config.network.connect_timeout_secs.unwrap_or(10),
-            ),
-            timeout: Duration::from_secs(external_config.network.timeout_secs.unwrap_or(60)),
-            max_retries: external_config.network.max_retries.unwrap_or(3),
+            connect_timeout: Duration::from_secs(network.connect_timeout_secs.unwrap_or(10)),
+            timeout: Duration::from_secs(network.timeout_secs.unwrap_or(60)),
+            max_retries: network.max_retries.unwrap_or(3),
             api_endpoints, // 直接使用
-            url_templates: external_config.url_templates,
+            url_templates,
             dir_config: external_config.directory_structure,
+            filename_templates: external_config.filename_templates,
+            cache_dir,
+            cache_ttl: Duration::from_secs(external_config.cache.ttl_secs),
+            cache_max_age: external_config.cache.max_age(),
+            record_dir: args.options.record.clone(),
+            replay_dir: args.options.replay.clone(),
+            profile: profile_name,
+            output_dir,
         })
     }
 }
@@ -189,6 +406,73 @@ impl Default for AppConfig {
             api_endpoints: HashMap::new(),
             url_templates: HashMap::new(),
             dir_config: DirectoryStructureConfig::default(),
+            filename_templates: FilenameTemplateConfig::default(),
+            cache_dir: None,
+            cache_ttl: Duration::from_secs(3600),
+            cache_max_age: Some(Duration::from_secs(30 * 24 * 3600)),
+            record_dir: None,
+            replay_dir: None,
+            profile: None,
+            output_dir: PathBuf::from(constants::DEFAULT_SAVE_DIR),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_network_overrides_only_set_fields() {
+        let base = ExternalConfig::default_app_config().network;
+        let overrides = NetworkConfig {
+            timeout_secs: Some(300),
+            ..Default::default()
+        };
+        let merged = overrides.merged_over(&base);
+        assert_eq!(merged.timeout_secs, Some(300));
+        assert_eq!(merged.max_retries, base.max_retries);
+        assert_eq!(merged.server_prefixes, base.server_prefixes);
+    }
+
+    #[test]
+    fn test_profiles_roundtrip_and_lookup() {
+        let json = r#"{
+            "url_templates": {},
+            "api_endpoints": {},
+            "profiles": {
+                "teacher": { "accesstoken": "t-token", "output": "teacher_downloads" },
+                "student": { "network": { "max_retries": 5 } }
+            }
+        }"#;
+        let config: ExternalConfig = serde_json::from_str(json).unwrap();
+        let teacher = config.profile("teacher").unwrap();
+        assert_eq!(teacher.accesstoken.as_deref(), Some("t-token"));
+        assert_eq!(teacher.output, Some(PathBuf::from("teacher_downloads")));
+        assert_eq!(config.profile("student").unwrap().network.max_retries, Some(5));
+
+        let err = config.profile("admin").unwrap_err();
+        assert!(err.to_string().contains("student, teacher"));
+    }
+
+    #[test]
+    fn test_cache_max_age_days() {
+        let with_days = |max_age_days| CacheConfig { max_age_days, ..Default::default() };
+        assert_eq!(with_days(0).max_age(), None);
+        assert_eq!(with_days(2).max_age(), Some(Duration::from_secs(2 * 24 * 3600)));
+        assert_eq!(with_days(u64::MAX).max_age(), Some(Duration::from_secs(u64::MAX)));
+    }
+
+    #[test]
+    fn test_filename_templates_reject_unknown_placeholders() {
+        assert!(FilenameTemplateConfig::default().validate().is_ok());
+
+        let templates = FilenameTemplateConfig {
+            document: "{titel} - {alias}.{ext}".to_string(),
+            ..Default::default()
+        };
+        let err = templates.validate().unwrap_err().to_string();
+        assert!(err.contains("filename_templates.document"));
+        assert!(err.contains("{titel}"));
+    }
+}
@@ -0,0 +1,391 @@
+// src/config/secret.rs
+
+// Access Token 的存储后端：明文配置文件、口令加密的 Token 文件，或系统密钥环。
+
+use crate::{
+    config::ExternalConfig,
+    constants,
+    error::{AppError, AppResult},
+    ui,
+};
+use aes_gcm::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+use anyhow::Context;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use clap::ValueEnum;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, IsTerminal},
+    path::PathBuf,
+    sync::{LazyLock, Mutex},
+};
+
+/// Token 的存储方式，在配置文件的 `token_storage` 字段中选择
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum TokenStorage {
+    /// 明文保存在 config.json 中 (默认，兼容旧版)
+    #[default]
+    Plaintext,
+    /// 使用口令加密后保存在单独的 Token 文件中，适合无图形界面的服务器
+    EncryptedFile,
+    /// 保存在系统密钥环中 (Secret Service / macOS 钥匙串 / Windows 凭据管理器)
+    #[cfg_attr(not(feature = "keyring"), value(hide = true))]
+    Keyring,
+}
+
+impl TokenStorage {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TokenStorage::Plaintext => "配置文件 (明文)",
+            TokenStorage::EncryptedFile => "加密Token文件",
+            TokenStorage::Keyring => "系统密钥环",
+        }
+    }
+
+    /// 检查当前版本是否支持该存储方式 (系统密钥环需要在编译时启用 `keyring` feature)
+    pub fn ensure_available(self) -> AppResult<()> {
+        if self == TokenStorage::Keyring && !cfg!(feature = "keyring") {
+            return Err(keyring_unavailable());
+        }
+        Ok(())
+    }
+}
+
+fn keyring_unavailable() -> AppError {
+    AppError::UserInputError(
+        "此版本编译时未启用系统密钥环支持 (需使用 `--features keyring` 重新编译)，请改用 encrypted-file 或 plaintext。"
+            .to_string(),
+    )
+}
+
+/// 读取指定 profile (或顶层) 的 Token。
+pub(crate) fn load(config: &ExternalConfig, profile: Option<&str>) -> AppResult<Option<String>> {
+    match config.token_storage {
+        TokenStorage::Plaintext => Ok(match profile {
+            Some(name) => config.profiles.get(name).and_then(|p| p.accesstoken.clone()),
+            None => config.accesstoken.clone(),
+        }),
+        TokenStorage::EncryptedFile => {
+            let Some(vault) = read_vault()? else {
+                return Ok(None);
+            };
+            Ok(match profile {
+                Some(name) => vault.profiles.get(name).cloned(),
+                None => vault.default,
+            })
+        }
+        TokenStorage::Keyring => keyring_backend::get(profile),
+    }
+}
+
+/// 写入 (`Some`) 或删除 (`None`) 指定 profile (或顶层) 的 Token。
+/// 明文后端只修改传入的 `config`，由调用方负责写回配置文件。
+pub(crate) fn store(
+    config: &mut ExternalConfig,
+    profile: Option<&str>,
+    token: Option<&str>,
+) -> AppResult<()> {
+    let token = token.map(str::to_string);
+    match config.token_storage {
+        TokenStorage::Plaintext => {
+            match profile {
+                Some(name) => {
+                    config.profiles.entry(name.to_string()).or_default().accesstoken = token
+                }
+                None => config.accesstoken = token,
+            }
+            Ok(())
+        }
+        TokenStorage::EncryptedFile => {
+            let mut vault = read_vault()?.unwrap_or_default();
+            match (profile, token) {
+                (Some(name), Some(token)) => {
+                    vault.profiles.insert(name.to_string(), token);
+                }
+                (Some(name), None) => {
+                    vault.profiles.remove(name);
+                }
+                (None, token) => vault.default = token,
+            }
+            write_vault(&vault)
+        }
+        TokenStorage::Keyring => match token {
+            Some(token) => keyring_backend::set(profile, &token),
+            None => keyring_backend::delete(profile),
+        },
+    }
+}
+
+/// 删除某个后端中保存的全部 Token (迁移完成后清理旧后端时使用)。
+pub(crate) fn purge(config: &mut ExternalConfig) -> AppResult<()> {
+    match config.token_storage {
+        TokenStorage::Plaintext => {
+            config.accesstoken = None;
+            for profile in config.profiles.values_mut() {
+                profile.accesstoken = None;
+            }
+        }
+        TokenStorage::EncryptedFile => {
+            let path = vault_path()?;
+            if path.is_file() {
+                fs::remove_file(&path)
+                    .with_context(|| format!("删除加密Token文件 '{}' 失败", path.display()))?;
+            }
+        }
+        TokenStorage::Keyring => {
+            let names: Vec<String> = config.profiles.keys().cloned().collect();
+            keyring_backend::delete(None)?;
+            for name in &names {
+                keyring_backend::delete(Some(name))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// --- 加密 Token 文件 ---
+
+/// 加密文件中保存的明文内容
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct TokenVault {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, String>,
+}
+
+/// 磁盘上的加密文件格式：PBKDF2-HMAC-SHA256 派生密钥，AES-256-GCM 加密
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct EncryptedVaultFile {
+    pub version: u32,
+    pub kdf_iterations: u32,
+    pub salt: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+/// 同一次运行中只向用户询问一次口令
+static PASSPHRASE_CACHE: LazyLock<Mutex<Option<String>>> = LazyLock::new(|| Mutex::new(None));
+
+fn vault_path() -> AppResult<PathBuf> {
+    let path = super::token::get_config_path()?;
+    Ok(path.with_file_name(constants::TOKEN_VAULT_FILE_NAME))
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> Key<Aes256Gcm> {
+    let mut key = Key::<Aes256Gcm>::default();
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, iterations, &mut key);
+    key
+}
+
+pub(crate) fn encrypt_vault(
+    vault: &TokenVault,
+    passphrase: &str,
+    iterations: u32,
+) -> AppResult<EncryptedVaultFile> {
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    let cipher = Aes256Gcm::new(&derive_key(passphrase, &salt, iterations));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let plaintext = serde_json::to_vec(vault)?;
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext.as_slice())
+        .map_err(|_| AppError::Security("加密 Token 失败".to_string()))?;
+    Ok(EncryptedVaultFile {
+        version: 1,
+        kdf_iterations: iterations,
+        salt: STANDARD.encode(salt),
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(ciphertext),
+    })
+}
+
+pub(crate) fn decrypt_vault(file: &EncryptedVaultFile, passphrase: &str) -> AppResult<TokenVault> {
+    let corrupted = || AppError::Security("加密Token文件格式无效或已损坏".to_string());
+    let salt = STANDARD.decode(&file.salt).map_err(|_| corrupted())?;
+    let nonce: [u8; 12] = STANDARD
+        .decode(&file.nonce)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(corrupted)?;
+    let ciphertext = STANDARD.decode(&file.ciphertext).map_err(|_| corrupted())?;
+    let cipher = Aes256Gcm::new(&derive_key(passphrase, &salt, file.kdf_iterations));
+    let plaintext = cipher
+        .decrypt(&Nonce::from(nonce), ciphertext.as_slice())
+        .map_err(|_| AppError::Security("解密 Token 文件失败 (口令错误或文件已损坏)".to_string()))?;
+    serde_json::from_slice(&plaintext).map_err(|_| corrupted())
+}
+
+fn read_vault() -> AppResult<Option<TokenVault>> {
+    let path = vault_path()?;
+    if !path.is_file() {
+        debug!("加密Token文件 {:?} 不存在", path);
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("读取加密Token文件 '{}' 失败", path.display()))?;
+    let file: EncryptedVaultFile = serde_json::from_str(&content)
+        .with_context(|| format!("解析加密Token文件 '{}' 失败", path.display()))?;
+    let passphrase = get_passphrase(false)?;
+    match decrypt_vault(&file, &passphrase) {
+        Ok(vault) => Ok(Some(vault)),
+        Err(e) => {
+            // 口令错误时清除缓存，避免后续操作继续使用错误的口令
+            PASSPHRASE_CACHE.lock().unwrap().take();
+            Err(e)
+        }
+    }
+}
+
+fn write_vault(vault: &TokenVault) -> AppResult<()> {
+    let path = vault_path()?;
+    let passphrase = get_passphrase(!path.is_file())?;
+    let file = encrypt_vault(vault, &passphrase, constants::TOKEN_VAULT_KDF_ITERATIONS)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(&file)?)
+        .with_context(|| format!("写入加密Token文件 '{}' 失败", path.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+    }
+    info!("已更新加密Token文件: {}", path.display());
+    Ok(())
+}
+
+/// 获取加密文件的口令：优先读取环境变量，其次在终端中提示输入。
+/// `confirm` 为 true 时 (首次创建文件) 要求输入两次。
+fn get_passphrase(confirm: bool) -> AppResult<String> {
+    let mut cache = PASSPHRASE_CACHE.lock().unwrap();
+    if let Some(passphrase) = cache.as_ref() {
+        return Ok(passphrase.clone());
+    }
+    if let Ok(passphrase) = std::env::var(constants::TOKEN_PASSPHRASE_ENV_VAR)
+        && !passphrase.is_empty()
+    {
+        return Ok(cache.insert(passphrase).clone());
+    }
+    if !io::stdin().is_terminal() {
+        return Err(AppError::UserInputError(format!(
+            "Token 使用加密文件保存，需要口令。请设置环境变量 {}。",
+            constants::TOKEN_PASSPHRASE_ENV_VAR
+        )));
+    }
+    let read = |msg: &str| ui::prompt_hidden(msg).map_err(|_| AppError::UserInterrupt);
+    let passphrase = read("请输入Token文件的口令 (输入不可见)")?;
+    if passphrase.is_empty() {
+        return Err(AppError::UserInputError("口令不能为空。".to_string()));
+    }
+    if confirm && read("请再次输入口令以确认")? != passphrase {
+        return Err(AppError::UserInputError("两次输入的口令不一致。".to_string()));
+    }
+    Ok(cache.insert(passphrase).clone())
+}
+
+// --- 系统密钥环 ---
+
+#[cfg(feature = "keyring")]
+mod keyring_backend {
+    use super::*;
+    use anyhow::anyhow;
+
+    fn entry(profile: Option<&str>) -> AppResult<keyring::Entry> {
+        let user = profile.unwrap_or(constants::KEYRING_DEFAULT_USER);
+        keyring::Entry::new(constants::KEYRING_SERVICE, user)
+            .map_err(|e| AppError::Other(anyhow!("无法访问系统密钥环: {}", e)))
+    }
+
+    pub(super) fn get(profile: Option<&str>) -> AppResult<Option<String>> {
+        match entry(profile)?.get_password() {
+            Ok(token) => Ok(Some(token)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(AppError::Other(anyhow!("读取系统密钥环失败: {}", e))),
+        }
+    }
+
+    pub(super) fn set(profile: Option<&str>, token: &str) -> AppResult<()> {
+        entry(profile)?
+            .set_password(token)
+            .map_err(|e| AppError::Other(anyhow!("写入系统密钥环失败: {}", e)))
+    }
+
+    pub(super) fn delete(profile: Option<&str>) -> AppResult<()> {
+        match entry(profile)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(AppError::Other(anyhow!("删除系统密钥环中的 Token 失败: {}", e))),
+        }
+    }
+}
+
+#[cfg(not(feature = "keyring"))]
+mod keyring_backend {
+    use super::*;
+
+    fn unsupported() -> AppError {
+        keyring_unavailable()
+    }
+
+    pub(super) fn get(_profile: Option<&str>) -> AppResult<Option<String>> {
+        Err(unsupported())
+    }
+
+    pub(super) fn set(_profile: Option<&str>, _token: &str) -> AppResult<()> {
+        Err(unsupported())
+    }
+
+    pub(super) fn delete(_profile: Option<&str>) -> AppResult<()> {
+        Err(unsupported())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vault_encrypt_decrypt_roundtrip() {
+        let vault = TokenVault {
+            default: Some("default-token".to_string()),
+            profiles: BTreeMap::from([("teacher".to_string(), "teacher-token".to_string())]),
+        };
+        // 测试中使用较少的迭代次数以加快速度
+        let file = encrypt_vault(&vault, "correct horse", 1_000).unwrap();
+        assert!(!file.ciphertext.contains("teacher-token"));
+
+        let decrypted = decrypt_vault(&file, "correct horse").unwrap();
+        assert_eq!(decrypted, vault);
+
+        assert!(matches!(
+            decrypt_vault(&file, "wrong passphrase"),
+            Err(AppError::Security(_))
+        ));
+    }
+
+    #[test]
+    fn test_plaintext_backend_reads_and_writes_config() {
+        let mut config = ExternalConfig::default_app_config();
+        store(&mut config, Some("teacher"), Some("t-token")).unwrap();
+        store(&mut config, None, Some("d-token")).unwrap();
+        assert_eq!(load(&config, Some("teacher")).unwrap().as_deref(), Some("t-token"));
+        assert_eq!(load(&config, None).unwrap().as_deref(), Some("d-token"));
+
+        purge(&mut config).unwrap();
+        assert_eq!(load(&config, Some("teacher")).unwrap(), None);
+        assert_eq!(load(&config, None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_token_storage_config_names() {
+        let storage: TokenStorage = serde_json::from_str(r#""encrypted-file""#).unwrap();
+        assert_eq!(storage, TokenStorage::EncryptedFile);
+    }
+}
@@ -1,16 +1,21 @@
 // src/config/token.rs
 
 use crate::{
-    config::ExternalConfig, // 只需要从父模块导入结构体定义
+    config::{
+        secret::{self, TokenStorage},
+        ExternalConfig, // 只需要从父模块导入结构体定义
+    },
     constants,
     error::{AppError, AppResult},
-    ui,
+    ui, utils,
 };
 use anyhow::{Context, anyhow};
-use log::{debug, info};
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, Utc};
+use log::{debug, info, warn};
 use std::{fs, path::PathBuf};
 
-pub(super) fn get_config_path() -> AppResult<PathBuf> {
+pub(crate) fn get_config_path() -> AppResult<PathBuf> {
     let path = dirs::home_dir()
         .ok_or_else(|| AppError::Other(anyhow!("无法获取用户主目录")))?
         .join(constants::CONFIG_DIR_NAME)
@@ -34,47 +39,169 @@ pub(crate) fn load_or_create_external_config() -> AppResult<ExternalConfig> {
             fs::create_dir_all(dir)?;
         }
 
-        let json_content = serde_json::to_string_pretty(&config)?;
-        fs::write(&config_path, json_content)?;
-
+        write_external_config(&config)?;
         Ok(config)
     }
 }
 
-pub fn save_token(token: &str) -> AppResult<()> {
+/// 先写入同目录下的临时文件再替换，写入中断时不会留下半个配置文件
+fn write_external_config(config: &ExternalConfig) -> AppResult<PathBuf> {
+    let config_path = get_config_path()?;
+    let json_content = serde_json::to_string_pretty(config)?;
+    utils::write_file_atomic(&config_path, json_content).map_err(|e| {
+        AppError::Other(anyhow!("写入配置文件 '{}' 失败: {}", config_path.display(), e))
+    })?;
+    Ok(config_path)
+}
+
+/// 保存 Token。指定 `profile` 时保存到该 profile 中 (不存在则新建)，否则保存到顶层。
+pub fn save_token(token: &str, profile: Option<&str>) -> AppResult<()> {
     if token.is_empty() {
         return Ok(());
     }
 
-    let config_path = get_config_path()?;
     let mut config = load_or_create_external_config()?; // 现在调用的是本模块的函数
+    if let Some(name) = profile {
+        // 无论 Token 存在哪里，profile 本身都需要登记在配置文件中才能被 --profile 选中
+        config.profiles.entry(name.to_string()).or_default();
+    }
+    secret::store(&mut config, profile, Some(token))?;
+    let config_path = write_external_config(&config)?;
+
+    let target = profile.map_or(String::new(), |name| format!(" (profile: {})", name));
+    let location = match config.token_storage {
+        TokenStorage::Plaintext => config_path.display().to_string(),
+        storage => storage.label().to_string(),
+    };
+    info!("用户已将 Token 保存至{}: {}", target, location);
+    ui::success(&format!("Token已成功保存至{}: {}", target, location));
+
+    Ok(())
+}
 
-    config.accesstoken = Some(token.to_string());
+/// 从配置文件中删除已保存的 Token (指定 `profile` 时只删除该 profile 的 Token)。
+/// 返回配置文件中原本是否存有 Token。
+pub fn clear_token(profile: Option<&str>) -> AppResult<bool> {
+    let mut config = load_or_create_external_config()?;
+    if secret::load(&config, profile)?.is_none() {
+        return Ok(false);
+    }
+    secret::store(&mut config, profile, None)?;
+    write_external_config(&config)?;
+    info!("已从{}中删除 Token", config.token_storage.label());
+    Ok(true)
+}
 
-    let json_content = serde_json::to_string_pretty(&config)?;
-    fs::write(&config_path, json_content)
-        .with_context(|| format!("保存Token到 '{}' 失败", config_path.display()))?;
+/// 将所有已保存的 Token (顶层及各 profile) 迁移到新的存储后端，并清理旧后端中的副本。
+/// 返回迁移的 Token 数量；若当前已在使用目标后端，返回 `None`。
+pub fn migrate_token_storage(target: TokenStorage) -> AppResult<Option<usize>> {
+    target.ensure_available()?;
+    let config = load_or_create_external_config()?;
+    let source = config.token_storage;
+    if source == target {
+        return Ok(None);
+    }
 
-    info!("用户已将 Token 保存至配置文件: {}", config_path.display());
-    ui::success(&format!("Token已成功保存至: {}", config_path.display()));
+    let profile_names: Vec<String> = config.profiles.keys().cloned().collect();
+    let mut tokens = Vec::new();
+    if let Some(token) = secret::load(&config, None)? {
+        tokens.push((None, token));
+    }
+    for name in profile_names {
+        if let Some(token) = secret::load(&config, Some(&name))? {
+            tokens.push((Some(name), token));
+        }
+    }
 
-    Ok(())
+    let (count, config_path) =
+        apply_migration(config, target, &tokens, write_external_config, secret::purge)?;
+    info!(
+        "已将 {} 个 Token 从 {} 迁移到 {}，配置文件: {}",
+        count,
+        source.label(),
+        target.label(),
+        config_path.display()
+    );
+    Ok(Some(count))
 }
 
-pub(crate) fn load_token_from_config() -> Option<String> {
-    load_or_create_external_config()
+type MigratedToken = (Option<String>, String);
+
+/// 迁移的核心步骤：先写入新后端并保存配置文件，确认成功后再清理旧后端。
+/// 配置文件写入失败时旧后端保持不变，配置仍指向旧后端，Token 不会丢失。
+fn apply_migration<W, P>(
+    mut config: ExternalConfig,
+    target: TokenStorage,
+    tokens: &[MigratedToken],
+    write_config: W,
+    purge_source: P,
+) -> AppResult<(usize, PathBuf)>
+where
+    W: FnOnce(&ExternalConfig) -> AppResult<PathBuf>,
+    P: FnOnce(&mut ExternalConfig) -> AppResult<()>,
+{
+    let mut migrated = config.clone();
+    migrated.token_storage = target;
+    for (profile, token) in tokens {
+        secret::store(&mut migrated, profile.as_deref(), Some(token))?;
+    }
+    // 旧后端为明文时，新配置中不应再保留明文 Token
+    if config.token_storage == TokenStorage::Plaintext {
+        migrated.accesstoken = None;
+        for profile in migrated.profiles.values_mut() {
+            profile.accesstoken = None;
+        }
+    }
+    let config_path = write_config(&migrated)?;
+
+    // 配置文件已指向新后端，清理失败只会留下旧副本，不影响使用
+    if let Err(e) = purge_source(&mut config) {
+        warn!("清理{}中的旧 Token 失败: {}", config.token_storage.label(), e);
+        ui::warn(&format!(
+            "Token 已迁移，但清理{}中的旧副本失败: {}",
+            config.token_storage.label(),
+            e
+        ));
+    }
+    Ok((tokens.len(), config_path))
+}
+
+/// 尝试将 Token 当作 JWT 解析，读取其中的 `exp` (过期时间) 字段。
+/// 若 Token 不是 JWT 格式或不含过期时间，返回 `None`。
+pub fn decode_token_expiry(token: &str) -> Option<DateTime<Utc>> {
+    let mut parts = token.split('.');
+    let (Some(_header), Some(payload), Some(_signature), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+    let bytes = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+    let claims: serde_json::Value = serde_json::from_slice(&bytes).ok()?;
+    let exp = claims.get("exp")?.as_i64()?;
+    DateTime::from_timestamp(exp, 0)
+}
+
+pub(crate) fn load_token_from_config(profile: Option<&str>) -> Option<String> {
+    let config = load_or_create_external_config().ok()?;
+    secret::load(&config, profile)
+        .inspect_err(|e| {
+            warn!("从{}读取 Token 失败: {}", config.token_storage.label(), e);
+            ui::warn(&format!("从{}读取 Token 失败: {}", config.token_storage.label(), e));
+        })
         .ok()
-        .and_then(|config| config.accesstoken)
+        .flatten()
 }
 
 // 2. 创建可测试的 `resolve_token_with_loader` 函数
+// 选择了 profile 时，配置文件这一级读取的是该 profile 的 Token，而不会回退到顶层 Token。
 pub(crate) fn resolve_token_with_loader<F>(
     cli_token: Option<&str>,
     env_var: Result<String, std::env::VarError>,
+    profile: Option<&str>,
     config_loader: F,
 ) -> (Option<String>, String)
 where
-    F: Fn() -> Option<String>,
+    F: Fn(Option<&str>) -> Option<String>,
 {
     if let Some(token) = cli_token.filter(|s| !s.is_empty()) {
         debug!("使用来自命令行参数的 Token");
@@ -86,19 +213,28 @@ where
         return (Some(token), "环境变量 (ACCESS_TOKEN)".to_string());
     }
     
-    if let Some(token) = config_loader().filter(|s| !s.is_empty()) {
-        debug!("使用来自本地配置文件的 Token");
-        return (Some(token), "本地Token文件".to_string());
+    if let Some(token) = config_loader(profile).filter(|s| !s.is_empty()) {
+        return match profile {
+            Some(name) => {
+                debug!("使用来自配置文件中 profile '{}' 的 Token", name);
+                (Some(token), format!("配置文件 (profile: {})", name))
+            }
+            None => {
+                debug!("使用来自本地配置文件的 Token");
+                (Some(token), "本地Token文件".to_string())
+            }
+        };
     }
     debug!("未在任何位置找到可用的 Token");
     (None, "未找到".to_string())
 }
 
 // 创建 `resolve_token` 作为对可测试函数的封装，供应用程序使用
-pub fn resolve_token(cli_token: Option<&str>) -> (Option<String>, String) {
+pub fn resolve_token(cli_token: Option<&str>, profile: Option<&str>) -> (Option<String>, String) {
     resolve_token_with_loader(
         cli_token,
         std::env::var("ACCESS_TOKEN"),
+        profile,
         load_token_from_config,
     )
 }
@@ -108,12 +244,16 @@ mod tests {
     use super::*;
 
     // --- 模拟加载器 ---
-    fn no_config_token() -> Option<String> {
+    fn no_config_token(_profile: Option<&str>) -> Option<String> {
         None
     }
 
-    fn some_config_token() -> Option<String> {
-        Some("config_token".to_string())
+    fn some_config_token(profile: Option<&str>) -> Option<String> {
+        match profile {
+            Some("teacher") => Some("teacher_token".to_string()),
+            Some(_) => None,
+            None => Some("config_token".to_string()),
+        }
     }
 
     // --- 测试用例 ---
@@ -121,7 +261,7 @@ mod tests {
     #[test]
     fn test_resolve_token_no_sources() {
         let (token, source) =
-            resolve_token_with_loader(None, Err(std::env::VarError::NotPresent), no_config_token);
+            resolve_token_with_loader(None, Err(std::env::VarError::NotPresent), None, no_config_token);
         assert_eq!(token, None);
         assert_eq!(source, "未找到");
     }
@@ -131,6 +271,7 @@ mod tests {
         let (token, source) = resolve_token_with_loader(
             None,
             Err(std::env::VarError::NotPresent),
+            None,
             some_config_token,
         );
         assert_eq!(token, Some("config_token".to_string()));
@@ -142,6 +283,7 @@ mod tests {
         let (token, source) = resolve_token_with_loader(
             None,
             Ok("env_token".to_string()),
+            None,
             some_config_token, // 即使 config 有，也应该被 env 覆盖
         );
         assert_eq!(token, Some("env_token".to_string()));
@@ -153,18 +295,121 @@ mod tests {
         let (token, source) = resolve_token_with_loader(
             Some("cli_token"),
             Ok("env_token".to_string()), // 即使 env 和 config 有，也应该被 cli 覆盖
+            None,
             some_config_token,
         );
         assert_eq!(token, Some("cli_token".to_string()));
         assert_eq!(source, "命令行参数");
     }
 
+    #[test]
+    fn test_resolve_token_from_profile() {
+        let (token, source) = resolve_token_with_loader(
+            None,
+            Err(std::env::VarError::NotPresent),
+            Some("teacher"),
+            some_config_token,
+        );
+        assert_eq!(token, Some("teacher_token".to_string()));
+        assert_eq!(source, "配置文件 (profile: teacher)");
+
+        // profile 中没有 Token 时，不应回退到顶层 Token
+        let (token, _) = resolve_token_with_loader(
+            None,
+            Err(std::env::VarError::NotPresent),
+            Some("student"),
+            some_config_token,
+        );
+        assert_eq!(token, None);
+
+        // 环境变量仍然优先于 profile
+        let (token, _) = resolve_token_with_loader(
+            None,
+            Ok("env_token".to_string()),
+            Some("teacher"),
+            some_config_token,
+        );
+        assert_eq!(token, Some("env_token".to_string()));
+    }
+
+    fn sample_tokens() -> Vec<MigratedToken> {
+        vec![
+            (None, "d-token".to_string()),
+            (Some("teacher".to_string()), "t-token".to_string()),
+        ]
+    }
+
+    #[test]
+    fn test_migration_keeps_old_backend_when_config_write_fails() {
+        let mut config = ExternalConfig::default_app_config();
+        config.token_storage = TokenStorage::EncryptedFile;
+        config.profiles.entry("teacher".to_string()).or_default();
+
+        let mut purged = false;
+        let result = apply_migration(
+            config,
+            TokenStorage::Plaintext,
+            &sample_tokens(),
+            |_| Err(AppError::Other(anyhow!("磁盘已满"))),
+            |_| {
+                purged = true;
+                Ok(())
+            },
+        );
+        assert!(result.is_err());
+        assert!(!purged, "配置文件写入失败时不应清理旧后端");
+    }
+
+    #[test]
+    fn test_migration_writes_config_before_purging_old_backend() {
+        let mut config = ExternalConfig::default_app_config();
+        config.token_storage = TokenStorage::EncryptedFile;
+        config.profiles.entry("teacher".to_string()).or_default();
+
+        let written = std::cell::RefCell::new(None);
+        let (count, _) = apply_migration(
+            config,
+            TokenStorage::Plaintext,
+            &sample_tokens(),
+            |migrated| {
+                *written.borrow_mut() = Some(migrated.clone());
+                Ok(PathBuf::from("config.json"))
+            },
+            |old| {
+                assert!(written.borrow().is_some(), "应先写入配置文件再清理旧后端");
+                assert_eq!(old.token_storage, TokenStorage::EncryptedFile);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(count, 2);
+
+        let written = written.into_inner().unwrap();
+        assert_eq!(written.token_storage, TokenStorage::Plaintext);
+        assert_eq!(written.accesstoken.as_deref(), Some("d-token"));
+        assert_eq!(written.profiles["teacher"].accesstoken.as_deref(), Some("t-token"));
+    }
+
+    #[test]
+    fn test_decode_token_expiry() {
+        let payload = URL_SAFE_NO_PAD.encode(r#"{"sub":"user","exp":1767225600}"#);
+        let token = format!("eyJhbGciOiJIUzI1NiJ9.{}.signature", payload);
+        let expiry = decode_token_expiry(&token).unwrap();
+        assert_eq!(expiry.to_rfc3339(), "2026-01-01T00:00:00+00:00");
+
+        // 非 JWT 格式或缺少 exp 字段
+        assert_eq!(decode_token_expiry("plain-opaque-token"), None);
+        let no_exp = format!("a.{}.c", URL_SAFE_NO_PAD.encode(r#"{"sub":"user"}"#));
+        assert_eq!(decode_token_expiry(&no_exp), None);
+    }
+
     #[test]
     fn test_resolve_token_empty_strings_are_ignored() {
         // 测试空的 cli token 不会覆盖有效的 env token
         let (token, source) = resolve_token_with_loader(
             Some(""),
             Ok("env_token".to_string()),
+            None,
             some_config_token,
         );
         assert_eq!(token, Some("env_token".to_string()));
@@ -172,7 +417,7 @@ mod tests {
 
         // 测试空的 env token 不会覆盖有效的 config token
         let (token, source) =
-            resolve_token_with_loader(None, Ok("".to_string()), some_config_token);
+            resolve_token_with_loader(None, Ok("".to_string()), None, some_config_token);
         assert_eq!(token, Some("config_token".to_string()));
         assert_eq!(source, "本地Token文件");
     }
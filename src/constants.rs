@@ -7,14 +7,27 @@ pub const CONFIG_DIR_NAME: &str = concat!(".", clap::crate_name!());
 pub const CONFIG_FILE_NAME: &str = "config.json";
 pub const LOG_FILE_NAME: &str = "app.log";
 pub const LOG_FALLBACK_FILE_NAME: &str = "fallback.log";
+pub const STATE_FILE_NAME: &str = "state.jsonl";
+pub const CACHE_DIR_NAME: &str = "cache";
+pub const STATE_COMPACT_SLACK: usize = 100;
 pub const DEFAULT_SAVE_DIR: &str = "downloads";
+pub const PROFILE_ENV_VAR: &str = "SED_DL_PROFILE";
+pub const TOKEN_VAULT_FILE_NAME: &str = "token.enc";
+pub const TOKEN_VAULT_KDF_ITERATIONS: u32 = 600_000;
+pub const TOKEN_PASSPHRASE_ENV_VAR: &str = "SED_DL_TOKEN_PASSPHRASE";
+pub const KEYRING_SERVICE: &str = clap::crate_name!();
+pub const KEYRING_DEFAULT_USER: &str = "default";
 pub const UNCLASSIFIED_DIR: &str = "未分类资源";
 pub const DEFAULT_AUDIO_FORMAT: &str = "mp3";
 pub const DEFAULT_VIDEO_QUALITY: &str = "best";
+/// 默认优先下载 PDF，没有 PDF 时下载任意可用格式
+pub const DEFAULT_DOCUMENT_FORMATS: &str = "pdf,*";
 pub const DEFAULT_SELECTION: &str = "all";
 pub const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36";
 pub const HIGH_SCHOOL_STAGE_NAME: &str = "高中";
 pub const FILTER_CHAIN_SEPARATOR: &str = " -> ";
+/// 批量下载前的 Token 预检中，剩余有效期少于此值 (分钟) 即视为“即将过期”
+pub const TOKEN_EXPIRY_MARGIN_MINS: i64 = 30;
 
 pub const HELP_TOKEN_GUIDE: &str = r#"
 1. 登录平台: 使用 Chrome / Edge / Firefox 浏览器登录。
@@ -43,6 +56,32 @@ pub mod performance {
     pub const M3U8_MAX_CONCURRENCY: usize = 50; // 将上限调整为更合理的50
 }
 
+/// M3U8 视频分片的持久化工作目录 (位于视频所在目录下，合并成功后删除)
+pub const M3U8_WORK_DIR_PREFIX: &str = ".sed-dl-parts-";
+pub const M3U8_MANIFEST_FILE_NAME: &str = "manifest.json";
+/// 工作目录中按顺序追加分片数据的合并文件
+pub const M3U8_STREAM_FILE_NAME: &str = "stream.ts";
+/// 资源元数据附属文件 (--write-metadata json) 的文件名后缀
+pub const RESOURCE_METADATA_SUFFIX: &str = ".resource.json";
+/// 转封装为 MP4 的视频允许比平台记录的 TS 大小小多少 (按比例)
+pub const MP4_SIZE_SHRINK_TOLERANCE: f64 = 0.2;
+
+/// 进程退出码。脚本 / 定时任务可据此判断运行结果。
+pub mod exit_codes {
+    /// 所有任务均成功 (或被跳过)
+    pub const SUCCESS: i32 = 0;
+    /// 发生了其他错误，程序提前终止
+    pub const ERROR: i32 = 1;
+    /// 部分文件下载或校验失败
+    pub const DOWNLOAD_FAILED: i32 = 2;
+    /// 部分 (或全部) 任务的元数据解析失败
+    pub const METADATA_FAILED: i32 = 3;
+    /// 认证失败 (Token 缺失、无效或已过期)
+    pub const AUTH_FAILED: i32 = 4;
+    /// 用户中断 (Ctrl+C)
+    pub const INTERRUPTED: i32 = 130;
+}
+
 pub mod api {
     pub mod types {
         pub const TCH_MATERIAL: &str = "tchMaterial";
@@ -59,8 +98,24 @@ pub mod api {
     pub mod resource_formats {
         pub const PDF: &str = "pdf";
         pub const M3U8: &str = "m3u8";
+        /// m3u8 视频流合并后的默认容器格式
+        pub const TS: &str = "ts";
         pub const BIN: &str = "bin";
+        pub const JSON: &str = "json";
+        /// 可直接保存的字幕格式
+        pub const SUBTITLES: [&str; 4] = ["srt", "vtt", "ass", "ssa"];
+        /// 封面/缩略图可能使用的图片格式
+        pub const IMAGES: [&str; 5] = ["jpg", "jpeg", "png", "webp", "gif"];
     }
+    /// `ti_file_flag` 为这些值的条目是缩略图等附属文件，不作为文档下载
+    pub const NON_DOCUMENT_FILE_FLAGS: [&str; 3] = ["thumbnail", "cover", "preview"];
+    /// `ti_file_flag` 为这些值的图片条目被视为封面 (按优先级排列)
+    pub const COVER_FILE_FLAGS: [&str; 2] = ["cover", "thumbnail"];
+    /// `ti_file_flag` 中包含这些关键字的 JSON 条目被视为平台字幕
+    pub const CAPTION_FILE_FLAG_KEYWORDS: [&str; 3] = ["caption", "subtitle", "zimu"];
+    /// 在字幕的 `ti_file_flag` 或文件名中识别的语言代码，用作字幕文件名的语言后缀
+    pub const SUBTITLE_LANGUAGE_CODES: [&str; 10] =
+        ["zh", "en", "chs", "cht", "ja", "ko", "fr", "de", "ru", "es"];
     pub mod resource_types {
         pub const ASSETS_VIDEO: &str = "assets_video";
         pub const ASSETS_DOCUMENT: &str = "assets_document";
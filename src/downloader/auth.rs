@@ -1,7 +1,8 @@
 // src/downloader/auth.rs
 
 use super::job::ResourceDownloader;
-use crate::{config, constants, error::*, models::*, symbols, ui};
+use crate::{client::RobustClient, config, constants, error::*, models::*, symbols, ui};
+use chrono::{Local, TimeDelta, Utc};
 use colored::Colorize;
 use log::{debug, error, info, warn};
 use reqwest::StatusCode;
@@ -49,7 +50,7 @@ impl ResourceDownloader {
                             }
                             *self.context.token.lock().await = new_token.clone();
                             if ui::confirm("是否保存此新 Token 以便后续使用?", false)
-                                && let Err(e) = config::token::save_token(&new_token)
+                                && let Err(e) = config::token::save_token(&new_token, self.context.config.profile.as_deref())
                             {
                                 error!("尝试保存新Token时失败: {}", e);
                                 ui::warn(&format!("保存新Token失败: {}", e));
@@ -90,6 +91,7 @@ impl ResourceDownloader {
                     super::task_processor::TaskProcessor::prepare_download_action(
                         task,
                         &self.context.args,
+                        &self.context.state,
                     )
                     && action != DownloadAction::Skip
                 {
@@ -119,33 +121,239 @@ impl ResourceDownloader {
         })
     }
 
+    /// 批量下载开始前的 Token 预检：若 Token 已过期、即将过期或被服务器拒绝，则立即失败，
+    /// 避免下载到一半才因认证问题中止。探测请求本身失败时只记录警告，不阻止下载。
+    pub async fn preflight_token_check(&self, tasks: &[FileInfo]) -> AppResult<()> {
+        // 回放模式下元数据来自 --record 记录的目录，不依赖当前 Token，因此不做探测和有效期检查
+        if self.context.config.replay_dir.is_some() {
+            debug!("回放模式，跳过 Token 预检。");
+            return Ok(());
+        }
+        let token = self.context.token.lock().await.clone();
+
+        if let Some(expiry) = config::token::decode_token_expiry(&token) {
+            let remaining = expiry - Utc::now();
+            debug!("Token 过期时间: {}，剩余: {}", expiry, remaining);
+            if remaining <= TimeDelta::zero() {
+                return Err(AppError::TokenInvalid);
+            }
+            if remaining < TimeDelta::minutes(constants::TOKEN_EXPIRY_MARGIN_MINS) {
+                let expiry = expiry.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string();
+                if !self.context.args.options.ignore_token_expiry {
+                    return Err(AppError::TokenExpiringSoon(expiry));
+                }
+                warn!("Token 将于 {} 过期，按 --ignore-token-expiry 继续下载", expiry);
+                ui::warn(&format!("Access Token 将于 {} 过期，下载可能在中途因认证失败而中断。", expiry));
+            }
+        }
+
+        match probe_token(&self.context.http_client, &token, find_probe_url(tasks)).await {
+            TokenProbeResult::Valid => {
+                info!("Token 预检通过。");
+                Ok(())
+            }
+            TokenProbeResult::Rejected if token.is_empty() => Err(AppError::TokenMissing),
+            TokenProbeResult::Rejected => Err(AppError::TokenInvalid),
+            TokenProbeResult::Unreachable(e) => {
+                warn!("Token 预检请求失败，将继续下载: {}", e);
+                Ok(())
+            }
+            TokenProbeResult::NoProbeUrl => Ok(()),
+        }
+    }
+
     /// 使用 HEAD 请求探测一个 URL，以验证新 Token 的有效性。
     async fn validate_token_with_probe(&self, token: &str, tasks: &[FileInfo]) -> bool {
-        let Some(probe_url_str) = tasks.iter().find_map(|t| {
-            if t.url.starts_with("http") {
-                Some(&t.url)
-            } else {
-                None
+        match probe_token(&self.context.http_client, token, find_probe_url(tasks)).await {
+            TokenProbeResult::Valid => true,
+            TokenProbeResult::NoProbeUrl => {
+                warn!("在剩余任务中未找到可用于探测Token的HTTP URL。");
+                true
             }
-        }) else {
-            warn!("在剩余任务中未找到可用于探测Token的HTTP URL。");
-            return true;
-        };
-        let Ok(mut url) = Url::parse(probe_url_str) else {
-            return false;
-        };
+            TokenProbeResult::Rejected | TokenProbeResult::Unreachable(_) => false,
+        }
+    }
+}
+
+/// 从任务列表中找出第一个可用于探测 Token 的 HTTP URL。
+pub(crate) fn find_probe_url(tasks: &[FileInfo]) -> Option<&str> {
+    tasks
+        .iter()
+        .map(|t| t.url.as_str())
+        .find(|url| url.starts_with("http"))
+}
+
+/// 携带 Token 对受保护的资源 URL 发送 HEAD 请求，根据响应状态判断 Token 是否有效。
+pub(crate) async fn probe_token(
+    http_client: &RobustClient,
+    token: &str,
+    probe_url: Option<&str>,
+) -> TokenProbeResult {
+    let Some(probe_url_str) = probe_url else {
+        return TokenProbeResult::NoProbeUrl;
+    };
+    let mut url = match Url::parse(probe_url_str) {
+        Ok(url) => url,
+        Err(e) => return TokenProbeResult::Unreachable(e.to_string()),
+    };
+    // 与实际下载时一致：Token 为空时不附加参数
+    if !token.is_empty() {
         url.query_pairs_mut().append_pair("accessToken", token);
-        let res = self.context.http_client.client.head(url).send().await;
-        match res {
-            Ok(response) => {
-                let status = response.status();
-                debug!("Token 探测响应状态码: {}", status);
-                !matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN)
-            }
-            Err(e) => {
-                warn!("Token 探测请求失败: {}", e);
-                false
+    }
+    match http_client.client.head(url).send().await {
+        Ok(response) => {
+            let status = response.status();
+            debug!("Token 探测响应状态码: {}", status);
+            if matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) {
+                TokenProbeResult::Rejected
+            } else {
+                TokenProbeResult::Valid
             }
         }
+        Err(e) => {
+            warn!("Token 探测请求失败: {}", e);
+            TokenProbeResult::Unreachable(e.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cli::Cli, config::AppConfig, DownloadJobContext};
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+    use clap::Parser;
+    use std::sync::Arc;
+    use tokio::sync::Mutex as TokioMutex;
+
+    fn create_test_downloader(token: &str) -> ResourceDownloader {
+        create_test_downloader_with_config(token, AppConfig::default())
+    }
+
+    fn create_test_downloader_with_config(token: &str, config: AppConfig) -> ResourceDownloader {
+        let args = Cli::parse_from(["sed-dl", "--url", "https://example.com"]);
+        ResourceDownloader::new(DownloadJobContext {
+            token: Arc::new(TokioMutex::new(token.to_string())),
+            ..DownloadJobContext::for_test(args, config)
+        })
+    }
+
+    fn jwt_expiring_in(delta: TimeDelta) -> String {
+        let exp = (Utc::now() + delta).timestamp();
+        let payload = URL_SAFE_NO_PAD.encode(format!(r#"{{"exp":{}}}"#, exp));
+        format!("header.{}.signature", payload)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_preflight_rejects_expired_or_expiring_tokens() {
+        let expired = create_test_downloader(&jwt_expiring_in(TimeDelta::hours(-1)));
+        assert!(matches!(
+            expired.preflight_token_check(&[]).await,
+            Err(AppError::TokenInvalid)
+        ));
+
+        let expiring = create_test_downloader(&jwt_expiring_in(TimeDelta::minutes(5)));
+        assert!(matches!(
+            expiring.preflight_token_check(&[]).await,
+            Err(AppError::TokenExpiringSoon(_))
+        ));
+
+        let fresh = create_test_downloader(&jwt_expiring_in(TimeDelta::days(7)));
+        assert!(fresh.preflight_token_check(&[]).await.is_ok());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_preflight_ignore_token_expiry_only_warns() {
+        let args = Cli::parse_from(["sed-dl", "--url", "https://example.com", "--ignore-token-expiry"]);
+        let context = |token: String| {
+            ResourceDownloader::new(DownloadJobContext {
+                token: Arc::new(TokioMutex::new(token)),
+                ..DownloadJobContext::for_test(args.clone(), AppConfig::default())
+            })
+        };
+
+        let expiring = context(jwt_expiring_in(TimeDelta::minutes(5)));
+        assert!(expiring.preflight_token_check(&[]).await.is_ok());
+
+        // 已经过期的 Token 服务器必然拒绝，仍然直接失败
+        let expired = context(jwt_expiring_in(TimeDelta::hours(-1)));
+        assert!(matches!(
+            expired.preflight_token_check(&[]).await,
+            Err(AppError::TokenInvalid)
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_preflight_skipped_when_replaying() {
+        let mut server = mockito::Server::new_async().await;
+        let probe = server
+            .mock("HEAD", "/a.pdf")
+            .match_query(mockito::Matcher::Any)
+            .with_status(403)
+            .expect(0)
+            .create_async()
+            .await;
+        let tasks = vec![FileInfo {
+            url: format!("{}/a.pdf", server.url()),
+            ..Default::default()
+        }];
+        let replaying = AppConfig {
+            replay_dir: Some(std::path::PathBuf::from("bug-report")),
+            ..Default::default()
+        };
+
+        let expired = create_test_downloader_with_config(&jwt_expiring_in(TimeDelta::hours(-1)), replaying);
+        assert!(expired.preflight_token_check(&tasks).await.is_ok());
+        probe.assert_async().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_preflight_probes_first_task() {
+        let mut server = mockito::Server::new_async().await;
+        let _rejected = server
+            .mock("HEAD", "/a.pdf")
+            .match_query(mockito::Matcher::Any)
+            .with_status(403)
+            .create_async()
+            .await;
+        let tasks = vec![FileInfo {
+            url: format!("{}/a.pdf", server.url()),
+            ..Default::default()
+        }];
+
+        let result = create_test_downloader("opaque-token")
+            .preflight_token_check(&tasks)
+            .await;
+        assert!(matches!(result, Err(AppError::TokenInvalid)));
+
+        let result = create_test_downloader("").preflight_token_check(&tasks).await;
+        assert!(matches!(result, Err(AppError::TokenMissing)));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_probe_token_classifies_responses() {
+        let mut server = mockito::Server::new_async().await;
+        let _valid = server
+            .mock("HEAD", "/ok.pdf")
+            .match_query(mockito::Matcher::UrlEncoded("accessToken".into(), "t".into()))
+            .with_status(200)
+            .create_async()
+            .await;
+        let _rejected = server
+            .mock("HEAD", "/denied.pdf")
+            .match_query(mockito::Matcher::Any)
+            .with_status(401)
+            .create_async()
+            .await;
+        let client = RobustClient::new(Arc::new(AppConfig::default())).unwrap();
+
+        let ok_url = format!("{}/ok.pdf", server.url());
+        let denied_url = format!("{}/denied.pdf", server.url());
+        assert_eq!(probe_token(&client, "t", Some(&ok_url)).await, TokenProbeResult::Valid);
+        assert_eq!(
+            probe_token(&client, "t", Some(&denied_url)).await,
+            TokenProbeResult::Rejected
+        );
+        assert_eq!(probe_token(&client, "t", None).await, TokenProbeResult::NoProbeUrl);
     }
 }
@@ -1,16 +1,21 @@
 // src/downloader/job.rs
 
-use super::{negotiator::ItemNegotiator, task_runner};
+use super::{negotiator::{ItemNegotiator, attach_subtitles_to_videos}, sidecar, task_processor::TaskProcessor, task_runner};
 use crate::{
     cli::ResourceType,
+    config::FilenameTemplateConfig,
     constants,
     error::*,
-    models::{FileInfo, MetadataExtractionResult, ResourceCategory},
+    models::{FileInfo, MetadataExtractionResult, PreviewEntry, ResourceCategory},
     ui, utils, DownloadJobContext,
 };
 use anyhow::anyhow;
 use log::{debug, error, info, warn};
-use std::{fs, path::Path};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
 
 
 #[derive(Clone)]
@@ -32,7 +37,7 @@ impl ResourceDownloader {
             return Ok(true);
         }
 
-        let base_output_dir = self.context.args.output.clone();
+        let base_output_dir = self.context.config.output_dir.clone();
         fs::create_dir_all(&base_output_dir)?;
         let absolute_path = dunce::canonicalize(&base_output_dir)?;
         info!("文件将保存到目录: \"{}\"", absolute_path.display());
@@ -60,7 +65,50 @@ impl ResourceDownloader {
         }
 
         let final_tasks_with_paths = self.prepare_final_tasks(final_tasks, &base_output_dir)?;
-        self.execute_download_loop(final_tasks_with_paths).await
+        let metadata_formats = &self.context.args.options.write_metadata;
+        let mut sidecar_tasks = if metadata_formats.is_empty() {
+            vec![]
+        } else {
+            final_tasks_with_paths.clone()
+        };
+        let all_succeeded = self.execute_download_loop(final_tasks_with_paths).await?;
+        if !sidecar_tasks.is_empty() {
+            // 只记录本次确认完整的文件，磁盘上残留的旧文件不算
+            let completed = self.context.manager.completed_files();
+            sidecar_tasks.retain(|task| completed.contains(&task.filepath));
+            // 元数据只是附加产物，写入失败不影响下载结果 (失败原因已逐个警告)
+            let count = sidecar::write_sidecars(&sidecar_tasks, &base_output_dir, metadata_formats);
+            if count > 0 {
+                ui::info(&format!("已写入 {} 个元数据文件。", count));
+            }
+        }
+        Ok(all_succeeded)
+    }
+
+    /// 预览流程 (--dry-run)：执行与下载相同的选择和路径解析，但不创建目录、不下载任何文件。
+    pub fn preview_items(&self, items: Vec<FileInfo>) -> AppResult<Vec<PreviewEntry>> {
+        let selected_indices = self.parse_selection_from_args(&items)?;
+        let base_output_dir = &self.context.config.output_dir;
+        let mut files = selected_indices
+            .into_iter()
+            .map(|i| {
+                let mut file = items[i].clone();
+                file.filepath = utils::preview_join_path(base_output_dir, &file.filepath)?;
+                Ok(file)
+            })
+            .collect::<AppResult<Vec<FileInfo>>>()?;
+        resolve_final_paths(&mut files, &self.context.config.filename_templates);
+        files
+            .into_iter()
+            .map(|file| {
+                let (action, _, reason) = TaskProcessor::prepare_download_action(
+                    &file,
+                    &self.context.args,
+                    &self.context.state,
+                )?;
+                Ok(PreviewEntry { file, action, reason })
+            })
+            .collect()
     }
 
     /// 封装了从单个输入（URL/ID）抓取元数据的完整逻辑
@@ -70,7 +118,7 @@ impl ResourceDownloader {
         use constants::api::types::*;
 
         let (extractor, resource_id) = if utils::is_resource_id(task_input) {
-            let resource_type_enum = context.args.r#type.as_ref().ok_or_else(|| {
+            let resource_type_enum = context.args.selection.r#type.as_ref().ok_or_else(|| {
                 AppError::UserInputError("使用ID时必须提供 --type".to_string())
             })?;
             let type_key = match resource_type_enum {
@@ -92,17 +140,21 @@ impl ResourceDownloader {
             )));
         };
 
-        let all_file_items = extractor.extract_file_info(&resource_id, context).await?;
+        let mut all_file_items = extractor.extract_file_info(&resource_id, context).await?;
+        for item in &mut all_file_items {
+            item.resource_id.get_or_insert_with(|| resource_id.clone());
+        }
         let original_count = all_file_items.len();
 
-        let items_after_ext_filter = if let Some(exts) = &context.args.filter_ext {
+        let items_after_ext_filter = if let Some(exts) = &context.args.options.filter_ext {
             let lower_exts_to_keep: Vec<String> = exts.iter().map(|s| s.to_lowercase()).collect();
             all_file_items
                 .into_iter()
                 .filter(|item| {
-                    item.filepath
-                        .extension()
-                        .and_then(|s| s.to_str())
+                    // 文件名模板中的 {ext} 不一定位于末尾，优先使用提取器记录的格式
+                    item.format
+                        .as_deref()
+                        .or_else(|| item.filepath.extension().and_then(|s| s.to_str()))
                         .is_some_and(|ext| lower_exts_to_keep.contains(&ext.to_lowercase()))
                 })
                 .collect()
@@ -126,8 +178,8 @@ impl ResourceDownloader {
     }
     
     fn parse_selection_from_args(&self, items: &[FileInfo]) -> AppResult<Vec<usize>> {
-        let user_input = self.context.args.select.clone();
-        let indices = utils::parse_selection_indices(&user_input, items.len());
+        let user_input = self.context.args.selection.selection();
+        let indices = utils::parse_selection_indices(user_input, items.len());
         debug!(
             "非交互模式：根据输入 '{}' 解析出索引: {:?}",
             user_input, indices
@@ -153,7 +205,7 @@ impl ResourceDownloader {
             &options,
             "文件下载列表",
             "支持格式: 1, 3, 2-4, all",
-            &self.context.args.select,
+            self.context.args.selection.selection(),
         )?;
         let indices = utils::parse_selection_indices(&user_input, options.len());
         debug!(
@@ -188,6 +240,7 @@ impl ResourceDownloader {
             other_items
         };
         
+        attach_subtitles_to_videos(&mut prepared_list);
         prepared_list.sort_by_key(|f| f.filepath.clone());
         
         // 在非交互模式下，prepared_list 的长度就是版本过滤后的数量。
@@ -207,13 +260,15 @@ impl ResourceDownloader {
         tasks: Vec<FileInfo>,
         base_dir: &Path,
     ) -> AppResult<Vec<FileInfo>> {
-        tasks
+        let mut tasks = tasks
             .into_iter()
             .map(|mut item| {
                 item.filepath = utils::secure_join_path(base_dir, &item.filepath)?;
                 Ok(item)
             })
-            .collect()
+            .collect::<AppResult<Vec<FileInfo>>>()?;
+        resolve_final_paths(&mut tasks, &self.context.config.filename_templates);
+        Ok(tasks)
     }
 
     pub(super) async fn execute_download_loop(&self, final_tasks: Vec<FileInfo>) -> AppResult<bool> {
@@ -250,4 +305,155 @@ impl ResourceDownloader {
         self.context.manager.print_report();
         Ok(self.context.manager.did_all_succeed())
     }
-}
\ No newline at end of file
+}
+
+/// 确定选中文件的最终路径：字幕按实际选中的视频重新命名 (选择前按全部候选命名，
+/// 用户可能未选中最高清晰度)，再为仍然重名的文件追加序号。
+fn resolve_final_paths(tasks: &mut [FileInfo], templates: &FilenameTemplateConfig) {
+    attach_subtitles_to_videos(tasks);
+    disambiguate_paths(tasks, templates);
+}
+
+/// 文件名模板缺少 `{quality}`、`{ext}` 等占位符时，同一资源的多个版本会解析到同一路径，
+/// 并发下载时互相覆盖。按 URL 排序后为除第一个以外的文件追加 " (2)"、" (3)" 等序号，
+/// 保证每次运行得到相同的路径，并提示用户检查对应的模板。
+fn disambiguate_paths(tasks: &mut [FileInfo], templates: &FilenameTemplateConfig) {
+    let mut groups: HashMap<PathBuf, Vec<usize>> = HashMap::new();
+    for (i, task) in tasks.iter().enumerate() {
+        groups.entry(task.filepath.clone()).or_default().push(i);
+    }
+    let mut taken: HashSet<PathBuf> = groups.keys().cloned().collect();
+    let mut duplicates: Vec<(PathBuf, Vec<usize>)> =
+        groups.into_iter().filter(|(_, indices)| indices.len() > 1).collect();
+    duplicates.sort();
+
+    for (path, mut indices) in duplicates {
+        indices.sort_by(|&a, &b| tasks[a].url.cmp(&tasks[b].url));
+        let (key, template) = template_for(&tasks[indices[0]], templates);
+        warn!("{} 个文件解析到同一路径 {:?} (模板 {}: \"{}\")", indices.len(), path, key, template);
+        ui::warn(&format!(
+            "{} 个文件的保存路径相同: {}，已自动追加序号。请检查文件名模板 filename_templates.{} = \"{}\" 是否缺少 {{quality}} 或 {{ext}}。",
+            indices.len(),
+            path.display(),
+            key,
+            template
+        ));
+        let mut number = 1;
+        for &i in &indices[1..] {
+            let numbered = loop {
+                number += 1;
+                let candidate = numbered_path(&path, tasks[i].format.as_deref(), number);
+                if taken.insert(candidate.clone()) {
+                    break candidate;
+                }
+            };
+            tasks[i].filepath = numbered;
+        }
+    }
+}
+
+/// 在文件名的扩展名 (`.{format}`) 之前插入序号，文件名不以该扩展名结尾时追加在末尾
+fn numbered_path(path: &Path, format: Option<&str>, number: usize) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let ext_suffix = format.map(|f| format!(".{}", f)).unwrap_or_default();
+    let numbered = match name.strip_suffix(&ext_suffix).filter(|_| !ext_suffix.is_empty()) {
+        Some(stem) => format!("{} ({}){}", stem, number, ext_suffix),
+        None => format!("{} ({})", name, number),
+    };
+    path.with_file_name(numbered)
+}
+
+/// 生成该文件名时使用的模板 (配置项名称及模板内容)
+fn template_for<'a>(item: &FileInfo, templates: &'a FilenameTemplateConfig) -> (&'static str, &'a str) {
+    let is_textbook = item
+        .metadata
+        .as_ref()
+        .is_some_and(|m| m.resource_type == constants::api::types::TCH_MATERIAL);
+    match item.category {
+        ResourceCategory::Video => ("video", &templates.video),
+        ResourceCategory::Audio => ("audio", &templates.audio),
+        ResourceCategory::Subtitle => ("subtitle", &templates.subtitle),
+        ResourceCategory::Image => ("cover", &templates.cover),
+        _ if is_textbook => ("textbook", &templates.textbook),
+        _ => ("document", &templates.document),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subtitles_follow_the_selected_video() {
+        let file = |path: &str, category, quality| FileInfo {
+            filepath: PathBuf::from(path),
+            category,
+            quality,
+            format: Some(if category == ResourceCategory::Video { "ts" } else { "srt" }.to_string()),
+            variant_group: Some("lesson-1".to_string()),
+            ..Default::default()
+        };
+        let mut candidates = vec![
+            file("/out/第1课 [720].ts", ResourceCategory::Video, Some(720)),
+            file("/out/第1课 [1080].ts", ResourceCategory::Video, Some(1080)),
+            file("/out/第1课.srt", ResourceCategory::Subtitle, None),
+        ];
+        // 选择前按全部候选命名，字幕跟随最高清晰度
+        attach_subtitles_to_videos(&mut candidates);
+        assert_eq!(candidates[2].filepath, Path::new("/out/第1课 [1080].srt"));
+
+        // 只选中 720p 时，字幕改为与 720p 视频同名
+        let mut selected = vec![candidates[0].clone(), candidates[2].clone()];
+        resolve_final_paths(&mut selected, &FilenameTemplateConfig::default());
+        assert_eq!(selected[1].filepath, Path::new("/out/第1课 [720].srt"));
+    }
+
+    #[test]
+    fn test_disambiguate_paths_for_template_without_quality() {
+        // 模板 "{lesson}.{ext}" 使同一课时的 720p 和 1080p 视频解析到同一路径
+        let video = |url: &str| FileInfo {
+            filepath: PathBuf::from("/out/第1课.mp4"),
+            url: url.to_string(),
+            category: ResourceCategory::Video,
+            format: Some("mp4".to_string()),
+            ..Default::default()
+        };
+        let mut tasks = vec![
+            video("https://a/1080.m3u8"),
+            video("https://a/720.m3u8"),
+            FileInfo {
+                filepath: PathBuf::from("/out/第1课 (2).mp4"),
+                url: "https://a/other.m3u8".to_string(),
+                ..Default::default()
+            },
+        ];
+        let templates = FilenameTemplateConfig {
+            video: "{lesson}.{ext}".to_string(),
+            ..Default::default()
+        };
+        disambiguate_paths(&mut tasks, &templates);
+
+        let paths: Vec<&Path> = tasks.iter().map(|t| t.filepath.as_path()).collect();
+        // 按 URL 排序决定序号，跳过已被占用的 "(2)"
+        assert_eq!(
+            paths,
+            [
+                Path::new("/out/第1课.mp4"),
+                Path::new("/out/第1课 (3).mp4"),
+                Path::new("/out/第1课 (2).mp4"),
+            ]
+        );
+
+        // 顺序不同时结果相同
+        let mut reversed: Vec<FileInfo> = tasks.iter().rev().cloned().collect();
+        for task in &mut reversed[1..] {
+            task.filepath = PathBuf::from("/out/第1课.mp4");
+        }
+        disambiguate_paths(&mut reversed, &templates);
+        assert_eq!(reversed[1].filepath, Path::new("/out/第1课 (3).mp4"));
+        assert_eq!(reversed[2].filepath, Path::new("/out/第1课.mp4"));
+    }
+}
@@ -1,5 +1,6 @@
 // src/downloader/m3u8.rs
 
+use super::remux::TsToMp4Remuxer;
 use crate::models::DownloadStatus;
 use crate::{client::RobustClient, constants, error::*, models::FileInfo, DownloadJobContext};
 use aes::cipher::{BlockDecryptMut, KeyInit, KeyIvInit, block_padding::Pkcs7};
@@ -7,14 +8,17 @@ use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
 use ecb;
 use futures::{StreamExt, stream};
 use indicatif::ProgressBar;
+use itertools::Itertools;
 use log::{debug, error, info, warn};
 use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::{
     cmp::min,
+    collections::HashMap,
     fs::{self, File},
-    io::{self, BufWriter, Write},
-    path::Path,
+    io::{Read, Write},
+    path::{Path, PathBuf},
     sync::Arc,
     time::Duration,
 };
@@ -27,16 +31,94 @@ pub(super) struct M3u8Downloader {
     context: DownloadJobContext,
 }
 
+/// 单个分片的解密参数 (AES-128-CBC)
+#[derive(Clone)]
+struct SegmentKey {
+    key: Arc<Vec<u8>>,
+    iv: [u8; 16],
+}
+
+impl SegmentKey {
+    fn decryptor(&self) -> AppResult<Aes128CbcDec> {
+        Aes128CbcDec::new_from_slices(&self.key, &self.iv)
+            .map_err(|e| AppError::Security(format!("AES解密器初始化失败: {}", e)))
+    }
+}
+
+/// 分片工作目录中的清单：记录播放列表的分片，以及已按顺序写入合并文件的进度。
+/// 每提交一个分片都会重写清单，因此只保存分片列表的摘要，而不是完整的 URL 列表。
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct SegmentManifest {
+    playlist_url: String,
+    segment_count: usize,
+    /// 分片 URL 列表的 MD5，用于判断播放列表是否发生变化
+    segments_md5: String,
+    /// 已完整写入合并文件的分片数 (总是从第一个分片开始连续计数)
+    #[serde(default)]
+    committed: usize,
+    /// 已写入分片在合并文件中的总字节数，续传时合并文件会被截断到这个长度
+    #[serde(default)]
+    committed_bytes: u64,
+}
+
+impl SegmentManifest {
+    fn new(playlist_url: String, segments: &[String]) -> Self {
+        let mut hasher = Md5::new();
+        for segment in segments {
+            hasher.update(segment.as_bytes());
+            hasher.update(b"\n");
+        }
+        Self {
+            playlist_url,
+            segment_count: segments.len(),
+            segments_md5: format!("{:x}", hasher.finalize()),
+            committed: 0,
+            committed_bytes: 0,
+        }
+    }
+
+    /// 是否描述的是同一个播放列表
+    fn matches(&self, other: &Self) -> bool {
+        self.playlist_url == other.playlist_url
+            && self.segment_count == other.segment_count
+            && self.segments_md5 == other.segments_md5
+    }
+
+    /// 先写入临时文件再重命名，中途中断时不会留下不完整的清单
+    fn save(&self, work_dir: &Path) -> AppResult<()> {
+        let mut temp_file = NamedTempFile::new_in(work_dir)?;
+        temp_file.write_all(&serde_json::to_vec(self)?)?;
+        temp_file.persist(work_dir.join(constants::M3U8_MANIFEST_FILE_NAME))?;
+        Ok(())
+    }
+}
+
 impl M3u8Downloader {
     pub(super) fn new(context: DownloadJobContext) -> Self {
         Self { context }
     }
 
+    /// 视频的分片工作目录，以播放列表 URL 区分，位于视频的保存目录下。
+    pub(super) fn work_dir_for(item: &FileInfo) -> PathBuf {
+        let hash = format!("{:x}", Md5::digest(item.url.as_bytes()));
+        let parent = item.filepath.parent().unwrap_or_else(|| Path::new("."));
+        parent.join(format!("{}{}", constants::M3U8_WORK_DIR_PREFIX, &hash[..16]))
+    }
+
+    /// 是否存在上次未完成的分片 (可续传)
+    pub(super) fn has_partial_segments(item: &FileInfo) -> bool {
+        Self::work_dir_for(item)
+            .join(constants::M3U8_MANIFEST_FILE_NAME)
+            .is_file()
+    }
+
+    /// `resume` 为 false 时会丢弃工作目录中已有的分片，从头下载。
     pub(super) async fn download(
         &self,
         item: &FileInfo,
         pbar: ProgressBar,
         use_byte_progress: bool,
+        resume: bool,
     ) -> AppResult<DownloadStatus> {
         info!("开始下载 M3U8 视频: {}", item.filepath.display());
         let mut url = Url::parse(&item.url)?;
@@ -47,100 +129,115 @@ impl M3u8Downloader {
         }
         drop(token); // 尽早释放锁
 
-        let (key, iv, playlist) = self.get_m3u8_key_and_playlist(url.clone()).await?;
+        let (playlist_url, playlist, segment_keys) = self.get_m3u8_keys_and_playlist(url).await?;
 
         if playlist.segments.is_empty() {
             error!("M3U8文件 '{}' 不含分片", item.url);
             return Err(AppError::M3u8Parse("M3U8文件不含分片".to_string()));
         }
         info!(
-            "M3U8 包含 {} 个分片。 解密密钥: {}, IV: {}",
+            "M3U8 包含 {} 个分片，其中 {} 个已加密",
             playlist.segments.len(),
-            if key.is_some() { "有" } else { "无" },
-            iv.as_deref().unwrap_or("无")
+            segment_keys.iter().flatten().count()
         );
         let segment_urls: Vec<String> = playlist.segments.iter().map(|s| s.uri.clone()).collect();
 
-        let decryptor = if let (Some(key), Some(iv_hex)) = (key, iv) {
-            let iv_bytes = hex::decode(iv_hex.trim_start_matches("0x"))
-                .map_err(|e| AppError::M3u8Parse(format!("无效的IV十六进制值: {}", e)))?;
-            Some(
-                Aes128CbcDec::new_from_slices(&key, &iv_bytes)
-                    .map_err(|e| AppError::Security(format!("AES解密器初始化失败: {}", e)))?,
-            )
-        } else {
-            None
-        };
-
-        let temp_dir = tempfile::Builder::new().prefix("m3u8_dl_").tempdir()?;
-        debug!("为M3U8下载创建临时目录: {:?}", temp_dir.path());
+        let work_dir = Self::work_dir_for(item);
+        let mut manifest = prepare_work_dir(
+            &work_dir,
+            SegmentManifest::new(item.url.clone(), &segment_urls),
+            resume,
+        )?;
+        let resumed = manifest.committed > 0;
+        if resumed {
+            info!(
+                "从工作目录 {:?} 续传: 已合并 {} 个分片，还需下载 {} 个",
+                work_dir,
+                manifest.committed,
+                segment_urls.len() - manifest.committed
+            );
+            if use_byte_progress {
+                pbar.inc(manifest.committed_bytes);
+            }
+        }
 
-        self.download_segments_with_retry(
-            &url,
+        self.stream_segments(
+            &playlist_url,
             &segment_urls,
-            temp_dir.path(),
-            decryptor,
+            &segment_keys,
+            &work_dir,
+            &mut manifest,
             pbar,
             use_byte_progress,
         )
         .await?;
 
-        info!("所有分片下载完成，开始合并...");
-        self.merge_ts_segments(temp_dir.path(), segment_urls.len(), &item.filepath)?;
-        info!("分片合并完成 -> {}", item.filepath.display());
-        Ok(DownloadStatus::Success)
+        info!("所有分片下载完成，生成最终文件...");
+        let remux_to_mp4 = item
+            .format
+            .as_deref()
+            .is_some_and(|format| format.eq_ignore_ascii_case("mp4"));
+        finalize_stream(
+            &work_dir.join(constants::M3U8_STREAM_FILE_NAME),
+            &item.filepath,
+            remux_to_mp4,
+        )?;
+        info!("视频保存完成 -> {}", item.filepath.display());
+        if let Err(e) = fs::remove_dir_all(&work_dir) {
+            warn!("清理分片工作目录 {:?} 失败: {}", work_dir, e);
+        }
+        Ok(if resumed {
+            DownloadStatus::Resumed
+        } else {
+            DownloadStatus::Success
+        })
     }
 
-    fn merge_ts_segments(
+    async fn fetch_and_parse_playlist(
         &self,
-        temp_dir: &Path,
-        num_segments: usize,
-        output_path: &std::path::PathBuf,
-    ) -> AppResult<()> {
-        // 在最终输出目录的同级目录下创建一个具名临时文件
-        let output_parent = output_path.parent().unwrap_or_else(|| Path::new("."));
-        let mut temp_file = NamedTempFile::new_in(output_parent)?;
-
-        {
-            let mut writer = BufWriter::new(&mut temp_file); // 借用现在只在这个块内有效
-            for i in 0..num_segments {
-                let ts_path = temp_dir.join(format!("{:05}.ts", i));
-                if !ts_path.exists() {
-                    let filename = ts_path
-                        .file_name()
-                        .map(|s| s.to_string_lossy().to_string())
-                        .unwrap_or_else(|| "未知分片".to_string());
-                    return Err(AppError::Merge(format!("丢失视频分片: {}", filename)));
-                }
-                let mut reader = File::open(ts_path)?;
-                io::copy(&mut reader, &mut writer)?;
-            }
-            writer.flush()?;
-        } // writer 在这里被 drop，对 temp_file 的借用结束 ---
+        url: &Url,
+    ) -> AppResult<(Url, m3u8_rs::MediaPlaylist)> {
+        let master = match self.fetch_playlist(url).await? {
+            m3u8_rs::Playlist::MediaPlaylist(media) => return Ok((url.clone(), media)),
+            m3u8_rs::Playlist::MasterPlaylist(master) => master,
+        };
 
-        // 现在可以安全地移动 temp_file 了
-        temp_file.persist(output_path)?;
+        let quality = &self.context.args.selection.video_quality;
+        let variant = select_variant(&master.variants, quality).ok_or_else(|| {
+            AppError::M3u8Parse("主播放列表中没有可用的视频码流".to_string())
+        })?;
+        info!(
+            "M3U8 为主播放列表 (共 {} 个码流)，按清晰度 '{}' 选择: 分辨率 {}, 码率 {}",
+            master.variants.len(),
+            quality,
+            variant
+                .resolution
+                .as_ref()
+                .map_or("未知".to_string(), |r| format!("{}x{}", r.width, r.height)),
+            variant.bandwidth
+        );
 
-        Ok(())
+        let mut variant_url = url.join(&variant.uri)?;
+        // 相对地址解析会丢掉查询参数，子播放列表同样需要携带 Token
+        if let Some((_, token)) = url.query_pairs().find(|(k, _)| k == "accessToken")
+            && !variant_url.query_pairs().any(|(k, _)| k == "accessToken")
+        {
+            variant_url.query_pairs_mut().append_pair("accessToken", &token);
+        }
+        match self.fetch_playlist(&variant_url).await? {
+            m3u8_rs::Playlist::MediaPlaylist(media) => Ok((variant_url, media)),
+            m3u8_rs::Playlist::MasterPlaylist(_) => Err(AppError::M3u8Parse(
+                "主播放列表中的码流地址仍是主播放列表".to_string(),
+            )),
+        }
     }
 
-    async fn fetch_and_parse_playlist(&self, url: &Url) -> AppResult<m3u8_rs::MediaPlaylist> {
+    async fn fetch_playlist(&self, url: &Url) -> AppResult<m3u8_rs::Playlist> {
         debug!("获取并解析 M3U8 文件: {}", url);
-        let playlist_text = self
-            .context
-            .http_client
-            .get(url.clone())
-            .await?
-            .text()
-            .await?;
-
-        match m3u8_rs::parse_playlist_res(playlist_text.as_bytes()) {
-            Ok(m3u8_rs::Playlist::MediaPlaylist(media)) => Ok(media),
-            Ok(_) => Err(AppError::M3u8Parse(
-                "预期的M3U8文件不是媒体播放列表".to_string(),
-            )),
-            Err(e) => Err(AppError::M3u8Parse(e.to_string())),
-        }
+        let playlist_text = self.context.http_client.fetch_text(url.as_str()).await?;
+
+        m3u8_rs::parse_playlist_res(playlist_text.as_bytes())
+            .map_err(|e| AppError::M3u8Parse(e.to_string()))
     }
 
     async fn fetch_and_decrypt_key(&self, base_url: &Url, key_uri: &str) -> AppResult<Vec<u8>> {
@@ -150,13 +247,7 @@ impl M3u8Downloader {
         // 1. 获取 nonce
         let nonce_url = format!("{}/signs", key_url);
         debug!("获取 nonce from: {}", nonce_url);
-        let signs_data: Value = self
-            .context
-            .http_client
-            .get(&nonce_url)
-            .await?
-            .json()
-            .await?;
+        let signs_data = self.fetch_key_server_json(&nonce_url).await?;
         let nonce = signs_data
             .get("nonce")
             .and_then(Value::as_str)
@@ -179,13 +270,7 @@ impl M3u8Downloader {
         // 3. 获取加密的 key
         let final_key_url = format!("{}?nonce={}&sign={}", key_url, nonce, sign);
         debug!("获取最终密钥 from: {}", final_key_url);
-        let key_data: Value = self
-            .context
-            .http_client
-            .get(&final_key_url)
-            .await?
-            .json()
-            .await?;
+        let key_data = self.fetch_key_server_json(&final_key_url).await?;
         let encrypted_key_b64 = key_data.get("key").and_then(Value::as_str).ok_or_else(|| {
             AppError::M3u8Parse("密钥服务器响应中未找到加密密钥 'key'".to_string())
         })?;
@@ -202,146 +287,319 @@ impl M3u8Downloader {
         Ok(decrypted_key)
     }
 
-    async fn download_segments_with_retry(
+    async fn fetch_key_server_json(&self, url: &str) -> AppResult<Value> {
+        let text = self.context.http_client.fetch_text(url).await?;
+        serde_json::from_str(&text)
+            .map_err(|e| AppError::Security(format!("密钥服务器响应解析失败: {}", e)))
+    }
+
+    /// 并发下载分片，并按顺序把解密后的数据追加到工作目录中的合并文件。
+    /// `buffered` 按输入顺序产出结果，且同时最多只有 `segment_concurrency` 个分片在下载或等待写入，
+    /// 相当于一个有界的重排缓冲区：内存占用不随视频大小增长，磁盘上也只保留一份数据。
+    #[allow(clippy::too_many_arguments)]
+    async fn stream_segments(
         &self,
         base_url: &Url,
         urls: &[String],
-        temp_path: &Path,
-        decryptor: Option<Aes128CbcDec>,
+        keys: &[Option<SegmentKey>],
+        work_dir: &Path,
+        manifest: &mut SegmentManifest,
         pbar: ProgressBar,
         use_byte_progress: bool,
     ) -> AppResult<()> {
-        let mut failed_indices: Vec<usize> = (0..urls.len()).collect();
-        for attempt in 0..=self.context.config.max_retries {
-            if failed_indices.is_empty() {
-                break;
-            }
-            if attempt > 0 {
-                warn!(
-                    "第 {} 次重试下载 {} 个失败的分片...",
-                    attempt,
-                    failed_indices.len()
-                );
-                tokio::time::sleep(Duration::from_secs(1)).await;
-            }
-            let segment_concurrency = min(
-                self.context.config.max_workers * constants::performance::M3U8_CONCURRENCY_MULTIPLIER,
-                constants::performance::M3U8_MAX_CONCURRENCY,
-            ); // 设置上限为30
-            let stream = stream::iter(failed_indices.clone())
-                .map(|i| {
-                    let url_res = base_url.join(&urls[i]);
-                    let ts_path = temp_path.join(format!("{:05}.ts", i));
-                    let client = self.context.http_client.clone();
-                    let decryptor = decryptor.clone();
-                    let pbar_clone = pbar.clone();
-
-                    tokio::spawn(async move {
-                        let url = match url_res {
-                            Ok(url) => url,
-                            Err(e) => return (i, Err(AppError::from(e))),
-                        };
-                        match Self::download_ts_segment(
-                            client,
-                            url,
-                            &ts_path,
-                            decryptor,
-                            pbar_clone,
-                            use_byte_progress,
-                        )
-                        .await
-                        {
-                            Ok(_) => (i, Ok(())),
-                            Err(e) => (i, Err(e)),
-                        }
-                    })
-                })
-                .buffer_unordered(segment_concurrency); // <--- 使用新变量
-            let results: Vec<_> = stream.collect().await;
-            failed_indices = results
-                .into_iter()
-                .filter_map(|handle_res| match handle_res {
-                    Ok((_index, Ok(_))) => None,
-                    Ok((index, Err(_))) => Some(index),
-                    Err(_) => {
-                        error!("一个下载任务 panic 或被取消");
-                        None
-                    }
-                })
-                .collect();
-        }
+        let segment_concurrency = min(
+            self.context.config.max_workers * constants::performance::M3U8_CONCURRENCY_MULTIPLIER,
+            constants::performance::M3U8_MAX_CONCURRENCY,
+        ); // 设置上限为30
+        let max_retries = self.context.config.max_retries;
+        let mut segments = stream::iter(manifest.committed..urls.len())
+            .map(|i| {
+                let url_res = base_url.join(&urls[i]);
+                let client = self.context.http_client.clone();
+                let key = keys[i].clone();
+                let pbar_clone = pbar.clone();
+                async move {
+                    let url = url_res?;
+                    Self::download_ts_segment_with_retry(
+                        client,
+                        url,
+                        key,
+                        max_retries,
+                        pbar_clone,
+                        use_byte_progress,
+                    )
+                    .await
+                }
+            })
+            .buffered(segment_concurrency);
 
-        if !failed_indices.is_empty() {
-            error!("{} 个分片最终下载失败", failed_indices.len());
-            return Err(AppError::Merge(format!(
-                "{} 个分片最终下载失败",
-                failed_indices.len()
-            )));
+        let stream_path = work_dir.join(constants::M3U8_STREAM_FILE_NAME);
+        let mut output = fs::OpenOptions::new().append(true).open(&stream_path)?;
+        while let Some(result) = segments.next().await {
+            let data = result.inspect_err(|e| {
+                error!("第 {} 个分片最终下载失败: {}", manifest.committed + 1, e);
+            })?;
+            output.write_all(&data)?;
+            // 先落盘数据再更新清单，保证清单记录的进度不会超过合并文件的实际内容
+            output.sync_data()?;
+            manifest.committed += 1;
+            manifest.committed_bytes += data.len() as u64;
+            manifest.save(work_dir)?;
         }
         Ok(())
     }
 
-    async fn download_ts_segment(
+    async fn download_ts_segment_with_retry(
         client: Arc<RobustClient>,
         url: Url,
-        ts_path: &Path,
-        decryptor: Option<Aes128CbcDec>,
+        key: Option<SegmentKey>,
+        max_retries: u32,
         pbar: ProgressBar,
         use_byte_progress: bool,
-    ) -> AppResult<()> {
-        let data = client.get(url).await?.bytes().await?;
-
-        if use_byte_progress {
-            pbar.inc(data.len() as u64);
+    ) -> AppResult<Vec<u8>> {
+        let mut attempt = 0;
+        loop {
+            match Self::download_ts_segment(&client, url.clone(), key.as_ref()).await {
+                Ok(data) => {
+                    if use_byte_progress {
+                        pbar.inc(data.len() as u64);
+                    }
+                    return Ok(data);
+                }
+                Err(e) if attempt < max_retries => {
+                    attempt += 1;
+                    warn!("分片 {} 下载失败，第 {} 次重试: {}", url, attempt, e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+                Err(e) => return Err(e),
+            }
         }
+    }
 
-        let final_data = if let Some(d) = decryptor {
-            d.decrypt_padded_vec_mut::<Pkcs7>(&data)
-                .map_err(|e| AppError::Security(format!("分片解密失败: {}", e)))?
-        } else {
-            data.to_vec()
-        };
-        fs::write(ts_path, &final_data)?;
-        Ok(())
+    async fn download_ts_segment(
+        client: &RobustClient,
+        url: Url,
+        key: Option<&SegmentKey>,
+    ) -> AppResult<Vec<u8>> {
+        let data = client.get(url).await?.bytes().await?;
+        match key {
+            Some(key) => key
+                .decryptor()?
+                .decrypt_padded_vec_mut::<Pkcs7>(&data)
+                .map_err(|e| AppError::Security(format!("分片解密失败: {}", e))),
+            None => Ok(data.to_vec()),
+        }
     }
 
-    async fn get_m3u8_key_and_playlist(
+    /// 获取媒体播放列表，并确定每个分片的解密参数。
+    /// `EXT-X-KEY` 对其后的所有分片生效，直到出现下一个 `EXT-X-KEY` (密钥轮换)；
+    /// 未指定 IV 时按 HLS 规范使用分片的媒体序列号作为 IV。每个不同的密钥只获取一次。
+    async fn get_m3u8_keys_and_playlist(
         &self,
         m3u8_url: Url,
-    ) -> AppResult<(Option<Vec<u8>>, Option<String>, m3u8_rs::MediaPlaylist)> {
-        // 步骤 1: 获取并解析播放列表
-        let media_playlist = self.fetch_and_parse_playlist(&m3u8_url).await?;
-
-        // 步骤 2: 检查是否加密，并提取加密信息
-        let Some((key_uri, iv)) = media_playlist.segments.iter().find_map(|seg| {
-            seg.key.as_ref().and_then(|k| {
-                if let m3u8_rs::Key {
-                    uri: Some(uri), iv, ..
-                } = k
-                {
-                    Some((uri.clone(), iv.clone()))
-                } else {
-                    None
+    ) -> AppResult<(Url, m3u8_rs::MediaPlaylist, Vec<Option<SegmentKey>>)> {
+        // 获取并解析播放列表 (主播放列表会被解析为所选码流的媒体播放列表)
+        let (m3u8_url, media_playlist) = self.fetch_and_parse_playlist(&m3u8_url).await?;
+
+        let mut key_cache: HashMap<String, Arc<Vec<u8>>> = HashMap::new();
+        let mut current_key: Option<&m3u8_rs::Key> = None;
+        let mut segment_keys = Vec::with_capacity(media_playlist.segments.len());
+        for (i, segment) in media_playlist.segments.iter().enumerate() {
+            // m3u8-rs 只把 EXT-X-KEY 记录在紧随其后的分片上，需要自行向后延续
+            if let Some(key) = &segment.key {
+                current_key = Some(key);
+            }
+            let segment_key = match current_key {
+                None
+                | Some(m3u8_rs::Key {
+                    method: m3u8_rs::KeyMethod::None,
+                    ..
+                }) => None,
+                Some(m3u8_rs::Key {
+                    method: m3u8_rs::KeyMethod::AES128,
+                    uri: Some(uri),
+                    iv,
+                    ..
+                }) => {
+                    let key = match key_cache.get(uri) {
+                        Some(key) => key.clone(),
+                        None => {
+                            let key = Arc::new(self.fetch_and_decrypt_key(&m3u8_url, uri).await?);
+                            key_cache.insert(uri.clone(), key.clone());
+                            key
+                        }
+                    };
+                    let iv = match iv {
+                        Some(iv_hex) => parse_iv(iv_hex)?,
+                        None => sequence_iv(media_playlist.media_sequence + i as u64),
+                    };
+                    Some(SegmentKey { key, iv })
                 }
-            })
-        }) else {
-            // 如果没有找到加密信息，直接返回
+                Some(m3u8_rs::Key {
+                    method: m3u8_rs::KeyMethod::AES128,
+                    uri: None,
+                    ..
+                }) => {
+                    return Err(AppError::M3u8Parse(
+                        "EXT-X-KEY 使用 AES-128 加密但缺少 URI".to_string(),
+                    ));
+                }
+                Some(key) => {
+                    return Err(AppError::M3u8Parse(format!(
+                        "不支持的 M3U8 加密方式: {}",
+                        key.method
+                    )));
+                }
+            };
+            segment_keys.push(segment_key);
+        }
+
+        if key_cache.is_empty() {
             debug!("M3U8 未加密");
-            return Ok((None, None, media_playlist));
-        };
+        } else {
+            debug!("M3U8 共使用 {} 个不同的密钥", key_cache.len());
+        }
+        Ok((m3u8_url, media_playlist, segment_keys))
+    }
+}
 
-        // 步骤 3: 如果已加密，获取并解密密钥
-        let decrypted_key = self.fetch_and_decrypt_key(&m3u8_url, &key_uri).await?;
+/// 按清晰度参数从主播放列表中选择码流 (忽略 I 帧码流)。
+/// 'best'/'worst' 取分辨率 (其次码率) 最高/最低的码流；指定数值时选择高度不超过该值的最高码流，
+/// 没有满足条件的码流时退回最低的一个。
+fn select_variant<'a>(
+    variants: &'a [m3u8_rs::VariantStream],
+    quality: &str,
+) -> Option<&'a m3u8_rs::VariantStream> {
+    let height = |v: &m3u8_rs::VariantStream| v.resolution.as_ref().map_or(0, |r| r.height);
+    let candidates: Vec<&m3u8_rs::VariantStream> = variants
+        .iter()
+        .filter(|v| !v.is_i_frame)
+        .sorted_by_key(|v| (height(v), v.bandwidth))
+        .collect();
+    match quality.to_lowercase().as_str() {
+        "worst" => candidates.first().copied(),
+        q => match q.parse::<u64>() {
+            Ok(target) => candidates
+                .iter()
+                .rev()
+                .find(|v| v.resolution.is_some() && height(v) <= target)
+                .or(candidates.first())
+                .copied(),
+            Err(_) => candidates.last().copied(),
+        },
+    }
+}
 
-        // 步骤 4: 返回所有结果
-        Ok((Some(decrypted_key), iv, media_playlist))
+/// 解析 EXT-X-KEY 中显式给出的 IV (形如 0x0123...，共 16 字节)
+fn parse_iv(iv_hex: &str) -> AppResult<[u8; 16]> {
+    let hex_digits = iv_hex
+        .strip_prefix("0x")
+        .or_else(|| iv_hex.strip_prefix("0X"))
+        .unwrap_or(iv_hex);
+    let bytes = hex::decode(hex_digits)
+        .map_err(|e| AppError::M3u8Parse(format!("无效的IV十六进制值: {}", e)))?;
+    bytes
+        .try_into()
+        .map_err(|_| AppError::M3u8Parse(format!("IV 长度不是 16 字节: {}", iv_hex)))
+}
+
+/// 未指定 IV 时，以分片的媒体序列号 (大端序，左侧补零到 16 字节) 作为 IV
+fn sequence_iv(sequence: u64) -> [u8; 16] {
+    let mut iv = [0u8; 16];
+    iv[8..].copy_from_slice(&sequence.to_be_bytes());
+    iv
+}
+
+/// 准备分片工作目录，返回本次下载应使用的清单。
+/// 允许续传且清单与当前播放列表一致时沿用已合并的进度 (并将合并文件截断到记录的长度)，
+/// 否则清空工作目录从头开始。
+fn prepare_work_dir(
+    work_dir: &Path,
+    manifest: SegmentManifest,
+    resume: bool,
+) -> AppResult<SegmentManifest> {
+    let stream_path = work_dir.join(constants::M3U8_STREAM_FILE_NAME);
+    let previous = fs::read(work_dir.join(constants::M3U8_MANIFEST_FILE_NAME))
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<SegmentManifest>(&bytes).ok())
+        .filter(|previous| resume && previous.matches(&manifest))
+        .filter(|previous| {
+            fs::metadata(&stream_path).is_ok_and(|m| m.len() >= previous.committed_bytes)
+        });
+
+    let manifest = match previous {
+        Some(previous) => {
+            let stream = fs::OpenOptions::new().write(true).open(&stream_path)?;
+            stream.set_len(previous.committed_bytes)?;
+            previous
+        }
+        None => {
+            if work_dir.exists() {
+                debug!("丢弃分片工作目录中的旧数据: {:?}", work_dir);
+                fs::remove_dir_all(work_dir)?;
+            }
+            fs::create_dir_all(work_dir)?;
+            File::create(&stream_path)?;
+            manifest
+        }
+    };
+    manifest.save(work_dir)?;
+    Ok(manifest)
+}
+
+/// 把合并完成的 TS 流移动到最终路径；输出格式为 MP4 时 (--video-container mp4) 先转封装。
+/// 转封装期间 TS 和 MP4 同时占用磁盘 (约两倍视频大小)，MP4 落盘后立即删除 TS。
+fn finalize_stream(stream_path: &Path, output_path: &Path, remux_to_mp4: bool) -> AppResult<()> {
+    if !remux_to_mp4 {
+        // 工作目录与输出文件位于同一目录下，重命名即可，无需复制数据
+        fs::rename(stream_path, output_path)?;
+        return Ok(());
+    }
+
+    // 在最终输出目录的同级目录下创建一个具名临时文件
+    let output_parent = output_path.parent().unwrap_or_else(|| Path::new("."));
+    let mut temp_file = NamedTempFile::new_in(output_parent)?;
+    {
+        let mut remuxer = TsToMp4Remuxer::new(temp_file.as_file_mut()); // 借用只在这个块内有效
+        let mut reader = File::open(stream_path)?;
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            let n = reader.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            remuxer.push(&buffer[..n])?;
+        }
+        remuxer.finish()?;
     }
+    temp_file.persist(output_path)?;
+    fs::remove_file(stream_path)?;
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{cli::Cli, config::AppConfig};
     use aes::cipher::{BlockDecryptMut, block_padding::Pkcs7};
+    use clap::Parser;
+
+    fn create_test_downloader(args: &[&str]) -> M3u8Downloader {
+        M3u8Downloader::new(DownloadJobContext {
+            token: Arc::default(),
+            ..DownloadJobContext::for_test(Cli::parse_from(args), AppConfig::default())
+        })
+    }
+
+    const MASTER_PLAYLIST: &str = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360
+360/index.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=5000000,RESOLUTION=1920x1080
+1080/index.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2500000,RESOLUTION=1280x720
+720/index.m3u8
+#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=9000000,RESOLUTION=1920x1080,URI=\"iframe.m3u8\"
+";
 
     #[test]
     fn test_aes_cbc_decryption_logic() {
@@ -360,7 +618,7 @@ mod tests {
         // 将十六进制字符串转换为字节数组
         let key = hex::decode(hex_key).unwrap();
         let iv = hex::decode(hex_iv).unwrap();
-        let mut encrypted_data = hex::decode(hex_encrypted_data).unwrap();
+        let encrypted_data = hex::decode(hex_encrypted_data).unwrap();
         let expected_decrypted_data = hex::decode(hex_expected_decrypted_data).unwrap();
 
         // --- Act ---
@@ -369,7 +627,7 @@ mod tests {
 
         // 执行解密
         let decrypted_data = cipher
-            .decrypt_padded_vec_mut::<Pkcs7>(&mut encrypted_data)
+            .decrypt_padded_vec_mut::<Pkcs7>(&encrypted_data)
             .unwrap();
 
         // --- Assert ---
@@ -378,4 +636,256 @@ mod tests {
             "解密后的数据与预期不符"
         );
     }
+
+    fn manifest(segments: &[&str]) -> SegmentManifest {
+        SegmentManifest::new(
+            "https://example.com/video.m3u8".to_string(),
+            &segments.iter().map(|s| s.to_string()).collect::<Vec<_>>(),
+        )
+    }
+
+    /// 模拟上次运行时已向合并文件写入 `committed` 个分片 (共 `committed_bytes` 字节)
+    fn record_progress(work_dir: &Path, committed: usize, committed_bytes: u64) {
+        let mut previous: SegmentManifest = serde_json::from_slice(
+            &fs::read(work_dir.join(constants::M3U8_MANIFEST_FILE_NAME)).unwrap(),
+        )
+        .unwrap();
+        previous.committed = committed;
+        previous.committed_bytes = committed_bytes;
+        previous.save(work_dir).unwrap();
+    }
+
+    #[test]
+    fn test_prepare_work_dir_resumes_only_matching_playlist() {
+        let temp = tempfile::tempdir().unwrap();
+        let work_dir = temp.path().join("parts");
+        let stream_path = work_dir.join(constants::M3U8_STREAM_FILE_NAME);
+        let current = || manifest(&["a.ts", "b.ts", "c.ts"]);
+
+        assert_eq!(prepare_work_dir(&work_dir, current(), true).unwrap().committed, 0);
+
+        // 已提交的分片被复用，提交之后写了一半的数据会被截掉
+        fs::write(&stream_path, b"seg0half").unwrap();
+        record_progress(&work_dir, 1, 4);
+        let resumed = prepare_work_dir(&work_dir, current(), true).unwrap();
+        assert_eq!((resumed.committed, resumed.committed_bytes), (1, 4));
+        assert_eq!(fs::read(&stream_path).unwrap(), b"seg0");
+
+        // 合并文件比清单记录的短 (被外部改动) 时不可信，从头开始
+        fs::write(&stream_path, b"se").unwrap();
+        assert_eq!(prepare_work_dir(&work_dir, current(), true).unwrap().committed, 0);
+
+        // 播放列表发生变化时丢弃旧数据
+        fs::write(&stream_path, b"seg0").unwrap();
+        record_progress(&work_dir, 1, 4);
+        let changed = || manifest(&["a.ts", "b2.ts", "c.ts"]);
+        assert_eq!(prepare_work_dir(&work_dir, changed(), true).unwrap().committed, 0);
+        assert_eq!(fs::read(&stream_path).unwrap(), b"");
+
+        // 不续传 (如强制重新下载) 时同样从头开始
+        fs::write(&stream_path, b"seg0").unwrap();
+        record_progress(&work_dir, 1, 4);
+        assert_eq!(prepare_work_dir(&work_dir, changed(), false).unwrap().committed, 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_download_fetches_only_missing_segments() {
+        let mut server = mockito::Server::new_async().await;
+        let _playlist = server
+            .mock("GET", "/video.m3u8")
+            .match_query(mockito::Matcher::Any)
+            .with_body("#EXTM3U\n#EXT-X-TARGETDURATION:10\n#EXTINF:10,\nseg0.ts\n#EXTINF:10,\nseg1.ts\n#EXT-X-ENDLIST\n")
+            .create_async()
+            .await;
+        let seg0 = server.mock("GET", "/seg0.ts").expect(0).create_async().await;
+        let seg1 = server
+            .mock("GET", "/seg1.ts")
+            .with_body("BBB")
+            .expect(1)
+            .create_async()
+            .await;
+
+        let temp = tempfile::tempdir().unwrap();
+        let item = FileInfo {
+            filepath: temp.path().join("lesson.ts"),
+            url: format!("{}/video.m3u8", server.url()),
+            category: crate::models::ResourceCategory::Video,
+            ..Default::default()
+        };
+        // 模拟上次运行在下载完第一个分片后被中断
+        let work_dir = M3u8Downloader::work_dir_for(&item);
+        let previous =
+            SegmentManifest::new(item.url.clone(), &["seg0.ts".to_string(), "seg1.ts".to_string()]);
+        prepare_work_dir(&work_dir, previous, true).unwrap();
+        fs::write(work_dir.join(constants::M3U8_STREAM_FILE_NAME), b"AAA").unwrap();
+        record_progress(&work_dir, 1, 3);
+        assert!(M3u8Downloader::has_partial_segments(&item));
+
+        let downloader = create_test_downloader(&["sed-dl", "--url", "https://example.com"]);
+        let status = downloader
+            .download(&item, ProgressBar::hidden(), false, true)
+            .await
+            .unwrap();
+
+        assert_eq!(status, DownloadStatus::Resumed);
+        assert_eq!(fs::read(&item.filepath).unwrap(), b"AAABBB");
+        assert!(!work_dir.exists(), "合并成功后应清理工作目录");
+        seg0.assert_async().await;
+        seg1.assert_async().await;
+    }
+
+    #[test]
+    fn test_select_variant_by_quality() {
+        let m3u8_rs::Playlist::MasterPlaylist(master) =
+            m3u8_rs::parse_playlist_res(MASTER_PLAYLIST.as_bytes()).unwrap()
+        else {
+            panic!("应解析为主播放列表");
+        };
+        let pick = |quality: &str| select_variant(&master.variants, quality).unwrap().uri.as_str();
+
+        assert_eq!(pick("best"), "1080/index.m3u8");
+        assert_eq!(pick("worst"), "360/index.m3u8");
+        assert_eq!(pick("720"), "720/index.m3u8");
+        // 没有完全匹配时选择不超过目标高度的最高码流，都超过时退回最低码流
+        assert_eq!(pick("900"), "720/index.m3u8");
+        assert_eq!(pick("240"), "360/index.m3u8");
+        assert!(select_variant(&[], "best").is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_download_resolves_master_playlist_variant() {
+        let mut server = mockito::Server::new_async().await;
+        let _master = server
+            .mock("GET", "/video.m3u8")
+            .match_query(mockito::Matcher::Any)
+            .with_body(MASTER_PLAYLIST)
+            .create_async()
+            .await;
+        let _variant = server
+            .mock("GET", "/720/index.m3u8")
+            .match_query(mockito::Matcher::Any)
+            .with_body("#EXTM3U\n#EXT-X-TARGETDURATION:10\n#EXTINF:10,\nseg0.ts\n#EXT-X-ENDLIST\n")
+            .expect(1)
+            .create_async()
+            .await;
+        // 分片的相对路径应以所选码流的播放列表为基准
+        let _segment = server
+            .mock("GET", "/720/seg0.ts")
+            .with_body("720P")
+            .create_async()
+            .await;
+
+        let temp = tempfile::tempdir().unwrap();
+        let item = FileInfo {
+            filepath: temp.path().join("lesson.ts"),
+            url: format!("{}/video.m3u8", server.url()),
+            category: crate::models::ResourceCategory::Video,
+            ..Default::default()
+        };
+        let downloader =
+            create_test_downloader(&["sed-dl", "--url", "https://example.com", "-q", "720"]);
+        let status = downloader
+            .download(&item, ProgressBar::hidden(), false, false)
+            .await
+            .unwrap();
+
+        assert_eq!(status, DownloadStatus::Success);
+        assert_eq!(fs::read(&item.filepath).unwrap(), b"720P");
+    }
+
+    #[test]
+    fn test_segment_iv_derivation() {
+        assert_eq!(
+            parse_iv("0x000102030405060708090A0B0C0D0E0F").unwrap(),
+            core::array::from_fn::<u8, 16, _>(|i| i as u8)
+        );
+        assert!(parse_iv("0x0102").is_err());
+        let iv = sequence_iv(0x0102);
+        assert_eq!(iv[..14], [0u8; 14]);
+        assert_eq!(iv[14..], [0x01, 0x02]);
+    }
+
+    /// 按平台密钥服务的协议 (nonce + sign) 模拟一个密钥，返回 (signs 响应, key 响应)
+    fn mock_key_responses(key: &[u8; 16], nonce: &str, key_filename: &str) -> (String, String) {
+        use aes::cipher::BlockEncryptMut;
+        let sign = format!("{:x}", Md5::digest(format!("{}{}", nonce, key_filename)))[..16].to_string();
+        let encrypted = ecb::Encryptor::<aes::Aes128>::new(sign.as_bytes().into())
+            .encrypt_padded_vec_mut::<Pkcs7>(key);
+        (
+            format!(r#"{{"nonce":"{}"}}"#, nonce),
+            format!(r#"{{"key":"{}"}}"#, BASE64.encode(encrypted)),
+        )
+    }
+
+    fn encrypt_segment(key: &[u8; 16], iv: &[u8; 16], data: &[u8]) -> Vec<u8> {
+        use aes::cipher::BlockEncryptMut;
+        cbc::Encryptor::<aes::Aes128>::new(key.into(), iv.into()).encrypt_padded_vec_mut::<Pkcs7>(data)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_download_handles_key_rotation_and_sequence_ivs() {
+        let mut server = mockito::Server::new_async().await;
+        let _playlist = server
+            .mock("GET", "/video.m3u8")
+            .match_query(mockito::Matcher::Any)
+            .with_body(
+                "#EXTM3U\n#EXT-X-TARGETDURATION:10\n#EXT-X-MEDIA-SEQUENCE:7\n\
+                 #EXT-X-KEY:METHOD=AES-128,URI=\"keys/k1\"\n#EXTINF:10,\nseg0.ts\n#EXTINF:10,\nseg1.ts\n\
+                 #EXT-X-KEY:METHOD=AES-128,URI=\"keys/k2\",IV=0x000102030405060708090a0b0c0d0e0f\n\
+                 #EXTINF:10,\nseg2.ts\n#EXT-X-ENDLIST\n",
+            )
+            .create_async()
+            .await;
+
+        let (k1, k2) = ([0x11u8; 16], [0x22u8; 16]);
+        let mut key_mocks = Vec::new();
+        for (key, name) in [(&k1, "k1"), (&k2, "k2")] {
+            let (signs, key_body) = mock_key_responses(key, "nonce", name);
+            // 同一个密钥被多个分片使用时也只应请求一次
+            key_mocks.push(
+                server
+                    .mock("GET", format!("/keys/{}/signs", name).as_str())
+                    .with_body(signs)
+                    .expect(1)
+                    .create_async()
+                    .await,
+            );
+            key_mocks.push(
+                server
+                    .mock("GET", format!("/keys/{}", name).as_str())
+                    .match_query(mockito::Matcher::Any)
+                    .with_body(key_body)
+                    .expect(1)
+                    .create_async()
+                    .await,
+            );
+        }
+        let explicit_iv = core::array::from_fn::<u8, 16, _>(|i| i as u8);
+        let segments = [
+            (encrypt_segment(&k1, &sequence_iv(7), b"first"), "/seg0.ts"),
+            (encrypt_segment(&k1, &sequence_iv(8), b"second"), "/seg1.ts"),
+            (encrypt_segment(&k2, &explicit_iv, b"third"), "/seg2.ts"),
+        ];
+        for (body, path) in &segments {
+            server.mock("GET", *path).with_body(body).create_async().await;
+        }
+
+        let temp = tempfile::tempdir().unwrap();
+        let item = FileInfo {
+            filepath: temp.path().join("lesson.ts"),
+            url: format!("{}/video.m3u8", server.url()),
+            category: crate::models::ResourceCategory::Video,
+            ..Default::default()
+        };
+        let downloader = create_test_downloader(&["sed-dl", "--url", "https://example.com"]);
+        downloader
+            .download(&item, ProgressBar::hidden(), false, false)
+            .await
+            .unwrap();
+
+        assert_eq!(fs::read(&item.filepath).unwrap(), b"firstsecondthird");
+        for mock in key_mocks {
+            mock.assert_async().await;
+        }
+    }
 }
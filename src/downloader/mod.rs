@@ -5,23 +5,33 @@ mod auth;
 mod dispatcher;
 mod job;
 mod m3u8;
+mod remux;
 pub mod negotiator;
+mod sidecar;
+mod state;
+mod subtitle;
 mod task_processor;
 mod task_runner;
 
 // 2. 从子模块中导出公共接口
+pub(crate) use auth::{find_probe_url, probe_token};
 pub use job::ResourceDownloader;
+pub use state::{DownloadRecord, DownloadStateStore};
 
 // 3. 将 DownloadManager 的逻辑移到这里，因为它是一个核心的、共享的状态管理器
-use crate::ui;
+use crate::{
+    models::{OutputEvent, ReportEntry, ReportStats},
+    ui,
+};
 use colored::*;
 use log::info;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
 
-#[derive(Clone, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct DownloadStats {
     pub total: usize,
     pub success: usize,
@@ -29,11 +39,24 @@ pub struct DownloadStats {
     pub failed: usize,
 }
 
+impl From<&DownloadStats> for ReportStats {
+    fn from(stats: &DownloadStats) -> Self {
+        Self {
+            total: stats.total,
+            success: stats.success,
+            skipped: stats.skipped,
+            failed: stats.failed,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct DownloadManager {
     stats: Arc<Mutex<DownloadStats>>,
     failed_downloads: Arc<Mutex<Vec<(String, String)>>>,
     skipped_downloads: Arc<Mutex<Vec<(String, String)>>>,
+    /// 本批次中下载成功或校验通过而跳过的文件
+    completed_files: Arc<Mutex<HashSet<PathBuf>>>,
 }
 
 impl Default for DownloadManager {
@@ -48,6 +71,7 @@ impl DownloadManager {
             stats: Arc::new(Mutex::new(DownloadStats::default())),
             failed_downloads: Arc::new(Mutex::new(Vec::new())),
             skipped_downloads: Arc::new(Mutex::new(Vec::new())),
+            completed_files: Arc::new(Mutex::new(HashSet::new())),
         }
     }
 
@@ -60,15 +84,18 @@ impl DownloadManager {
         };
         self.failed_downloads.lock().unwrap().clear();
         self.skipped_downloads.lock().unwrap().clear();
+        self.completed_files.lock().unwrap().clear();
     }
 
-    pub fn record_success(&self) {
+    pub fn record_success(&self, filepath: &Path) {
         self.stats.lock().unwrap().success += 1;
+        self.completed_files.lock().unwrap().insert(filepath.to_path_buf());
     }
 
-    pub fn record_skip(&self, filename: &str, reason: &str) {
+    pub fn record_skip(&self, filepath: &Path, filename: &str, reason: &str) {
         info!("跳过文件 '{}'，原因: {}", filename, reason);
         self.stats.lock().unwrap().skipped += 1;
+        self.completed_files.lock().unwrap().insert(filepath.to_path_buf());
         self.skipped_downloads
             .lock()
             .unwrap()
@@ -100,6 +127,11 @@ impl DownloadManager {
         self.stats.lock().unwrap().clone()
     }
 
+    /// 本批次中下载成功或已完整存在的文件
+    pub fn completed_files(&self) -> HashSet<PathBuf> {
+        self.completed_files.lock().unwrap().clone()
+    }
+
     pub fn did_all_succeed(&self) -> bool {
         self.stats.lock().unwrap().failed == 0
     }
@@ -113,6 +145,24 @@ impl DownloadManager {
             stats.total, stats.success, stats.skipped, stats.failed
         );
 
+        if ui::is_json_output() {
+            let to_entries = |items: &[(String, String)]| {
+                items
+                    .iter()
+                    .map(|(filename, reason)| ReportEntry {
+                        filename: filename.clone(),
+                        reason: reason.clone(),
+                    })
+                    .collect()
+            };
+            ui::emit_event(&OutputEvent::Summary {
+                stats: ReportStats::from(&stats),
+                skipped_files: to_entries(&skipped),
+                failed_files: to_entries(&failed),
+            });
+            return;
+        }
+
         if !skipped.is_empty() || !failed.is_empty() {
             ui::print_sub_header("下载详情报告");
             if !skipped.is_empty() {
@@ -4,17 +4,68 @@ use crate::{
     DownloadJobContext,
     error::AppResult,
     models::{FileInfo, ResourceCategory},
-    ui,
+    ui, utils,
 };
 use itertools::Itertools;
 use log::{debug, info, warn};
-use regex::Regex;
 use std::{
     collections::{BTreeSet, HashMap},
-    sync::LazyLock,
+    path::PathBuf,
 };
 
-static VIDEO_QUALITY_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r" \[(\d{3,4})\]").unwrap());
+/// 同一内容不同版本的分组键；没有分组键的文件自成一组
+fn variant_key(file: &FileInfo) -> String {
+    file.variant_group
+        .clone()
+        .unwrap_or_else(|| file.filepath.to_string_lossy().to_string())
+}
+
+/// 文件格式 (小写)，用于音频格式的协商
+fn format_key(file: &FileInfo) -> Option<String> {
+    file.format.as_deref().map(str::to_lowercase)
+}
+
+/// 协商出最终的视频流后，将同一视频的字幕改名为与所选视频同名 (`视频名[.语言].扩展名`)，
+/// 以便播放器自动加载。同一视频选中了多种清晰度时以最高清晰度为准；没有对应视频的字幕保持原名。
+pub fn attach_subtitles_to_videos(items: &mut [FileInfo]) {
+    let mut video_paths: HashMap<String, (Option<u32>, PathBuf)> = HashMap::new();
+    for video in items.iter().filter(|f| f.category == ResourceCategory::Video) {
+        let Some(group) = &video.variant_group else { continue };
+        let entry = video_paths
+            .entry(group.clone())
+            .or_insert_with(|| (video.quality, video.filepath.clone()));
+        if video.quality > entry.0 {
+            *entry = (video.quality, video.filepath.clone());
+        }
+    }
+
+    let subtitle_groups = items
+        .iter()
+        .enumerate()
+        .filter(|(_, f)| f.category == ResourceCategory::Subtitle)
+        .filter_map(|(i, f)| Some((f.variant_group.clone()?, i)))
+        .into_group_map();
+    for (group, indices) in subtitle_groups {
+        let Some((_, video_path)) = video_paths.get(&group) else {
+            continue;
+        };
+        let Some(stem) = video_path.file_stem().map(|s| s.to_string_lossy().to_string()) else {
+            continue;
+        };
+        let languages: Vec<Option<String>> =
+            indices.iter().map(|&i| items[i].language.clone()).collect();
+        for (i, suffix) in indices.into_iter().zip(utils::subtitle_suffixes(&languages)) {
+            let subtitle = &mut items[i];
+            let extension = subtitle.format.clone().unwrap_or_else(|| "srt".to_string());
+            let filename = match suffix {
+                Some(suffix) => format!("{}.{}.{}", stem, suffix, extension),
+                None => format!("{}.{}", stem, extension),
+            };
+            debug!("字幕 {:?} 改名为与视频同名: {}", subtitle.filepath, filename);
+            subtitle.filepath = video_path.with_file_name(filename);
+        }
+    }
+}
 
 pub struct ItemNegotiator<'a> {
     context: &'a DownloadJobContext,
@@ -27,13 +78,7 @@ impl<'a> ItemNegotiator<'a> {
 
     /// 按视频质量对 FileInfo 列表进行降序排序
     fn sort_videos_by_quality_desc(&self, streams: &mut [FileInfo]) {
-        streams.sort_by_key(|f| {
-            VIDEO_QUALITY_RE
-                .captures(&f.filepath.to_string_lossy())
-                .and_then(|c| c.get(1))
-                .and_then(|m| m.as_str().parse::<u32>().ok())
-                .unwrap_or(0)
-        });
+        streams.sort_by_key(|f| f.quality.unwrap_or(0));
         streams.reverse();
     }
 
@@ -53,41 +98,23 @@ impl<'a> ItemNegotiator<'a> {
 
         let video_groups: Vec<Vec<FileInfo>> = video_items
             .into_iter()
-            .sorted_by_key(|f| {
-                VIDEO_QUALITY_RE
-                    .replace(&f.filepath.to_string_lossy(), "")
-                    .trim()
-                    .to_string()
-            })
-            .chunk_by(|f| {
-                VIDEO_QUALITY_RE
-                    .replace(&f.filepath.to_string_lossy(), "")
-                    .trim()
-                    .to_string()
-            })
+            .sorted_by_key(variant_key)
+            .chunk_by(variant_key)
             .into_iter()
             .map(|(_, group)| group.collect())
             .collect();
 
-        let mut sorted_qualities: Vec<_> = video_groups
+        // BTreeSet 已按数值升序排列，反转后即为从高到低的降序
+        let sorted_qualities: Vec<String> = video_groups
             .iter()
             .flatten()
-            .filter_map(|f| {
-                VIDEO_QUALITY_RE
-                    .captures(&f.filepath.to_string_lossy())
-                    .and_then(|c| c.get(1))
-                    .map(|m| m.as_str().to_string())
-            })
+            .filter_map(|f| f.quality)
             .collect::<BTreeSet<_>>()
             .into_iter()
+            .rev()
+            .map(|q| q.to_string())
             .collect();
 
-        // 直接对清晰度字符串列表进行排序。
-        // `sort_by_key` 会将字符串解析为数字进行比较，实现数值排序。
-        // `reverse()` 将排序结果反转，实现从高到低的降序排列。
-        sorted_qualities.sort_by_key(|q_str| q_str.parse::<u32>().unwrap_or(0));
-        sorted_qualities.reverse();
-
         if sorted_qualities.len() <= 1 {
             return Ok(video_groups.into_iter().flatten().collect());
         }
@@ -124,7 +151,7 @@ impl<'a> ItemNegotiator<'a> {
             return Ok(final_items);
         }
 
-        let selected_quality = &self.context.args.video_quality;
+        let selected_quality = &self.context.args.selection.video_quality;
         info!("根据参数选择视频清晰度: {}", selected_quality);
 
         let quality_is_valid = ["best", "worst"]
@@ -143,18 +170,8 @@ impl<'a> ItemNegotiator<'a> {
         let original_video_count = video_items.len();
         let selected_videos = video_items
             .into_iter()
-            .sorted_by_key(|f| {
-                VIDEO_QUALITY_RE
-                    .replace(&f.filepath.to_string_lossy(), "")
-                    .trim()
-                    .to_string()
-            })
-            .chunk_by(|f| {
-                VIDEO_QUALITY_RE
-                    .replace(&f.filepath.to_string_lossy(), "")
-                    .trim()
-                    .to_string()
-            })
+            .sorted_by_key(variant_key)
+            .chunk_by(variant_key)
             .into_iter()
             .filter_map(|(_, group)| {
                 let mut streams: Vec<FileInfo> = group.collect();
@@ -188,15 +205,10 @@ impl<'a> ItemNegotiator<'a> {
         match quality.to_lowercase().as_str() {
             "best" => streams.first(),
             "worst" => streams.last(),
-            q => q.parse::<u32>().ok().and_then(|target_num| {
-                streams.iter().find(|f| {
-                    VIDEO_QUALITY_RE
-                        .captures(&f.filepath.to_string_lossy())
-                        .and_then(|caps| caps.get(1))
-                        .and_then(|m| m.as_str().parse::<u32>().ok())
-                        == Some(target_num)
-                })
-            }),
+            q => q
+                .parse::<u32>()
+                .ok()
+                .and_then(|target_num| streams.iter().find(|f| f.quality == Some(target_num))),
         }
     }
 
@@ -213,22 +225,12 @@ impl<'a> ItemNegotiator<'a> {
 
         let audio_groups: HashMap<String, Vec<FileInfo>> = audio_items
             .into_iter()
-            .map(|f| {
-                (
-                    f.filepath.with_extension("").to_string_lossy().to_string(),
-                    f,
-                )
-            })
+            .map(|f| (variant_key(&f), f))
             .into_group_map();
         let sorted_formats: Vec<_> = audio_groups
             .values()
             .flatten()
-            .filter_map(|f| {
-                f.filepath
-                    .extension()
-                    .and_then(|e| e.to_str())
-                    .map(|s| s.to_uppercase())
-            })
+            .filter_map(|f| format_key(f).map(|s| s.to_uppercase()))
             .collect::<BTreeSet<_>>()
             .into_iter()
             .collect();
@@ -245,9 +247,7 @@ impl<'a> ItemNegotiator<'a> {
 
         for (_, group) in audio_groups {
             for file in group {
-                if let Some(ext) = file.filepath.extension().and_then(|e| e.to_str())
-                    && lower_choices.contains(&ext.to_lowercase())
-                {
+                if format_key(&file).is_some_and(|format| lower_choices.contains(&format)) {
                     final_items.push(file);
                 }
             }
@@ -263,16 +263,15 @@ impl<'a> ItemNegotiator<'a> {
             return Ok(final_items);
         }
 
-        let selected_format = self.context.args.audio_format.to_lowercase();
+        let selected_format = self.context.args.selection.audio_format.to_lowercase();
         info!("根据参数选择音频格式: {}", selected_format);
 
         // --- 使用 extend 和 filter ---
-        final_items.extend(audio_items.into_iter().filter(|f| {
-            f.filepath
-                .extension()
-                .and_then(|e| e.to_str())
-                .is_some_and(|ext| ext.to_lowercase() == selected_format)
-        }));
+        final_items.extend(
+            audio_items
+                .into_iter()
+                .filter(|f| format_key(f).is_some_and(|format| format == selected_format)),
+        );
 
         Ok(final_items)
     }
@@ -282,61 +281,38 @@ impl<'a> ItemNegotiator<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{cli::Cli, downloader::DownloadManager, DownloadJobContext};
+    use crate::{cli::Cli, DownloadJobContext};
     use clap::Parser;
-    use std::{
-        path::PathBuf,
-        sync::{atomic::AtomicBool, Arc},
-    };
-    use tokio::sync::Mutex as TokioMutex;
+    use std::path::PathBuf;
 
     // --- 辅助函数：创建一个用于测试的上下文 ---
     fn create_test_context(args_str: &'static str) -> DownloadJobContext {
-        let args = Arc::new(Cli::parse_from(args_str.split_whitespace()));
-        let config = Arc::new(crate::config::AppConfig::default());
-
-        DownloadJobContext {
-            manager: DownloadManager::new(),
-            token: Arc::new(TokioMutex::new("fake-token".to_string())),
-            config,
-            http_client: Arc::new(
-                crate::client::RobustClient::new(Arc::new(crate::config::AppConfig::default()))
-                    .unwrap(),
-            ),
-            args,
-            non_interactive: true,
-            cancellation_token: Arc::new(AtomicBool::new(false)),
-        }
+        DownloadJobContext::for_test(
+            Cli::parse_from(args_str.split_whitespace()),
+            crate::config::AppConfig::default(),
+        )
     }
 
     // --- 辅助函数：创建一些模拟的视频文件信息 ---
+    fn video(name: &str, group: &str, quality: Option<u32>, url: &str) -> FileInfo {
+        FileInfo {
+            filepath: PathBuf::from(name),
+            url: url.to_string(),
+            category: ResourceCategory::Video,
+            quality,
+            format: Some("ts".to_string()),
+            variant_group: Some(group.to_string()),
+            ..Default::default()
+        }
+    }
+
     fn create_sample_videos() -> Vec<FileInfo> {
         vec![
-            FileInfo {
-                filepath: PathBuf::from("video_a [1080].ts"),
-                url: "url_1080".to_string(),
-                category: ResourceCategory::Video,
-                ..Default::default()
-            },
-            FileInfo {
-                filepath: PathBuf::from("video_a [720].ts"),
-                url: "url_720".to_string(),
-                category: ResourceCategory::Video,
-                ..Default::default()
-            },
-            FileInfo {
-                filepath: PathBuf::from("video_a [480].ts"),
-                url: "url_480".to_string(),
-                category: ResourceCategory::Video,
-                ..Default::default()
-            },
+            video("video_a [1080].ts", "a", Some(1080), "url_1080"),
+            video("video_a [720].ts", "a", Some(720), "url_720"),
+            video("video_a [480].ts", "a", Some(480), "url_480"),
             // 另一个视频，测试分组
-            FileInfo {
-                filepath: PathBuf::from("video_b [720].ts"),
-                url: "url_b_720".to_string(),
-                category: ResourceCategory::Video,
-                ..Default::default()
-            },
+            video("video_b [720].ts", "b", Some(720), "url_b_720"),
         ]
     }
 
@@ -394,17 +370,86 @@ mod tests {
         assert!(result.is_empty());
     }
 
+    #[test]
+    fn test_filter_videos_ignores_filename_layout() {
+        // 清晰度和分组只取自结构化字段，与文件名的格式无关
+        let context = create_test_context("sed-dl --url a --video-quality 720");
+        let negotiator = ItemNegotiator::new(&context);
+        let videos = vec![
+            video("第1课/高清.ts", "lesson1", Some(1080), "url_1080"),
+            video("第1课/标清.ts", "lesson1", Some(720), "url_720"),
+            video("第2课/未知清晰度.ts", "lesson2", None, "url_unknown"),
+            video("第2课/标清.ts", "lesson2", Some(720), "url_2_720"),
+        ];
+        let result = negotiator.filter_videos_non_interactive(videos).unwrap();
+
+        let mut urls: Vec<&str> = result.iter().map(|f| f.url.as_str()).collect();
+        urls.sort();
+        assert_eq!(urls, ["url_2_720", "url_720"]);
+
+        let context = create_test_context("sed-dl --url a --video-quality worst");
+        let negotiator = ItemNegotiator::new(&context);
+        let videos = vec![
+            video("a.ts", "lesson", None, "url_unknown"),
+            video("b.ts", "lesson", Some(480), "url_480"),
+        ];
+        let result = negotiator.filter_videos_non_interactive(videos).unwrap();
+        // 清晰度未知的流排在最后
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].url, "url_unknown");
+    }
+
+    fn subtitle(name: &str, group: &str, language: Option<&str>) -> FileInfo {
+        FileInfo {
+            filepath: PathBuf::from(name),
+            category: ResourceCategory::Subtitle,
+            format: Some("srt".to_string()),
+            variant_group: Some(group.to_string()),
+            language: language.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_subtitles_renamed_after_selected_video() {
+        let mut items = vec![
+            video("课程/第1课 - 录像 [720] - [张三].ts", "a", Some(720), "url_720"),
+            video("课程/第1课 - 录像 [1080] - [张三].ts", "a", Some(1080), "url_1080"),
+            subtitle("课程/第1课 - 录像 - [张三].zh.srt", "a", Some("zh")),
+            subtitle("课程/第1课 - 录像 - [张三].en.srt", "a", Some("en")),
+            subtitle("课程/第1课 - 录像 - [张三].3.srt", "a", None),
+            // 没有对应视频的字幕保持原名
+            subtitle("课程/独立字幕.srt", "b", None),
+        ];
+        attach_subtitles_to_videos(&mut items);
+
+        let names: Vec<_> = items[2..].iter().map(|f| f.filepath.clone()).collect();
+        assert_eq!(
+            names,
+            [
+                PathBuf::from("课程/第1课 - 录像 [1080] - [张三].zh.srt"),
+                PathBuf::from("课程/第1课 - 录像 [1080] - [张三].en.srt"),
+                PathBuf::from("课程/第1课 - 录像 [1080] - [张三].srt"),
+                PathBuf::from("课程/独立字幕.srt"),
+            ]
+        );
+    }
+
     // --- 辅助函数：创建一些模拟的音频文件信息 ---
     fn create_sample_audios() -> Vec<FileInfo> {
         vec![
             FileInfo {
                 filepath: PathBuf::from("track1.mp3"),
                 category: ResourceCategory::Audio,
+                format: Some("mp3".to_string()),
+                variant_group: Some("book#1".to_string()),
                 ..Default::default()
             },
             FileInfo {
                 filepath: PathBuf::from("track1.m4a"),
                 category: ResourceCategory::Audio,
+                format: Some("m4a".to_string()),
+                variant_group: Some("book#1".to_string()),
                 ..Default::default()
             },
             FileInfo {
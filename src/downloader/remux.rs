@@ -0,0 +1,1140 @@
+// src/downloader/remux.rs
+
+use crate::error::{AppError, AppResult};
+use log::{debug, warn};
+use mp4::{
+    AacConfig, AudioObjectType, AvcConfig, BoxHeader, BoxType, Bytes, ChannelConfig, MediaConfig,
+    MoovBox, Mp4Config, Mp4Sample, Mp4Writer, ReadBox, SampleFreqIndex, TrackConfig, TrackType,
+    WriteBox,
+};
+use std::{
+    collections::VecDeque,
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+};
+
+const TS_PACKET_SIZE: usize = 188;
+const TS_SYNC_BYTE: u8 = 0x47;
+const STREAM_TYPE_AAC_ADTS: u8 = 0x0F;
+const STREAM_TYPE_H264: u8 = 0x1B;
+const STREAM_TYPE_H265: u8 = 0x24;
+/// PES 时间戳的时钟频率，也直接用作视频轨道的 timescale
+const PES_CLOCK: u32 = 90_000;
+const PTS_WRAP: i64 = 1 << 33;
+/// MP4 的影片时间刻度 (mvhd/tkhd/elst 中的时长均以此为单位)
+const MOVIE_TIMESCALE: u32 = 1000;
+const AAC_SAMPLES_PER_FRAME: u32 = 1024;
+/// 在拿到全部轨道参数 (SPS/PPS、ADTS 头) 之前最多缓存的帧数，超出后放弃等待缺失的轨道
+const MAX_PENDING_FRAMES: usize = 1024;
+/// 无法从时间戳推算时使用的视频帧时长 (25fps)
+const FALLBACK_FRAME_DURATION: u32 = PES_CLOCK / 25;
+
+fn remux_error(detail: impl std::fmt::Display) -> AppError {
+    AppError::Merge(format!("MP4 转封装失败: {}", detail))
+}
+
+enum Frame {
+    Video {
+        dts: i64,
+        pts: i64,
+        is_sync: bool,
+        data: Vec<u8>,
+    },
+    Audio {
+        /// 以音频采样率为时钟的时间戳
+        pts: i64,
+        data: Vec<u8>,
+    },
+}
+
+#[derive(Default)]
+struct VideoTrack {
+    pid: Option<u16>,
+    track_id: Option<u32>,
+    sps: Option<Vec<u8>>,
+    pps: Option<Vec<u8>>,
+    last_dts: Option<i64>,
+    /// 视频帧的时长要等到下一帧到来才能确定，因此总是延后一帧写入
+    held: Option<(i64, i64, bool, Vec<u8>)>,
+    last_duration: u32,
+    /// 已写入样本中第一帧的 DTS 和最小的 PTS，用于生成编辑列表
+    first_dts: Option<i64>,
+    min_pts: Option<i64>,
+    media_duration: u64,
+}
+
+#[derive(Default)]
+struct AudioTrack {
+    pid: Option<u16>,
+    track_id: Option<u32>,
+    config: Option<AacConfig>,
+    /// 跨 PES 边界的不完整 ADTS 帧
+    carry: Vec<u8>,
+    last_pes_pts: Option<i64>,
+    /// 按帧长推算的下一帧时间戳 (采样率时钟)
+    next_pts: Option<i64>,
+    /// 与视频一样延后一帧写入，时长取相邻两帧的时间戳之差
+    held: Option<(i64, Vec<u8>)>,
+    first_pts: Option<i64>,
+    media_duration: u64,
+}
+
+/// 一条轨道在编辑列表中的信息
+struct TrackEdit {
+    track_id: u32,
+    timescale: u32,
+    /// 轨道第一帧的显示时间 (PES 时钟)
+    start: i64,
+    /// 媒体时间轴上开始显示的位置 (轨道时钟)，即首帧的合成时间偏移 (B 帧延迟)
+    media_time: u64,
+    media_duration: u64,
+}
+
+/// 把 MPEG-TS (H.264 + AAC) 流转封装为 MP4，不重新编码。
+/// 以任意大小的数据块逐段输入 (`push`)，结束时调用 `finish` 写入 moov。
+/// 样本时长取自相邻帧的时间戳，各轨道起始时间的差异通过编辑列表 (edts/elst) 保留。
+pub(super) struct TsToMp4Remuxer<W: Read + Write + Seek> {
+    output: Option<BufWriter<W>>,
+    writer: Option<Mp4Writer<BufWriter<W>>>,
+    buffer: Vec<u8>,
+    pmt_pid: Option<u16>,
+    pmt_parsed: bool,
+    video: VideoTrack,
+    audio: AudioTrack,
+    video_pes: Option<Vec<u8>>,
+    audio_pes: Option<Vec<u8>>,
+    pending: VecDeque<Frame>,
+}
+
+impl<W: Read + Write + Seek> TsToMp4Remuxer<W> {
+    pub(super) fn new(output: W) -> Self {
+        Self {
+            output: Some(BufWriter::new(output)),
+            writer: None,
+            buffer: Vec::with_capacity(TS_PACKET_SIZE * 64),
+            pmt_pid: None,
+            pmt_parsed: false,
+            video: VideoTrack::default(),
+            audio: AudioTrack::default(),
+            video_pes: None,
+            audio_pes: None,
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub(super) fn push(&mut self, data: &[u8]) -> AppResult<()> {
+        self.buffer.extend_from_slice(data);
+        let mut offset = 0;
+        while self.buffer.len() - offset >= TS_PACKET_SIZE {
+            if self.buffer[offset] != TS_SYNC_BYTE {
+                // 丢失同步，跳到下一个同步字节
+                offset += 1;
+                continue;
+            }
+            let packet: [u8; TS_PACKET_SIZE] = self.buffer[offset..offset + TS_PACKET_SIZE]
+                .try_into()
+                .expect("切片长度与 TS 包长度一致");
+            self.handle_packet(&packet)?;
+            offset += TS_PACKET_SIZE;
+        }
+        self.buffer.drain(..offset);
+        Ok(())
+    }
+
+    pub(super) fn finish(mut self) -> AppResult<W> {
+        if let Some(pes) = self.video_pes.take() {
+            self.handle_video_pes(&pes)?;
+        }
+        if let Some(pes) = self.audio_pes.take() {
+            self.handle_audio_pes(&pes)?;
+        }
+        if self.writer.is_none() {
+            self.start_writer()?;
+        }
+        self.flush_pending()?;
+        if let Some((dts, pts, is_sync, data)) = self.video.held.take() {
+            let duration = self.video.last_duration.max(1);
+            self.write_video_sample(dts, pts, is_sync, data, duration)?;
+        }
+        if let Some((pts, data)) = self.audio.held.take() {
+            self.write_audio_sample(pts, data, AAC_SAMPLES_PER_FRAME)?;
+        }
+        let mut writer = self.writer.take().expect("writer 已在上方创建");
+        writer.write_end().map_err(remux_error)?;
+        let mut output = writer
+            .into_writer()
+            .into_inner()
+            .map_err(|e| remux_error(e.into_error()))?;
+        let edits = self.track_edits();
+        if needs_edit_lists(&edits) {
+            write_edit_lists(&mut output, &edits)?;
+        }
+        Ok(output)
+    }
+
+    fn handle_packet(&mut self, packet: &[u8; TS_PACKET_SIZE]) -> AppResult<()> {
+        let payload_start = packet[1] & 0x40 != 0;
+        let pid = (u16::from(packet[1] & 0x1F) << 8) | u16::from(packet[2]);
+        let adaptation_field_control = (packet[3] >> 4) & 0x03;
+        let mut offset = 4;
+        if adaptation_field_control & 0x02 != 0 {
+            offset += 1 + packet[4] as usize;
+        }
+        if adaptation_field_control & 0x01 == 0 || offset >= TS_PACKET_SIZE {
+            return Ok(());
+        }
+        let payload = &packet[offset..];
+
+        if pid == 0 {
+            if payload_start && self.pmt_pid.is_none() {
+                self.pmt_pid = parse_pat(payload);
+            }
+        } else if Some(pid) == self.pmt_pid {
+            if payload_start && !self.pmt_parsed {
+                self.parse_pmt(payload)?;
+            }
+        } else if Some(pid) == self.video.pid {
+            if payload_start && let Some(pes) = self.video_pes.replace(Vec::new()) {
+                self.handle_video_pes(&pes)?;
+            }
+            if let Some(pes) = self.video_pes.as_mut() {
+                pes.extend_from_slice(payload);
+            }
+        } else if Some(pid) == self.audio.pid {
+            if payload_start && let Some(pes) = self.audio_pes.replace(Vec::new()) {
+                self.handle_audio_pes(&pes)?;
+            }
+            if let Some(pes) = self.audio_pes.as_mut() {
+                pes.extend_from_slice(payload);
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_pmt(&mut self, payload: &[u8]) -> AppResult<()> {
+        let Some(section) = psi_section(payload, 0x02) else {
+            return Ok(());
+        };
+        if section.len() < 12 {
+            return Ok(());
+        }
+        let program_info_length = (usize::from(section[10] & 0x0F) << 8) | usize::from(section[11]);
+        let mut i = 12 + program_info_length;
+        while i + 5 <= section.len() {
+            let stream_type = section[i];
+            let pid = (u16::from(section[i + 1] & 0x1F) << 8) | u16::from(section[i + 2]);
+            let es_info_length = (usize::from(section[i + 3] & 0x0F) << 8) | usize::from(section[i + 4]);
+            match stream_type {
+                STREAM_TYPE_H264 if self.video.pid.is_none() => self.video.pid = Some(pid),
+                STREAM_TYPE_AAC_ADTS if self.audio.pid.is_none() => self.audio.pid = Some(pid),
+                STREAM_TYPE_H265 => return Err(remux_error("暂不支持 H.265 视频")),
+                other => debug!("忽略 TS 中类型为 0x{:02x} 的流 (PID {})", other, pid),
+            }
+            i += 5 + es_info_length;
+        }
+        if self.video.pid.is_none() && self.audio.pid.is_none() {
+            return Err(remux_error("TS 中没有 H.264 视频或 AAC 音频流"));
+        }
+        debug!(
+            "TS 节目表: 视频 PID {:?}, 音频 PID {:?}",
+            self.video.pid, self.audio.pid
+        );
+        self.pmt_parsed = true;
+        Ok(())
+    }
+
+    fn handle_video_pes(&mut self, pes: &[u8]) -> AppResult<()> {
+        let Some((pts, dts, payload)) = parse_pes(pes) else {
+            return Ok(());
+        };
+        let mut is_sync = false;
+        let mut data = Vec::with_capacity(payload.len() + 16);
+        for nal in split_annexb(payload) {
+            match nal[0] & 0x1F {
+                7 => {
+                    self.video.sps.get_or_insert_with(|| nal.to_vec());
+                }
+                8 => {
+                    self.video.pps.get_or_insert_with(|| nal.to_vec());
+                }
+                9 => {} // 访问单元分隔符在 MP4 中不需要
+                nal_type => {
+                    is_sync |= nal_type == 5;
+                    data.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+                    data.extend_from_slice(nal);
+                }
+            }
+        }
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let fallback_dts = self
+            .video
+            .last_dts
+            .map_or(0, |prev| prev + i64::from(self.video.last_duration.max(1)));
+        let dts = match dts.or(pts) {
+            Some(raw) => unwrap_timestamp(raw, self.video.last_dts),
+            None => fallback_dts,
+        };
+        let pts = pts.map_or(dts, |raw| unwrap_timestamp(raw, Some(dts)));
+        self.video.last_dts = Some(dts);
+        self.queue_frame(Frame::Video {
+            dts,
+            pts,
+            is_sync,
+            data,
+        })
+    }
+
+    fn handle_audio_pes(&mut self, pes: &[u8]) -> AppResult<()> {
+        let Some((pts, _, payload)) = parse_pes(pes) else {
+            return Ok(());
+        };
+        // PES 的 PTS 属于在这个 PES 中开始的第一帧，跨边界接续的帧沿用推算的时间戳
+        let mut pes_pts = pts.map(|raw| unwrap_timestamp(raw, self.audio.last_pes_pts));
+        if pes_pts.is_some() {
+            self.audio.last_pes_pts = pes_pts;
+        }
+        let carried = self.audio.carry.len();
+        let mut data = std::mem::take(&mut self.audio.carry);
+        data.extend_from_slice(payload);
+
+        let mut offset = 0;
+        while data.len() - offset >= 7 {
+            let header = &data[offset..];
+            if header[0] != 0xFF || header[1] & 0xF0 != 0xF0 {
+                offset += 1;
+                continue;
+            }
+            let header_len = if header[1] & 0x01 != 0 { 7 } else { 9 };
+            let frame_len = (usize::from(header[3] & 0x03) << 11)
+                | (usize::from(header[4]) << 3)
+                | (usize::from(header[5]) >> 5);
+            if frame_len <= header_len {
+                offset += 1;
+                continue;
+            }
+            if data.len() - offset < frame_len {
+                break;
+            }
+            if self.audio.config.is_none() {
+                self.audio.config = Some(parse_adts_config(header)?);
+            }
+            let frame = data[offset + header_len..offset + frame_len].to_vec();
+            let frame_pes_pts = if offset >= carried { pes_pts.take() } else { None };
+            let pts = self.next_audio_pts(frame_pes_pts);
+            self.queue_frame(Frame::Audio { pts, data: frame })?;
+            offset += frame_len;
+        }
+        self.audio.carry = data.split_off(offset);
+        Ok(())
+    }
+
+    /// 计算音频帧的时间戳 (采样率时钟)。帧时间戳一般按每帧 1024 个采样推算，
+    /// 与 PES 给出的 PTS 相差超过半帧时 (起始、丢帧或断流) 以 PTS 为准重新对齐。
+    fn next_audio_pts(&mut self, pes_pts: Option<i64>) -> i64 {
+        let freq = self
+            .audio
+            .config
+            .as_ref()
+            .map_or(i64::from(PES_CLOCK), |aac| i64::from(aac.freq_index.freq()));
+        let frame = i64::from(AAC_SAMPLES_PER_FRAME);
+        let pes_pts = pes_pts.map(|pts| pts * freq / i64::from(PES_CLOCK));
+        let pts = match (pes_pts, self.audio.next_pts) {
+            (Some(actual), Some(expected)) if (actual - expected).abs() <= frame / 2 => expected,
+            (Some(actual), _) => actual,
+            (None, Some(expected)) => expected,
+            (None, None) => 0,
+        };
+        self.audio.next_pts = Some(pts + frame);
+        pts
+    }
+
+    fn tracks_ready(&self) -> bool {
+        let video_ready =
+            self.video.pid.is_none() || (self.video.sps.is_some() && self.video.pps.is_some());
+        let audio_ready = self.audio.pid.is_none() || self.audio.config.is_some();
+        video_ready && audio_ready
+    }
+
+    fn queue_frame(&mut self, frame: Frame) -> AppResult<()> {
+        self.pending.push_back(frame);
+        if self.writer.is_none() {
+            if !self.tracks_ready() && self.pending.len() < MAX_PENDING_FRAMES {
+                return Ok(());
+            }
+            self.start_writer()?;
+        }
+        self.flush_pending()
+    }
+
+    /// 根据已经拿到参数的轨道创建 MP4 写入器。缺少参数的轨道会被丢弃。
+    fn start_writer(&mut self) -> AppResult<()> {
+        let output = self.output.take().expect("start_writer 只会被调用一次");
+        let config = Mp4Config {
+            major_brand: "isom".parse().unwrap(),
+            minor_version: 512,
+            compatible_brands: ["isom", "iso2", "avc1", "mp41"]
+                .iter()
+                .map(|brand| brand.parse().unwrap())
+                .collect(),
+            timescale: MOVIE_TIMESCALE,
+        };
+        let mut writer = Mp4Writer::write_start(output, &config).map_err(remux_error)?;
+        let mut next_track_id = 1;
+
+        if let (Some(sps), Some(pps)) = (&self.video.sps, &self.video.pps) {
+            let (width, height) = parse_sps_dimensions(sps).unwrap_or_else(|| {
+                warn!("无法从 SPS 中解析视频分辨率");
+                (0, 0)
+            });
+            writer
+                .add_track(&TrackConfig {
+                    track_type: TrackType::Video,
+                    timescale: PES_CLOCK,
+                    language: "und".to_string(),
+                    media_conf: MediaConfig::AvcConfig(AvcConfig {
+                        width,
+                        height,
+                        seq_param_set: sps.clone(),
+                        pic_param_set: pps.clone(),
+                    }),
+                })
+                .map_err(remux_error)?;
+            self.video.track_id = Some(next_track_id);
+            next_track_id += 1;
+        } else if self.video.pid.is_some() {
+            warn!("TS 中未找到 H.264 参数集 (SPS/PPS)，输出的 MP4 将不含视频轨道");
+        }
+
+        if let Some(aac) = &self.audio.config {
+            writer
+                .add_track(&TrackConfig {
+                    track_type: TrackType::Audio,
+                    timescale: aac.freq_index.freq(),
+                    language: "und".to_string(),
+                    media_conf: MediaConfig::AacConfig(aac.clone()),
+                })
+                .map_err(remux_error)?;
+            self.audio.track_id = Some(next_track_id);
+        } else if self.audio.pid.is_some() {
+            warn!("TS 中未找到有效的 AAC 音频帧，输出的 MP4 将不含音频轨道");
+        }
+
+        if self.video.track_id.is_none() && self.audio.track_id.is_none() {
+            return Err(remux_error("没有可写入的音视频轨道"));
+        }
+        self.writer = Some(writer);
+        Ok(())
+    }
+
+    fn flush_pending(&mut self) -> AppResult<()> {
+        while let Some(frame) = self.pending.pop_front() {
+            match frame {
+                Frame::Video {
+                    dts,
+                    pts,
+                    is_sync,
+                    data,
+                } => {
+                    if self.video.track_id.is_none() {
+                        continue;
+                    }
+                    if let Some((held_dts, held_pts, held_sync, held_data)) =
+                        self.video.held.replace((dts, pts, is_sync, data))
+                    {
+                        let duration = u32::try_from(dts - held_dts)
+                            .ok()
+                            .filter(|d| *d > 0)
+                            .unwrap_or(if self.video.last_duration > 0 {
+                                self.video.last_duration
+                            } else {
+                                FALLBACK_FRAME_DURATION
+                            });
+                        self.video.last_duration = duration;
+                        self.write_video_sample(held_dts, held_pts, held_sync, held_data, duration)?;
+                    }
+                }
+                Frame::Audio { pts, data } => {
+                    if self.audio.track_id.is_none() {
+                        continue;
+                    }
+                    if let Some((held_pts, held_data)) = self.audio.held.replace((pts, data)) {
+                        let duration = u32::try_from(pts - held_pts)
+                            .ok()
+                            .filter(|d| *d > 0)
+                            .unwrap_or(AAC_SAMPLES_PER_FRAME);
+                        self.write_audio_sample(held_pts, held_data, duration)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_video_sample(
+        &mut self,
+        dts: i64,
+        pts: i64,
+        is_sync: bool,
+        data: Vec<u8>,
+        duration: u32,
+    ) -> AppResult<()> {
+        let Some(track_id) = self.video.track_id else {
+            return Ok(());
+        };
+        self.video.first_dts.get_or_insert(dts);
+        self.video.min_pts = Some(self.video.min_pts.map_or(pts, |min| min.min(pts)));
+        self.video.media_duration += u64::from(duration);
+        let sample = Mp4Sample {
+            start_time: 0,
+            duration,
+            rendering_offset: i32::try_from(pts - dts).unwrap_or(0),
+            is_sync,
+            bytes: Bytes::from(data),
+        };
+        self.writer_mut()
+            .write_sample(track_id, &sample)
+            .map_err(remux_error)
+    }
+
+    fn write_audio_sample(&mut self, pts: i64, data: Vec<u8>, duration: u32) -> AppResult<()> {
+        let Some(track_id) = self.audio.track_id else {
+            return Ok(());
+        };
+        self.audio.first_pts.get_or_insert(pts);
+        self.audio.media_duration += u64::from(duration);
+        let sample = Mp4Sample {
+            start_time: 0,
+            duration,
+            rendering_offset: 0,
+            is_sync: true,
+            bytes: Bytes::from(data),
+        };
+        self.writer_mut()
+            .write_sample(track_id, &sample)
+            .map_err(remux_error)
+    }
+
+    fn writer_mut(&mut self) -> &mut Mp4Writer<BufWriter<W>> {
+        self.writer.as_mut().expect("写入样本前 writer 已创建")
+    }
+
+    /// 收集已写入样本的轨道的起始时间，用于生成编辑列表
+    fn track_edits(&self) -> Vec<TrackEdit> {
+        let mut edits = Vec::new();
+        if let (Some(track_id), Some(first_dts), Some(min_pts)) =
+            (self.video.track_id, self.video.first_dts, self.video.min_pts)
+        {
+            edits.push(TrackEdit {
+                track_id,
+                timescale: PES_CLOCK,
+                start: min_pts,
+                media_time: u64::try_from(min_pts - first_dts).unwrap_or(0),
+                media_duration: self.video.media_duration,
+            });
+        }
+        if let (Some(track_id), Some(first_pts), Some(aac)) =
+            (self.audio.track_id, self.audio.first_pts, &self.audio.config)
+        {
+            let freq = aac.freq_index.freq();
+            edits.push(TrackEdit {
+                track_id,
+                timescale: freq,
+                start: first_pts * i64::from(PES_CLOCK) / i64::from(freq),
+                media_time: 0,
+                media_duration: self.audio.media_duration,
+            });
+        }
+        edits
+    }
+}
+
+/// 轨道起始时间 (相对于最早开始的轨道) 换算为影片时间刻度
+fn edit_delays(edits: &[TrackEdit]) -> Vec<u64> {
+    let earliest = edits.iter().map(|e| e.start).min().unwrap_or(0);
+    edits
+        .iter()
+        .map(|e| {
+            let delay = u64::try_from(e.start - earliest).unwrap_or(0);
+            delay * u64::from(MOVIE_TIMESCALE) / u64::from(PES_CLOCK)
+        })
+        .collect()
+}
+
+fn needs_edit_lists(edits: &[TrackEdit]) -> bool {
+    edits.iter().any(|e| e.media_time > 0) || edit_delays(edits).iter().any(|&d| d > 0)
+}
+
+/// 在已写好的 MP4 的 moov 中为每条轨道加入编辑列表，并相应更新 tkhd/mvhd 的时长：
+/// 晚于最早轨道开始的轨道先放一段空编辑，再从首帧的显示时间开始播放整条媒体。
+/// moov 位于文件末尾，加入编辑列表后只会变长，直接原位覆盖即可。
+fn write_edit_lists<F: Read + Write + Seek>(file: &mut F, edits: &[TrackEdit]) -> AppResult<()> {
+    let end = file.seek(SeekFrom::End(0))?;
+    let mut reader = BufReader::new(&mut *file);
+    let mut moov_start = reader.seek(SeekFrom::Start(0))?;
+    let mut moov = loop {
+        if moov_start >= end {
+            return Err(remux_error("输出文件中未找到 moov"));
+        }
+        let header = BoxHeader::read(&mut reader).map_err(remux_error)?;
+        if header.name == BoxType::MoovBox {
+            break MoovBox::read_box(&mut reader, header.size).map_err(remux_error)?;
+        }
+        if header.size == 0 {
+            return Err(remux_error("输出文件中的 box 长度无效"));
+        }
+        moov_start = reader.seek(SeekFrom::Start(moov_start + header.size))?;
+    };
+    drop(reader);
+
+    let delays = edit_delays(edits);
+    let movie_timescale = u64::from(MOVIE_TIMESCALE);
+    let mut movie_duration = 0;
+    for trak in &mut moov.traks {
+        let Some((edit, &delay)) = edits
+            .iter()
+            .zip(&delays)
+            .find(|(e, _)| e.track_id == trak.tkhd.track_id)
+        else {
+            movie_duration = movie_duration.max(trak.tkhd.duration);
+            continue;
+        };
+        let presented = edit.media_duration.saturating_sub(edit.media_time);
+        let segment_duration = presented * movie_timescale / u64::from(edit.timescale);
+
+        let edts = trak.edts.get_or_insert_with(Default::default);
+        let elst = edts.elst.get_or_insert_with(Default::default);
+        elst.entries.clear();
+        if delay > 0 {
+            // 空编辑: media_time = -1
+            elst.entries.push(Default::default());
+            let entry = elst.entries.last_mut().expect("刚刚加入");
+            entry.segment_duration = delay;
+            entry.media_time = u64::from(u32::MAX);
+            entry.media_rate = 1;
+        }
+        elst.entries.push(Default::default());
+        let entry = elst.entries.last_mut().expect("刚刚加入");
+        entry.segment_duration = segment_duration;
+        entry.media_time = edit.media_time;
+        entry.media_rate = 1;
+
+        trak.tkhd.duration = delay + segment_duration;
+        if trak.tkhd.duration > u64::from(u32::MAX) {
+            trak.tkhd.version = 1;
+        }
+        movie_duration = movie_duration.max(trak.tkhd.duration);
+    }
+    moov.mvhd.duration = movie_duration;
+    if movie_duration > u64::from(u32::MAX) {
+        moov.mvhd.version = 1;
+    }
+
+    file.seek(SeekFrom::Start(moov_start))?;
+    let mut writer = BufWriter::new(&mut *file);
+    moov.write_box(&mut writer).map_err(remux_error)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// 取出 PSI 表 (PAT/PMT) 的节数据 (去掉 pointer_field 和末尾的 CRC)
+fn psi_section(payload: &[u8], table_id: u8) -> Option<&[u8]> {
+    let pointer = *payload.first()? as usize;
+    let section = payload.get(1 + pointer..)?;
+    if section.len() < 3 || section[0] != table_id {
+        return None;
+    }
+    let section_length = (usize::from(section[1] & 0x0F) << 8) | usize::from(section[2]);
+    let end = (3 + section_length).checked_sub(4)?;
+    section.get(..end)
+}
+
+fn parse_pat(payload: &[u8]) -> Option<u16> {
+    let section = psi_section(payload, 0x00)?;
+    section.get(8..)?.chunks_exact(4).find_map(|entry| {
+        let program_number = u16::from_be_bytes([entry[0], entry[1]]);
+        (program_number != 0).then(|| (u16::from(entry[2] & 0x1F) << 8) | u16::from(entry[3]))
+    })
+}
+
+/// 解析 PES 包，返回 (PTS, DTS, 负载)
+fn parse_pes(pes: &[u8]) -> Option<(Option<u64>, Option<u64>, &[u8])> {
+    if pes.len() < 9 || pes[..3] != [0x00, 0x00, 0x01] {
+        return None;
+    }
+    let pts_dts_flags = pes[7] >> 6;
+    let header_end = 9 + pes[8] as usize;
+    let pts = (pts_dts_flags & 0x02 != 0)
+        .then(|| pes.get(9..14).map(read_timestamp))
+        .flatten();
+    let dts = (pts_dts_flags == 0x03)
+        .then(|| pes.get(14..19).map(read_timestamp))
+        .flatten();
+    Some((pts, dts, pes.get(header_end..)?))
+}
+
+fn read_timestamp(b: &[u8]) -> u64 {
+    (u64::from(b[0] >> 1) & 0x07) << 30
+        | u64::from(b[1]) << 22
+        | u64::from(b[2] >> 1) << 15
+        | u64::from(b[3]) << 7
+        | u64::from(b[4] >> 1)
+}
+
+/// 将 33 位的 PES 时间戳展开为单调的 64 位时间戳 (处理回绕)
+fn unwrap_timestamp(raw: u64, reference: Option<i64>) -> i64 {
+    let raw = raw as i64;
+    let Some(reference) = reference else {
+        return raw;
+    };
+    let mut value = reference - reference.rem_euclid(PTS_WRAP) + raw;
+    if value - reference > PTS_WRAP / 2 {
+        value -= PTS_WRAP;
+    } else if reference - value > PTS_WRAP / 2 {
+        value += PTS_WRAP;
+    }
+    value
+}
+
+/// 按 Annex B 起始码 (00 00 01 / 00 00 00 01) 切分 NAL 单元
+fn split_annexb(data: &[u8]) -> Vec<&[u8]> {
+    let mut nals = Vec::new();
+    let mut start = None;
+    let mut i = 0;
+    while i + 3 <= data.len() {
+        if data[i..i + 3] == [0x00, 0x00, 0x01] {
+            if let Some(s) = start {
+                nals.push(&data[s..i]);
+            }
+            i += 3;
+            start = Some(i);
+        } else {
+            i += 1;
+        }
+    }
+    if let Some(s) = start {
+        nals.push(&data[s..]);
+    }
+    nals.into_iter()
+        .map(|nal| {
+            let end = nal.iter().rposition(|&b| b != 0).map_or(0, |p| p + 1);
+            &nal[..end]
+        })
+        .filter(|nal| !nal.is_empty())
+        .collect()
+}
+
+fn parse_adts_config(header: &[u8]) -> AppResult<AacConfig> {
+    let profile = header[2] >> 6;
+    let freq_index = (header[2] >> 2) & 0x0F;
+    let channels = ((header[2] & 0x01) << 2) | (header[3] >> 6);
+    Ok(AacConfig {
+        bitrate: 0,
+        profile: AudioObjectType::try_from(profile + 1).map_err(remux_error)?,
+        freq_index: SampleFreqIndex::try_from(freq_index).map_err(remux_error)?,
+        chan_conf: ChannelConfig::try_from(channels).map_err(remux_error)?,
+    })
+}
+
+/// 读取 H.264 码流中 Exp-Golomb 编码字段的位读取器 (已去除防竞争字节)
+struct BitReader {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl BitReader {
+    fn new(nal: &[u8]) -> Self {
+        let mut data = Vec::with_capacity(nal.len());
+        let mut zeros = 0;
+        for &b in nal {
+            if zeros >= 2 && b == 0x03 {
+                zeros = 0;
+                continue;
+            }
+            zeros = if b == 0 { zeros + 1 } else { 0 };
+            data.push(b);
+        }
+        Self { data, pos: 0 }
+    }
+
+    fn bit(&mut self) -> Option<u32> {
+        let byte = *self.data.get(self.pos / 8)?;
+        let bit = (byte >> (7 - self.pos % 8)) & 1;
+        self.pos += 1;
+        Some(u32::from(bit))
+    }
+
+    fn bits(&mut self, n: u32) -> Option<u32> {
+        (0..n).try_fold(0, |acc, _| Some((acc << 1) | self.bit()?))
+    }
+
+    fn ue(&mut self) -> Option<u32> {
+        let mut leading_zeros = 0;
+        while self.bit()? == 0 {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                return None;
+            }
+        }
+        Some((1u32 << leading_zeros) - 1 + self.bits(leading_zeros)?)
+    }
+
+    fn se(&mut self) -> Option<i32> {
+        let v = self.ue()? as i64;
+        Some(if v % 2 == 0 { -(v / 2) } else { (v + 1) / 2 } as i32)
+    }
+}
+
+/// 从 SPS 中解析视频的显示宽高 (已扣除裁剪区域)
+fn parse_sps_dimensions(sps: &[u8]) -> Option<(u16, u16)> {
+    let mut r = BitReader::new(sps.get(1..)?);
+    let profile_idc = r.bits(8)?;
+    r.bits(16)?; // constraint flags + level_idc
+    r.ue()?; // seq_parameter_set_id
+    let mut chroma_format_idc = 1;
+    if matches!(profile_idc, 100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135) {
+        chroma_format_idc = r.ue()?;
+        if chroma_format_idc == 3 {
+            r.bit()?; // separate_colour_plane_flag
+        }
+        r.ue()?; // bit_depth_luma_minus8
+        r.ue()?; // bit_depth_chroma_minus8
+        r.bit()?; // qpprime_y_zero_transform_bypass_flag
+        if r.bit()? == 1 {
+            let lists = if chroma_format_idc == 3 { 12 } else { 8 };
+            for i in 0..lists {
+                if r.bit()? == 1 {
+                    let size = if i < 6 { 16 } else { 64 };
+                    let (mut last, mut next) = (8i32, 8i32);
+                    for _ in 0..size {
+                        if next != 0 {
+                            next = (last + r.se()? + 256) % 256;
+                        }
+                        if next != 0 {
+                            last = next;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    r.ue()?; // log2_max_frame_num_minus4
+    match r.ue()? {
+        0 => {
+            r.ue()?; // log2_max_pic_order_cnt_lsb_minus4
+        }
+        1 => {
+            r.bit()?;
+            r.se()?;
+            r.se()?;
+            for _ in 0..r.ue()? {
+                r.se()?;
+            }
+        }
+        _ => {}
+    }
+    r.ue()?; // max_num_ref_frames
+    r.bit()?; // gaps_in_frame_num_value_allowed_flag
+    let width_in_mbs = r.ue()?.checked_add(1)?;
+    let height_in_map_units = r.ue()?.checked_add(1)?;
+    let frame_mbs_only = r.bit()?;
+    if frame_mbs_only == 0 {
+        r.bit()?; // mb_adaptive_frame_field_flag
+    }
+    r.bit()?; // direct_8x8_inference_flag
+    let (mut crop_left, mut crop_right, mut crop_top, mut crop_bottom) = (0, 0, 0, 0);
+    if r.bit()? == 1 {
+        crop_left = r.ue()?;
+        crop_right = r.ue()?;
+        crop_top = r.ue()?;
+        crop_bottom = r.ue()?;
+    }
+    let (sub_width, sub_height) = match chroma_format_idc {
+        0 => (1, 1),
+        1 => (2, 2),
+        2 => (2, 1),
+        _ => (1, 1),
+    };
+    // SPS 来自网络，字段可能是任意值，所有运算都要防止溢出
+    let crop_unit_y = sub_height * (2 - frame_mbs_only);
+    let crop_width = crop_left.checked_add(crop_right)?.checked_mul(sub_width)?;
+    let crop_height = crop_top.checked_add(crop_bottom)?.checked_mul(crop_unit_y)?;
+    let width = width_in_mbs.checked_mul(16)?.checked_sub(crop_width)?;
+    let height = height_in_map_units
+        .checked_mul(16)?
+        .checked_mul(2 - frame_mbs_only)?
+        .checked_sub(crop_height)?;
+    Some((u16::try_from(width).ok()?, u16::try_from(height).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// 构造测试用 H.264 码流字段的位写入器
+    #[derive(Default)]
+    struct BitWriter {
+        bytes: Vec<u8>,
+        bits: usize,
+    }
+
+    impl BitWriter {
+        fn bit(&mut self, b: u32) {
+            if self.bits.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if b != 0 {
+                *self.bytes.last_mut().unwrap() |= 0x80 >> (self.bits % 8);
+            }
+            self.bits += 1;
+        }
+        fn bits(&mut self, value: u32, n: u32) {
+            for i in (0..n).rev() {
+                self.bit((value >> i) & 1);
+            }
+        }
+        fn ue(&mut self, value: u32) {
+            let v = value + 1;
+            let len = 32 - v.leading_zeros();
+            self.bits(0, len - 1);
+            self.bits(v, len);
+        }
+    }
+
+    /// 1920x1080 (编码高度 1088，底部裁剪 8 行) 的 Baseline SPS
+    fn test_sps() -> Vec<u8> {
+        build_sps(119, 67, [0, 0, 0, 4])
+    }
+
+    /// 按给定的宏块宽高 (减 1) 和裁剪值 (左、右、上、下) 构造 Baseline SPS
+    fn build_sps(width_mbs_minus1: u32, height_mbs_minus1: u32, crop: [u32; 4]) -> Vec<u8> {
+        let mut w = BitWriter::default();
+        w.bits(0x67, 8); // NAL 头
+        w.bits(66, 8); // profile_idc = Baseline
+        w.bits(0, 8);
+        w.bits(40, 8); // level 4.0
+        w.ue(0); // sps_id
+        w.ue(0); // log2_max_frame_num_minus4
+        w.ue(0); // pic_order_cnt_type
+        w.ue(0); // log2_max_pic_order_cnt_lsb_minus4
+        w.ue(1); // max_num_ref_frames
+        w.bit(0);
+        w.ue(width_mbs_minus1);
+        w.ue(height_mbs_minus1);
+        w.bit(1); // frame_mbs_only_flag
+        w.bit(1); // direct_8x8_inference_flag
+        w.bit(1); // frame_cropping_flag
+        for value in crop {
+            w.ue(value);
+        }
+        w.bit(0); // vui_parameters_present_flag
+        w.bit(1); // rbsp_stop_one_bit
+        w.bytes
+    }
+
+    fn encode_timestamp(marker: u8, ts: u64) -> [u8; 5] {
+        [
+            (marker << 4) | (((ts >> 30) as u8 & 0x07) << 1) | 1,
+            (ts >> 22) as u8,
+            (((ts >> 15) as u8) << 1) | 1,
+            (ts >> 7) as u8,
+            ((ts as u8) << 1) | 1,
+        ]
+    }
+
+    fn pes(stream_id: u8, pts: u64, payload: &[u8]) -> Vec<u8> {
+        let mut out = vec![0x00, 0x00, 0x01, stream_id, 0x00, 0x00, 0x80, 0x80, 0x05];
+        out.extend_from_slice(&encode_timestamp(0x2, pts));
+        out.extend_from_slice(payload);
+        out
+    }
+
+    /// 把一段数据拆成若干 TS 包，末包用适配字段填充
+    fn packetize(pid: u16, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (i, chunk) in data.chunks(184).enumerate() {
+            let mut packet = vec![TS_SYNC_BYTE, ((pid >> 8) as u8) & 0x1F, pid as u8, 0x10];
+            if i == 0 {
+                packet[1] |= 0x40;
+            }
+            if chunk.len() < 184 {
+                packet[3] = 0x30;
+                let stuffing = 184 - chunk.len() - 1;
+                packet.push(stuffing as u8);
+                if stuffing > 0 {
+                    packet.push(0x00);
+                    packet.extend(std::iter::repeat_n(0xFF, stuffing - 1));
+                }
+            }
+            packet.extend_from_slice(chunk);
+            assert_eq!(packet.len(), TS_PACKET_SIZE);
+            out.extend(packet);
+        }
+        out
+    }
+
+    fn psi(table: &[u8]) -> Vec<u8> {
+        let mut out = vec![0x00]; // pointer_field
+        out.extend_from_slice(table);
+        out.extend_from_slice(&[0, 0, 0, 0]); // CRC (不校验)
+        out
+    }
+
+    fn adts_frame(payload_len: usize) -> Vec<u8> {
+        let frame_len = 7 + payload_len;
+        // AAC LC, 44.1kHz, 立体声
+        let mut frame = vec![
+            0xFF,
+            0xF1,
+            (1 << 6) | (4 << 2),
+            (2 << 6) | ((frame_len >> 11) as u8 & 0x03),
+            (frame_len >> 3) as u8,
+            ((frame_len as u8 & 0x07) << 5) | 0x1F,
+            0xFC,
+        ];
+        frame.extend(std::iter::repeat_n(0xAB, payload_len));
+        frame
+    }
+
+    /// 3 帧视频 (25fps，首帧 PTS 126000)，音频按 `audio_pes` 给出的 (PTS, 帧数) 分成若干 PES
+    fn build_test_ts(audio_pes: &[(u64, usize)]) -> Vec<u8> {
+        let pat = psi(&[0x00, 0xB0, 0x0D, 0x00, 0x01, 0xC1, 0x00, 0x00, 0x00, 0x01, 0xF0, 0x00]);
+        let pmt = psi(&[
+            0x02, 0xB0, 0x17, 0x00, 0x01, 0xC1, 0x00, 0x00, 0xE1, 0x00, 0xF0, 0x00, //
+            STREAM_TYPE_H264, 0xE1, 0x00, 0xF0, 0x00, //
+            STREAM_TYPE_AAC_ADTS, 0xE1, 0x01, 0xF0, 0x00,
+        ]);
+
+        let mut ts = packetize(0x0000, &pat);
+        ts.extend(packetize(0x1000, &pmt));
+        for frame in 0..3u64 {
+            let mut annexb = vec![0x00, 0x00, 0x00, 0x01, 0x09, 0xF0];
+            if frame == 0 {
+                annexb.extend_from_slice(&[0x00, 0x00, 0x00, 0x01]);
+                annexb.extend(test_sps());
+                annexb.extend_from_slice(&[0x00, 0x00, 0x00, 0x01, 0x68, 0xCE, 0x38, 0x80]);
+                annexb.extend_from_slice(&[0x00, 0x00, 0x01, 0x65, 0x88, 0x84, 0x00, 0x33]);
+            } else {
+                annexb.extend_from_slice(&[0x00, 0x00, 0x01, 0x41, 0x9A, 0x02, 0x04]);
+            }
+            ts.extend(packetize(0x0100, &pes(0xE0, 126_000 + frame * 3600, &annexb)));
+        }
+        for &(pts, frames) in audio_pes {
+            let audio: Vec<u8> = (0..frames).flat_map(|_| adts_frame(300)).collect();
+            ts.extend(packetize(0x0101, &pes(0xC0, pts, &audio)));
+        }
+        ts
+    }
+
+    fn remux(ts: &[u8]) -> mp4::Mp4Reader<Cursor<Vec<u8>>> {
+        let mut remuxer = TsToMp4Remuxer::new(Cursor::new(Vec::new()));
+        // 以不对齐 TS 包边界的块输入，模拟分片逐个写入
+        for chunk in ts.chunks(1000) {
+            remuxer.push(chunk).unwrap();
+        }
+        let mp4_bytes = remuxer.finish().unwrap().into_inner();
+        let size = mp4_bytes.len() as u64;
+        mp4::Mp4Reader::read_header(Cursor::new(mp4_bytes), size).unwrap()
+    }
+
+    /// 各轨道编辑列表的 (segment_duration, media_time)
+    fn edit_list(reader: &mp4::Mp4Reader<Cursor<Vec<u8>>>, track_id: u32) -> Vec<(u64, u64)> {
+        let trak = reader
+            .moov
+            .traks
+            .iter()
+            .find(|t| t.tkhd.track_id == track_id)
+            .unwrap();
+        trak.edts
+            .as_ref()
+            .and_then(|edts| edts.elst.as_ref())
+            .map(|elst| {
+                elst.entries
+                    .iter()
+                    .map(|e| (e.segment_duration, e.media_time))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_parse_sps_dimensions() {
+        assert_eq!(parse_sps_dimensions(&test_sps()), Some((1920, 1080)));
+    }
+
+    #[test]
+    fn test_parse_sps_dimensions_rejects_huge_values() {
+        let huge = [u32::MAX - 1, u32::MAX / 2, u32::MAX / 16, 1 << 28, 1 << 20, 0];
+        for &width in &huge {
+            for &height in &huge {
+                for &crop in &huge {
+                    for crop in [[crop, crop, 0, 0], [0, 0, crop, crop], [crop; 4]] {
+                        let dims = parse_sps_dimensions(&build_sps(width, height, crop));
+                        if let Some((w, h)) = dims {
+                            assert!(u32::from(w) <= width.saturating_add(1).saturating_mul(16));
+                            assert!(u32::from(h) <= height.saturating_add(1).saturating_mul(16));
+                        }
+                    }
+                }
+            }
+        }
+        assert_eq!(parse_sps_dimensions(&build_sps(u32::MAX - 1, 67, [0; 4])), None);
+        assert_eq!(parse_sps_dimensions(&build_sps(119, 67, [u32::MAX - 1; 4])), None);
+    }
+
+    #[test]
+    fn test_unwrap_timestamp_handles_33bit_wraparound() {
+        let before_wrap = PTS_WRAP - 1800;
+        assert_eq!(unwrap_timestamp(1800, Some(before_wrap)), PTS_WRAP + 1800);
+        assert_eq!(unwrap_timestamp(5000, Some(3000)), 5000);
+    }
+
+    #[test]
+    fn test_remux_ts_to_mp4() {
+        let reader = remux(&build_test_ts(&[(126_000, 4)]));
+        let video = reader.tracks().get(&1).expect("应包含视频轨道");
+        assert_eq!(video.track_type().unwrap(), TrackType::Video);
+        assert_eq!((video.width(), video.height()), (1920, 1080));
+        assert_eq!(video.sample_count(), 3);
+
+        let audio = reader.tracks().get(&2).expect("应包含音频轨道");
+        assert_eq!(audio.track_type().unwrap(), TrackType::Audio);
+        assert_eq!(audio.sample_freq_index().unwrap(), SampleFreqIndex::Freq44100);
+        assert_eq!(audio.channel_config().unwrap(), ChannelConfig::Stereo);
+        assert_eq!(audio.sample_count(), 4);
+        // 两条轨道同时开始，不需要编辑列表
+        assert!(edit_list(&reader, 1).is_empty());
+        assert!(edit_list(&reader, 2).is_empty());
+    }
+
+    #[test]
+    fn test_remux_keeps_audio_start_offset() {
+        // 音频比视频晚 100ms 开始
+        let reader = remux(&build_test_ts(&[(126_000 + 9_000, 4)]));
+        let video_duration = 3 * 3600 * 1000 / 90_000;
+        assert_eq!(edit_list(&reader, 1), vec![(video_duration, 0)]);
+        let audio_duration = 4 * 1024 * 1000 / 44_100;
+        assert_eq!(
+            edit_list(&reader, 2),
+            vec![(100, u64::from(u32::MAX)), (audio_duration, 0)]
+        );
+        assert_eq!(reader.moov.mvhd.duration, 100 + audio_duration);
+    }
+
+    #[test]
+    fn test_remux_keeps_gap_after_dropped_audio_frame() {
+        // 第二个 PES 之前丢了一帧 (44.1kHz 下每帧约 2089.8 个 PES 时钟)
+        let mut reader = remux(&build_test_ts(&[(126_000, 2), (126_000 + 6_269, 2)]));
+        assert_eq!(reader.tracks().get(&2).unwrap().sample_count(), 4);
+        // 126000 和 132269 换算到 44.1kHz 时钟分别为 61740 和 64811
+        let times: Vec<(u64, u32)> = (1..=4)
+            .map(|id| {
+                let sample = reader.read_sample(2, id).unwrap().unwrap();
+                (sample.start_time, sample.duration)
+            })
+            .collect();
+        assert_eq!(times, vec![(0, 1024), (1024, 2047), (3071, 1024), (4095, 1024)]);
+    }
+}
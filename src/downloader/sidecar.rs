@@ -0,0 +1,311 @@
+// src/downloader/sidecar.rs
+
+use crate::{
+    cli::MetadataFormat,
+    constants,
+    error::*,
+    models::{FileInfo, ResourceCategory, ResourceMetadata},
+    ui, utils,
+};
+use chrono::{DateTime, FixedOffset};
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs,
+    path::{Component, Path},
+    sync::Arc,
+};
+
+/// `<标题>.resource.json` 中的单个文件条目
+#[derive(Serialize)]
+struct SidecarFile<'a> {
+    /// 相对于元数据文件所在目录的路径，统一使用 `/` 分隔；文件不在该目录下时以 `../` 开头
+    path: String,
+    category: ResourceCategory,
+    url: &'a str,
+    size: Option<u64>,
+    md5: Option<&'a str>,
+    date: Option<DateTime<FixedOffset>>,
+}
+
+#[derive(Serialize)]
+struct ResourceSidecar<'a> {
+    #[serde(flatten)]
+    resource: &'a ResourceMetadata,
+    files: Vec<SidecarFile<'a>>,
+}
+
+/// 为已下载的文件写入元数据附属文件，返回写入的附属文件数量。
+/// `tasks` 应只包含本次运行中下载成功或校验通过的文件，其路径已是最终的绝对路径。
+/// 单个资源写入失败时给出警告并继续处理其余资源。
+pub(super) fn write_sidecars(
+    tasks: &[FileInfo],
+    base_output_dir: &Path,
+    formats: &[MetadataFormat],
+) -> usize {
+    let mut by_resource: BTreeMap<&str, (&Arc<ResourceMetadata>, Vec<&FileInfo>)> =
+        BTreeMap::new();
+    for task in tasks {
+        if let Some(metadata) = &task.metadata {
+            by_resource
+                .entry(metadata.resource_id.as_str())
+                .or_insert_with(|| (metadata, vec![]))
+                .1
+                .push(task);
+        }
+    }
+
+    let mut written = 0;
+    for (metadata, files) in by_resource.values() {
+        match write_resource_sidecars(metadata, files, base_output_dir, formats) {
+            Ok(count) => written += count,
+            Err(e) => {
+                warn!("写入资源 '{}' 的元数据文件失败: {}", metadata.title, e);
+                ui::warn(&format!("写入 '{}' 的元数据文件失败: {}", metadata.title, e));
+            }
+        }
+    }
+    info!("共写入 {} 个元数据附属文件", written);
+    written
+}
+
+fn write_resource_sidecars(
+    metadata: &ResourceMetadata,
+    files: &[&FileInfo],
+    base_output_dir: &Path,
+    formats: &[MetadataFormat],
+) -> AppResult<usize> {
+    let mut written = 0;
+    if formats.contains(&MetadataFormat::Json) {
+        // 目录模板可能让所有文件都落在资源目录之外，此时资源目录尚未被创建
+        let dir = utils::secure_join_path(base_output_dir, &metadata.base_dir)?;
+        fs::create_dir_all(&dir)?;
+        let sidecar = ResourceSidecar {
+            resource: metadata,
+            files: files
+                .iter()
+                .map(|f| SidecarFile {
+                    path: relative_path_string(&f.filepath, &dir),
+                    category: f.category,
+                    url: &f.url,
+                    size: f.ti_size,
+                    md5: f.ti_md5.as_deref(),
+                    date: f.date,
+                })
+                .collect(),
+        };
+        let path = dir.join(format!(
+            "{}{}",
+            metadata.file_stem,
+            constants::RESOURCE_METADATA_SUFFIX
+        ));
+        debug!("写入资源元数据: {:?}", path);
+        utils::write_file_atomic(&path, serde_json::to_string_pretty(&sidecar)?)?;
+        written += 1;
+    }
+    if formats.contains(&MetadataFormat::Nfo) {
+        for video in files.iter().filter(|f| f.category == ResourceCategory::Video) {
+            let path = video.filepath.with_extension("nfo");
+            debug!("写入视频 NFO: {:?}", path);
+            utils::write_file_atomic(&path, render_nfo(video, metadata))?;
+            written += 1;
+        }
+    }
+    Ok(written)
+}
+
+/// 文件相对于元数据文件所在目录的路径。目录模板可能把课时目录放在资源目录之外
+/// (如 `{teacher}/{lesson}`)，此时从两者的共同上级目录出发，以 `..` 回溯。
+fn relative_path_string(path: &Path, dir: &Path) -> String {
+    let path: Vec<Component> = path.components().collect();
+    let dir: Vec<Component> = dir.components().collect();
+    let common = path.iter().zip(&dir).take_while(|(a, b)| a == b).count();
+    let parents = dir[common..]
+        .iter()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .map(|_| "..".into());
+    let rest = path[common..].iter().filter_map(|c| match c {
+        Component::Normal(part) => Some(part.to_string_lossy()),
+        _ => None,
+    });
+    parents.chain(rest).collect::<Vec<_>>().join("/")
+}
+
+/// 生成 Kodi/Jellyfin 可识别的 `<movie>` NFO
+fn render_nfo(video: &FileInfo, metadata: &ResourceMetadata) -> String {
+    let title = video
+        .filepath
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| metadata.title.clone());
+
+    let mut nfo = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<movie>\n");
+    let mut element = |name: &str, value: &str| {
+        let _ = writeln!(nfo, "  <{0}>{1}</{0}>", name, xml_escape(value));
+    };
+    element("title", &title);
+    element("plot", &metadata.chapter_path.join(" / "));
+    if let Some(date) = video.date.or(metadata.update_time) {
+        element("premiered", &date.format("%Y-%m-%d").to_string());
+    }
+    for teacher in &metadata.teachers {
+        element("director", teacher);
+    }
+    for tag in &metadata.tags {
+        element("tag", &tag.name);
+    }
+    let _ = writeln!(
+        nfo,
+        "  <set>\n    <name>{}</name>\n  </set>",
+        xml_escape(&metadata.title)
+    );
+    let _ = writeln!(
+        nfo,
+        "  <uniqueid type=\"smartedu\" default=\"true\">{}</uniqueid>",
+        xml_escape(&metadata.resource_id)
+    );
+    nfo.push_str("</movie>\n");
+    nfo
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::MetadataTag;
+    use serde_json::Value;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_write_sidecars_for_downloaded_files() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let base_output_dir = dunce::canonicalize(temp_dir.path()).unwrap();
+        let date = DateTime::parse_from_rfc3339("2024-03-01T08:00:00+08:00").unwrap();
+        let metadata = Arc::new(ResourceMetadata {
+            resource_id: "course-1".to_string(),
+            resource_type: constants::api::types::QUALITY_COURSE.to_string(),
+            title: "分数 & 小数".to_string(),
+            update_time: Some(date),
+            tags: vec![MetadataTag {
+                dimension_id: "zxxxk".to_string(),
+                name: "数学".to_string(),
+            }],
+            teachers: vec!["张老师".to_string()],
+            chapter_path: vec!["第一单元".to_string(), "第1课".to_string()],
+            base_dir: PathBuf::from("数学/第一单元"),
+            file_stem: "分数 & 小数".to_string(),
+        });
+        let course_dir = base_output_dir.join("数学/第一单元");
+        fs::create_dir_all(course_dir.join("课时1")).unwrap();
+        let file = |relative: &str, category: ResourceCategory| FileInfo {
+            filepath: course_dir.join(relative),
+            url: format!("https://example.com/{}", relative),
+            date: Some(date),
+            category,
+            resource_id: Some("course-1".to_string()),
+            metadata: Some(metadata.clone()),
+            ..Default::default()
+        };
+        let tasks = vec![
+            file("课时1/视频 [1080] - [张老师].ts", ResourceCategory::Video),
+            file("课件 - [张老师].pdf", ResourceCategory::Document),
+        ];
+        fs::write(&tasks[0].filepath, b"ts").unwrap();
+        fs::write(&tasks[1].filepath, b"pdf").unwrap();
+
+        let written = write_sidecars(
+            &tasks,
+            &base_output_dir,
+            &[MetadataFormat::Json, MetadataFormat::Nfo],
+        );
+        assert_eq!(written, 2);
+
+        let json: Value = serde_json::from_str(
+            &fs::read_to_string(course_dir.join("分数 & 小数.resource.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(json["resource_id"], "course-1");
+        assert_eq!(json["chapter_path"][1], "第1课");
+        assert_eq!(json["tags"][0]["name"], "数学");
+        let paths: Vec<&str> = json["files"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f["path"].as_str().unwrap())
+            .collect();
+        assert_eq!(paths, ["课时1/视频 [1080] - [张老师].ts", "课件 - [张老师].pdf"]);
+
+        let nfo = fs::read_to_string(course_dir.join("课时1/视频 [1080] - [张老师].nfo")).unwrap();
+        assert!(nfo.contains("<title>视频 [1080] - [张老师]</title>"));
+        assert!(nfo.contains("<plot>第一单元 / 第1课</plot>"));
+        assert!(nfo.contains("<premiered>2024-03-01</premiered>"));
+        assert!(nfo.contains("<director>张老师</director>"));
+        assert!(nfo.contains("<name>分数 &amp; 小数</name>"));
+    }
+
+    fn lesson_metadata(resource_id: &str, base_dir: &str) -> Arc<ResourceMetadata> {
+        Arc::new(ResourceMetadata {
+            resource_id: resource_id.to_string(),
+            resource_type: constants::api::types::SYNC_CLASSROOM.to_string(),
+            title: resource_id.to_string(),
+            update_time: None,
+            tags: vec![],
+            teachers: vec![],
+            chapter_path: vec![],
+            base_dir: PathBuf::from(base_dir),
+            file_stem: resource_id.to_string(),
+        })
+    }
+
+    #[test]
+    fn test_write_sidecars_when_files_live_outside_resource_dir() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let base_output_dir = dunce::canonicalize(temp_dir.path()).unwrap();
+        // `{teacher}/{lesson}` 模板：视频都在教师目录下，资源目录本身不会被下载流程创建
+        let lesson = lesson_metadata("lesson-1", "第一单元/第1课");
+        let video_path = base_output_dir.join("李老师/第1课/视频.ts");
+        fs::create_dir_all(video_path.parent().unwrap()).unwrap();
+        fs::write(&video_path, b"ts").unwrap();
+        // 资源目录的位置被一个普通文件占用，这个资源的元数据无法写入
+        let broken = lesson_metadata("lesson-2", "占用");
+        fs::write(base_output_dir.join("占用"), b"").unwrap();
+        let broken_path = base_output_dir.join("李老师/第2课/视频.ts");
+
+        let task = |filepath: &Path, metadata: &Arc<ResourceMetadata>| FileInfo {
+            filepath: filepath.to_path_buf(),
+            category: ResourceCategory::Video,
+            metadata: Some(metadata.clone()),
+            ..Default::default()
+        };
+        let tasks = vec![task(&video_path, &lesson), task(&broken_path, &broken)];
+
+        // 写入失败的资源不影响其他资源
+        assert_eq!(write_sidecars(&tasks, &base_output_dir, &[MetadataFormat::Json]), 1);
+        let sidecar = base_output_dir.join("第一单元/第1课/lesson-1.resource.json");
+        let json: Value = serde_json::from_str(&fs::read_to_string(sidecar).unwrap()).unwrap();
+        assert_eq!(json["files"][0]["path"], "../../李老师/第1课/视频.ts");
+    }
+
+    #[test]
+    fn test_relative_path_outside_resource_dir() {
+        let root = Path::new("/out");
+        assert_eq!(
+            relative_path_string(&root.join("张老师/第一单元/课时1/a.ts"), &root.join("张老师/第一单元")),
+            "课时1/a.ts"
+        );
+        // `{teacher}/{lesson}` 模板下，其他教师的课时目录不在资源目录之内
+        assert_eq!(
+            relative_path_string(&root.join("李老师/课时2/b.ts"), &root.join("张老师/第一单元")),
+            "../../李老师/课时2/b.ts"
+        );
+    }
+}
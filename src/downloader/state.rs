@@ -0,0 +1,437 @@
+// src/downloader/state.rs
+
+use crate::{
+    constants,
+    error::*,
+    models::{DownloadStatus, FileInfo, ResourceCategory},
+    utils,
+};
+use anyhow::{Context, anyhow};
+use chrono::{DateTime, FixedOffset, Local};
+use indicatif::HumanBytes;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tempfile::NamedTempFile;
+
+/// 下载状态数据库中的一条记录，对应一个本地文件。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadRecord {
+    pub filepath: PathBuf,
+    pub url: String,
+    #[serde(default)]
+    pub resource_id: Option<String>,
+    #[serde(default)]
+    pub category: ResourceCategory,
+    pub status: DownloadStatus,
+    /// 记录时本地文件的实际大小
+    #[serde(default)]
+    pub size: Option<u64>,
+    /// 服务器声明的 MD5 校验值
+    #[serde(default)]
+    pub ti_md5: Option<String>,
+    pub first_recorded_at: DateTime<FixedOffset>,
+    pub updated_at: DateTime<FixedOffset>,
+    /// 最近一次成功下载的时间
+    #[serde(default)]
+    pub completed_at: Option<DateTime<FixedOffset>>,
+}
+
+impl DownloadRecord {
+    /// 判断该记录是否表明文件已完整下载，且与当前任务及磁盘上的文件一致。
+    /// 只比较文件大小 (stat)，不读取文件内容，因此可以瞬间完成。
+    pub fn is_complete_for(&self, item: &FileInfo) -> bool {
+        if !self.status.is_success() || self.url != item.url {
+            return false;
+        }
+        if let (Some(recorded), Some(expected)) = (&self.ti_md5, &item.ti_md5)
+            && !recorded.eq_ignore_ascii_case(expected)
+        {
+            return false;
+        }
+        match (self.size, item.filepath.metadata()) {
+            (Some(size), Ok(meta)) => meta.len() == size,
+            _ => false,
+        }
+    }
+
+    /// 重新检查磁盘上的文件是否仍与记录一致，返回发现的问题 (无问题时返回 `None`)。
+    /// `check_md5` 为 true 时会读取文件内容计算 MD5，较慢。
+    pub fn verify(&self, check_md5: bool) -> AppResult<Option<String>> {
+        if !self.status.is_success() {
+            let (_, _, msg) = self.status.get_display_info();
+            return Ok(Some(format!("上次下载未成功: {}", msg)));
+        }
+        let Ok(meta) = self.filepath.metadata() else {
+            return Ok(Some("文件不存在".to_string()));
+        };
+        if let Some(size) = self.size
+            && meta.len() != size
+        {
+            return Ok(Some(format!(
+                "大小与记录不一致 (记录: {}, 实际: {})",
+                HumanBytes(size),
+                HumanBytes(meta.len())
+            )));
+        }
+        if check_md5 && let Some(expected) = &self.ti_md5 {
+            let actual = utils::calculate_file_md5(&self.filepath)?;
+            if !actual.eq_ignore_ascii_case(expected) {
+                return Ok(Some("MD5 不匹配".to_string()));
+            }
+        }
+        Ok(None)
+    }
+}
+
+struct StateInner {
+    records: HashMap<PathBuf, DownloadRecord>,
+    log_file: Option<File>,
+}
+
+/// 持久化的下载状态数据库。
+/// 以 JSON Lines 格式追加写入 `~/.sed-dl/state.jsonl`，加载时同一路径的记录以最后一条为准。
+pub struct DownloadStateStore {
+    path: Option<PathBuf>,
+    inner: Mutex<StateInner>,
+}
+
+impl DownloadStateStore {
+    /// 打开位于用户配置目录下的默认状态数据库。
+    pub fn open_default() -> AppResult<Self> {
+        Self::open(&Self::default_path()?)
+    }
+
+    /// 以只读方式打开默认状态数据库：不创建目录、不压缩、不追加写入，用于 `--dry-run`。
+    pub fn open_default_read_only() -> AppResult<Self> {
+        Self::open_read_only(&Self::default_path()?)
+    }
+
+    fn default_path() -> AppResult<PathBuf> {
+        Ok(dirs::home_dir()
+            .ok_or_else(|| AppError::Other(anyhow!("无法获取用户主目录")))?
+            .join(constants::CONFIG_DIR_NAME)
+            .join(constants::STATE_FILE_NAME))
+    }
+
+    pub fn open(path: &Path) -> AppResult<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let (records, line_count) = Self::load_records(path)?;
+        debug!(
+            "从 {:?} 加载了 {} 条下载记录 (共 {} 行)",
+            path,
+            records.len(),
+            line_count
+        );
+
+        let store = Self {
+            path: Some(path.to_path_buf()),
+            inner: Mutex::new(StateInner {
+                records,
+                log_file: None,
+            }),
+        };
+        if line_count > store.len() * 2 + constants::STATE_COMPACT_SLACK {
+            store.compact()?;
+        }
+        let log_file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("打开下载状态文件 '{}' 失败", path.display()))?;
+        store.inner.lock().unwrap().log_file = Some(log_file);
+        Ok(store)
+    }
+
+    /// 只加载已有记录，之后的 `record` 只更新内存，不会写入文件。
+    pub fn open_read_only(path: &Path) -> AppResult<Self> {
+        let (records, _) = Self::load_records(path)?;
+        debug!("以只读方式从 {:?} 加载了 {} 条下载记录", path, records.len());
+        Ok(Self {
+            path: None,
+            inner: Mutex::new(StateInner {
+                records,
+                log_file: None,
+            }),
+        })
+    }
+
+    /// 创建一个不落盘的状态数据库，用于测试或无法访问主目录的情况。
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            inner: Mutex::new(StateInner {
+                records: HashMap::new(),
+                log_file: None,
+            }),
+        }
+    }
+
+    fn load_records(path: &Path) -> AppResult<(HashMap<PathBuf, DownloadRecord>, usize)> {
+        let mut records = HashMap::new();
+        if !path.is_file() {
+            return Ok((records, 0));
+        }
+        let reader = BufReader::new(File::open(path)?);
+        let mut line_count = 0;
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            line_count += 1;
+            match serde_json::from_str::<DownloadRecord>(&line) {
+                Ok(record) => {
+                    records.insert(record.filepath.clone(), record);
+                }
+                Err(e) => warn!("下载状态文件第 {} 行无法解析，已忽略: {}", index + 1, e),
+            }
+        }
+        Ok((records, line_count))
+    }
+
+    /// 将所有记录重写为每个文件一行，清除历史冗余行。
+    fn compact(&self) -> AppResult<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut inner = self.inner.lock().unwrap();
+        let parent = path.parent().unwrap_or_else(|| Path::new("."));
+        let mut temp_file = NamedTempFile::new_in(parent)?;
+        {
+            let mut writer = BufWriter::new(&mut temp_file);
+            for record in inner.records.values() {
+                serde_json::to_writer(&mut writer, record)?;
+                writer.write_all(b"\n")?;
+            }
+            writer.flush()?;
+        }
+        // 先关闭旧的追加句柄，再替换文件
+        inner.log_file = None;
+        temp_file.persist(path)?;
+        info!("已压缩下载状态文件 {:?}，保留 {} 条记录", path, inner.records.len());
+        Ok(())
+    }
+
+    pub fn get(&self, filepath: &Path) -> Option<DownloadRecord> {
+        self.inner.lock().unwrap().records.get(filepath).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 返回所有记录，按文件路径排序。
+    pub fn records(&self) -> Vec<DownloadRecord> {
+        let mut records: Vec<_> = self.inner.lock().unwrap().records.values().cloned().collect();
+        records.sort_by(|a, b| a.filepath.cmp(&b.filepath));
+        records
+    }
+
+    /// 记录一个任务的最终状态并追加写入状态文件。
+    /// 对于“跳过”的任务，如果已有记录则保留原状态，只刷新时间戳；
+    /// 没有记录时，跳过意味着本地文件已通过校验，因此记为成功。
+    pub fn record(&self, item: &FileInfo, status: DownloadStatus) {
+        let now = Local::now().fixed_offset();
+        let size = item.filepath.metadata().ok().map(|m| m.len());
+        let mut inner = self.inner.lock().unwrap();
+
+        let record = match inner.records.get(&item.filepath) {
+            Some(existing) if status == DownloadStatus::Skipped => DownloadRecord {
+                size: size.or(existing.size),
+                updated_at: now,
+                ..existing.clone()
+            },
+            existing => {
+                let status = if status == DownloadStatus::Skipped {
+                    DownloadStatus::Success
+                } else {
+                    status
+                };
+                DownloadRecord {
+                    filepath: item.filepath.clone(),
+                    url: item.url.clone(),
+                    resource_id: item.resource_id.clone(),
+                    category: item.category,
+                    status,
+                    size,
+                    ti_md5: item.ti_md5.clone(),
+                    first_recorded_at: existing.map_or(now, |r| r.first_recorded_at),
+                    updated_at: now,
+                    completed_at: if status.is_success() {
+                        Some(now)
+                    } else {
+                        existing.and_then(|r| r.completed_at)
+                    },
+                }
+            }
+        };
+
+        if let Some(file) = inner.log_file.as_mut() {
+            let written = serde_json::to_string(&record)
+                .map_err(AppError::from)
+                .and_then(|line| writeln!(file, "{}", line).map_err(AppError::from));
+            if let Err(e) = written {
+                warn!("写入下载状态记录失败 {:?}: {}", item.filepath, e);
+            }
+        }
+        inner.records.insert(item.filepath.clone(), record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_item(dir: &Path, name: &str, content: &[u8]) -> FileInfo {
+        let filepath = dir.join(name);
+        fs::write(&filepath, content).unwrap();
+        FileInfo {
+            filepath,
+            url: format!("https://example.com/{}", name),
+            ti_size: Some(content.len() as u64),
+            resource_id: Some("res-1".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_records_survive_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let state_path = dir.path().join("state.jsonl");
+        let item = sample_item(dir.path(), "a.pdf", b"hello");
+
+        let store = DownloadStateStore::open(&state_path).unwrap();
+        store.record(&item, DownloadStatus::Md5Failed);
+        store.record(&item, DownloadStatus::Success);
+        drop(store);
+
+        let reopened = DownloadStateStore::open(&state_path).unwrap();
+        let record = reopened.get(&item.filepath).expect("记录应当被持久化");
+        assert_eq!(reopened.len(), 1);
+        assert_eq!(record.status, DownloadStatus::Success);
+        assert_eq!(record.resource_id.as_deref(), Some("res-1"));
+        assert_eq!(record.size, Some(5));
+        assert!(record.completed_at.is_some());
+        assert!(record.is_complete_for(&item));
+    }
+
+    #[test]
+    fn test_skip_keeps_existing_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let item = sample_item(dir.path(), "b.mp3", b"audio");
+        let store = DownloadStateStore::in_memory();
+
+        store.record(&item, DownloadStatus::Success);
+        store.record(&item, DownloadStatus::Skipped);
+
+        assert_eq!(store.get(&item.filepath).unwrap().status, DownloadStatus::Success);
+    }
+
+    #[test]
+    fn test_skip_without_record_counts_as_success() {
+        let dir = tempfile::tempdir().unwrap();
+        let item = sample_item(dir.path(), "g.pdf", b"validated");
+        let store = DownloadStateStore::in_memory();
+
+        store.record(&item, DownloadStatus::Skipped);
+
+        let record = store.get(&item.filepath).unwrap();
+        assert_eq!(record.status, DownloadStatus::Success);
+        assert!(record.completed_at.is_some());
+        assert!(record.is_complete_for(&item));
+    }
+
+    #[test]
+    fn test_is_complete_for_detects_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut item = sample_item(dir.path(), "c.pdf", b"content");
+        item.ti_md5 = Some("aaaa".to_string());
+        let store = DownloadStateStore::in_memory();
+        store.record(&item, DownloadStatus::Success);
+        let record = store.get(&item.filepath).unwrap();
+
+        // 服务器端文件更新 (MD5 变化)
+        let mut updated = item.clone();
+        updated.ti_md5 = Some("bbbb".to_string());
+        assert!(!record.is_complete_for(&updated));
+
+        // 本地文件被修改 (大小变化)
+        fs::write(&item.filepath, b"changed content").unwrap();
+        assert!(!record.is_complete_for(&item));
+    }
+
+    #[test]
+    fn test_verify_reports_problems() {
+        let dir = tempfile::tempdir().unwrap();
+        let item = sample_item(dir.path(), "e.pdf", b"content");
+        let store = DownloadStateStore::in_memory();
+        store.record(&item, DownloadStatus::Success);
+        let record = store.get(&item.filepath).unwrap();
+        assert_eq!(record.verify(false).unwrap(), None);
+
+        fs::write(&item.filepath, b"truncated").unwrap();
+        assert!(record.verify(false).unwrap().unwrap().contains("大小"));
+
+        fs::remove_file(&item.filepath).unwrap();
+        assert_eq!(record.verify(false).unwrap().as_deref(), Some("文件不存在"));
+
+        store.record(&item, DownloadStatus::NetworkError);
+        let failed = store.get(&item.filepath).unwrap();
+        assert!(failed.verify(false).unwrap().unwrap().contains("未成功"));
+    }
+
+    #[test]
+    fn test_compaction_on_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let state_path = dir.path().join("state.jsonl");
+        let item = sample_item(dir.path(), "d.pdf", b"x");
+
+        let store = DownloadStateStore::open(&state_path).unwrap();
+        for _ in 0..(constants::STATE_COMPACT_SLACK + 10) {
+            store.record(&item, DownloadStatus::Success);
+        }
+        drop(store);
+
+        let reopened = DownloadStateStore::open(&state_path).unwrap();
+        assert_eq!(reopened.len(), 1);
+        let lines = fs::read_to_string(&state_path).unwrap().lines().count();
+        assert_eq!(lines, 1, "压缩后每个文件只应保留一行记录");
+    }
+
+    #[test]
+    fn test_read_only_store_never_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let state_path = dir.path().join("state.jsonl");
+        let item = sample_item(dir.path(), "f.pdf", b"x");
+
+        let store = DownloadStateStore::open(&state_path).unwrap();
+        for _ in 0..(constants::STATE_COMPACT_SLACK + 10) {
+            store.record(&item, DownloadStatus::Success);
+        }
+        drop(store);
+        let before = fs::read_to_string(&state_path).unwrap();
+
+        let read_only = DownloadStateStore::open_read_only(&state_path).unwrap();
+        assert_eq!(read_only.get(&item.filepath).unwrap().status, DownloadStatus::Success);
+        read_only.record(&item, DownloadStatus::NetworkError);
+        drop(read_only);
+        assert_eq!(fs::read_to_string(&state_path).unwrap(), before, "只读模式不应压缩或追加");
+
+        let missing = dir.path().join("missing/state.jsonl");
+        assert!(DownloadStateStore::open_read_only(&missing).unwrap().is_empty());
+        assert!(!missing.parent().unwrap().exists());
+    }
+}
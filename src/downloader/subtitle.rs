@@ -0,0 +1,225 @@
+// src/downloader/subtitle.rs
+
+use crate::{
+    DownloadJobContext, constants::api::resource_formats, error::*, models::DownloadStatus,
+    models::FileInfo, utils,
+};
+use indicatif::ProgressBar;
+use log::{debug, info};
+use reqwest::StatusCode;
+use serde_json::Value;
+use std::{borrow::Cow, fmt::Write as _};
+use url::Url;
+
+/// 字幕条目所在数组可能使用的字段名 (平台字幕 JSON 的顶层为对象时)
+const CUE_LIST_KEYS: [&str; 6] = ["body", "captions", "subtitles", "data", "list", "items"];
+const CUE_START_KEYS: [&str; 6] = ["start", "startTime", "start_time", "from", "begin", "bg"];
+const CUE_END_KEYS: [&str; 6] = ["end", "endTime", "end_time", "to", "stop", "ed"];
+const CUE_TEXT_KEYS: [&str; 5] = ["text", "content", "line", "value", "onebest"];
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+/// 数值时间戳的最大值超过该值时按毫秒处理，否则按秒处理 (10 小时)
+const SECONDS_UPPER_BOUND: f64 = 36_000.0;
+
+/// 下载字幕。字幕文件很小，整体读入内存；平台的 JSON 字幕转换为 SRT 后保存。
+pub(super) struct SubtitleDownloader {
+    context: DownloadJobContext,
+}
+
+impl SubtitleDownloader {
+    pub(super) fn new(context: DownloadJobContext) -> Self {
+        Self { context }
+    }
+
+    pub(super) async fn download(
+        &self,
+        item: &FileInfo,
+        pbar: ProgressBar,
+        use_byte_progress: bool,
+    ) -> AppResult<DownloadStatus> {
+        let mut url = Url::parse(&item.url)?;
+        let token = self.context.token.lock().await;
+        if !token.is_empty() {
+            url.query_pairs_mut().append_pair("accessToken", &token);
+        }
+        drop(token);
+
+        let res = self.context.http_client.client.get(url).send().await?;
+        if matches!(res.status(), StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) {
+            return Err(AppError::TokenInvalid);
+        }
+        let bytes = res.error_for_status()?.bytes().await?;
+        if use_byte_progress {
+            pbar.inc(bytes.len() as u64);
+        }
+
+        utils::write_file_atomic(&item.filepath, subtitle_file_content(item, &bytes)?)?;
+        Ok(DownloadStatus::Success)
+    }
+}
+
+/// 生成字幕文件的内容：提取器标记为平台 JSON 字幕的条目转换为 SRT，其他格式原样保存
+fn subtitle_file_content<'a>(item: &FileInfo, bytes: &'a [u8]) -> AppResult<Cow<'a, [u8]>> {
+    if item.source_format.as_deref() != Some(resource_formats::JSON) {
+        return Ok(Cow::Borrowed(bytes));
+    }
+    info!("字幕为平台 JSON 格式，转换为 SRT: {:?}", item.filepath);
+    let bytes = bytes.strip_prefix(UTF8_BOM).unwrap_or(bytes);
+    let json: Value = serde_json::from_slice(bytes)?;
+    Ok(Cow::Owned(caption_json_to_srt(&json)?.into_bytes()))
+}
+
+struct Cue {
+    start: f64,
+    end: f64,
+    text: String,
+}
+
+/// 将平台的 JSON 字幕转换为 SRT。
+/// 兼容常见的结构：顶层即为条目数组，或条目数组位于 `body`/`captions` 等字段下；
+/// 时间可以是数字 (秒或毫秒) 或 `HH:MM:SS.mmm` 形式的字符串。
+pub(crate) fn caption_json_to_srt(json: &Value) -> AppResult<String> {
+    let entries = find_cue_list(json)
+        .ok_or_else(|| AppError::Subtitle("无法识别的字幕 JSON 结构".to_string()))?;
+
+    let mut cues: Vec<(Value, Value, String)> = Vec::new();
+    for entry in entries {
+        let field = |keys: &[&str]| keys.iter().find_map(|k| entry.get(*k)).cloned();
+        let (Some(start), Some(end), Some(text)) = (
+            field(&CUE_START_KEYS),
+            field(&CUE_END_KEYS),
+            field(&CUE_TEXT_KEYS).and_then(|t| t.as_str().map(str::to_string)),
+        ) else {
+            debug!("忽略无法识别的字幕条目: {}", entry);
+            continue;
+        };
+        cues.push((start, end, text));
+    }
+    if cues.is_empty() {
+        return Err(AppError::Subtitle("字幕 JSON 中没有有效的条目".to_string()));
+    }
+
+    let max_number = cues
+        .iter()
+        .flat_map(|(start, end, _)| [start.as_f64(), end.as_f64()])
+        .flatten()
+        .fold(0.0, f64::max);
+    let numbers_in_ms = max_number > SECONDS_UPPER_BOUND;
+    let to_seconds = |v: &Value| -> Option<f64> {
+        match v {
+            Value::Number(n) => n.as_f64().map(|n| if numbers_in_ms { n / 1000.0 } else { n }),
+            Value::String(s) => parse_timestamp(s),
+            _ => None,
+        }
+    };
+
+    let mut cues: Vec<Cue> = cues
+        .into_iter()
+        .filter_map(|(start, end, text)| {
+            let text = text.trim().to_string();
+            if text.is_empty() {
+                return None;
+            }
+            Some(Cue {
+                start: to_seconds(&start)?,
+                end: to_seconds(&end)?,
+                text,
+            })
+        })
+        .collect();
+    cues.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+    let mut srt = String::new();
+    for (i, cue) in cues.iter().enumerate() {
+        let _ = write!(
+            srt,
+            "{}\n{} --> {}\n{}\n\n",
+            i + 1,
+            format_srt_time(cue.start),
+            format_srt_time(cue.end.max(cue.start)),
+            cue.text
+        );
+    }
+    Ok(srt)
+}
+
+fn find_cue_list(json: &Value) -> Option<&Vec<Value>> {
+    match json {
+        Value::Array(list) => Some(list),
+        Value::Object(map) => CUE_LIST_KEYS
+            .iter()
+            .filter_map(|k| map.get(*k))
+            .find_map(find_cue_list),
+        _ => None,
+    }
+}
+
+/// 解析 `HH:MM:SS.mmm`、`MM:SS,mmm` 或纯数字 (秒) 形式的时间
+fn parse_timestamp(s: &str) -> Option<f64> {
+    let s = s.trim().replace(',', ".");
+    s.split(':')
+        .try_fold(0.0, |acc, part| Some(acc * 60.0 + part.parse::<f64>().ok()?))
+}
+
+fn format_srt_time(seconds: f64) -> String {
+    let total_ms = (seconds.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        total_ms / 3_600_000,
+        total_ms / 60_000 % 60,
+        total_ms / 1000 % 60,
+        total_ms % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_caption_json_to_srt() {
+        // 毫秒时间戳，条目位于 body 字段下，乱序且含空行
+        let json = json!({"body": [
+            {"startTime": 62500, "endTime": 65000, "text": "第二句"},
+            {"startTime": 1000, "endTime": 3200, "text": " 第一句 "},
+            {"startTime": 70000, "endTime": 71000, "text": ""},
+        ]});
+        assert_eq!(
+            caption_json_to_srt(&json).unwrap(),
+            "1\n00:00:01,000 --> 00:00:03,200\n第一句\n\n2\n00:01:02,500 --> 00:01:05,000\n第二句\n\n"
+        );
+
+        // 秒与字符串时间戳
+        let json = json!([
+            {"from": 1.5, "to": 2.25, "content": "a"},
+            {"start": "01:00:00.100", "end": "01:00:01,200", "text": "b"},
+        ]);
+        assert_eq!(
+            caption_json_to_srt(&json).unwrap(),
+            "1\n00:00:01,500 --> 00:00:02,250\na\n\n2\n01:00:00,100 --> 01:00:01,200\nb\n\n"
+        );
+
+        assert!(caption_json_to_srt(&json!({"code": 0})).is_err());
+    }
+
+    fn subtitle_item(source_format: &str) -> FileInfo {
+        FileInfo {
+            filepath: "lesson.srt".into(),
+            source_format: Some(source_format.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_subtitle_content_follows_source_format() {
+        // ASS 字幕以 "[Script Info]" 开头，不应被当作 JSON 解析
+        let ass = b"[Script Info]\nTitle: lesson\n\n[Events]\n";
+        let content = subtitle_file_content(&subtitle_item("ass"), ass).unwrap();
+        assert_eq!(content.as_ref(), ass);
+
+        // 带 BOM 的平台 JSON 字幕仍然转换为 SRT
+        let json = "\u{feff}[{\"start\": 1, \"end\": 2, \"text\": \"hi\"}]";
+        let content = subtitle_file_content(&subtitle_item("json"), json.as_bytes()).unwrap();
+        assert_eq!(content.as_ref(), b"1\n00:00:01,000 --> 00:00:02,000\nhi\n\n");
+    }
+}
@@ -1,7 +1,7 @@
 // src/downloader/task_processor.rs
 
-use super::m3u8::M3u8Downloader;
-use crate::{DownloadJobContext, cli::Cli, error::*, models::*, utils};
+use super::{m3u8::M3u8Downloader, state::DownloadStateStore, subtitle::SubtitleDownloader};
+use crate::{DownloadJobContext, cli::Cli, constants, error::*, models::*, utils};
 use futures::StreamExt;
 use indicatif::{HumanBytes, ProgressBar};
 use log::{debug, error, info, warn};
@@ -42,7 +42,7 @@ impl TaskProcessor {
                 fs::create_dir_all(parent)?;
             }
             let (action, resume_bytes, reason) =
-                Self::prepare_download_action(&item, &self.context.args)?;
+                Self::prepare_download_action(&item, &self.context.args, &self.context.state)?;
             if action == DownloadAction::Skip {
                 return Ok(DownloadResult {
                     filename: item
@@ -58,6 +58,11 @@ impl TaskProcessor {
             let download_status = match item.category {
                 ResourceCategory::Video => {
                     M3u8Downloader::new(self.context.clone())
+                        .download(&item, pbar, use_byte_progress, action == DownloadAction::Resume)
+                        .await?
+                }
+                ResourceCategory::Subtitle => {
+                    SubtitleDownloader::new(self.context.clone())
                         .download(&item, pbar, use_byte_progress)
                         .await?
                 }
@@ -107,17 +112,57 @@ impl TaskProcessor {
 
     /// 检查本地文件状态，决定是跳过、续传还是重新下载。
     /// 改为 pub(super) 以便 auth 模块可以调用它。
+    /// 优先查询下载状态数据库，命中完整记录时无需读取文件内容即可跳过。
     pub(super) fn prepare_download_action(
         item: &FileInfo,
         args: &Cli,
+        state: &DownloadStateStore,
     ) -> AppResult<(DownloadAction, u64, String)> {
+        let forced_by_id = matches!(
+            (&args.options.force_redownload_ids, &item.resource_id),
+            (Some(ids), Some(resource_id)) if ids.contains(resource_id)
+        );
         if !item.filepath.exists() {
+            // 视频在合并前被中断时，已下载的分片保存在工作目录中，可从中续传
+            if item.category == ResourceCategory::Video
+                && !args.options.force_redownload
+                && !forced_by_id
+                && M3u8Downloader::has_partial_segments(item)
+            {
+                return Ok((
+                    DownloadAction::Resume,
+                    0,
+                    "存在未完成的视频分片，继续下载".to_string(),
+                ));
+            }
             return Ok((DownloadAction::DownloadNew, 0, "文件不存在".to_string()));
         }
-        if args.force_redownload {
+        if args.options.force_redownload {
             info!("用户强制重新下载文件: {:?}", item.filepath);
             return Ok((DownloadAction::DownloadNew, 0, "强制重新下载".to_string()));
         }
+        if forced_by_id {
+            info!(
+                "资源 '{}' 被指定强制重新下载: {:?}",
+                item.resource_id.as_deref().unwrap_or_default(),
+                item.filepath
+            );
+            return Ok((DownloadAction::DownloadNew, 0, "指定资源强制重新下载".to_string()));
+        }
+        if let Some(record) = state.get(&item.filepath) {
+            if record.is_complete_for(item) {
+                debug!("下载记录命中，跳过校验: {:?}", item.filepath);
+                return Ok((DownloadAction::Skip, 0, "下载记录显示已完成".to_string()));
+            }
+            if matches!(record.status, DownloadStatus::Md5Failed | DownloadStatus::SizeFailed) {
+                let (_, _, msg) = record.status.get_display_info();
+                return Ok((
+                    DownloadAction::DownloadNew,
+                    0,
+                    format!("上次下载{}，重新下载", msg),
+                ));
+            }
+        }
         match Self::check_local_file_status(item)? {
             ValidationStatus::Valid => {
                 Ok((DownloadAction::Skip, 0, "文件已存在且校验通过".to_string()))
@@ -190,6 +235,10 @@ impl TaskProcessor {
         if let Some(expected_size) = item.ti_size {
             // --- 区分文件类型 ---
             let is_video = item.category == ResourceCategory::Video;
+            let is_mp4 = item
+                .filepath
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("mp4"));
             let tolerance = if is_video {
                 // M3U8 视频，应用 1% 的容差
                 (expected_size as f64 * 0.01) as u64
@@ -197,18 +246,30 @@ impl TaskProcessor {
                 // 普通文件，无容差
                 0
             };
+            // 平台给出的是 TS 的大小，转封装为 MP4 后去掉了 TS 包头开销，文件会明显变小
+            let lower_tolerance = if is_video && is_mp4 {
+                (expected_size as f64 * constants::MP4_SIZE_SHRINK_TOLERANCE) as u64
+            } else {
+                tolerance
+            };
 
             let diff = (actual_size as i64 - expected_size as i64).unsigned_abs();
+            let allowed = if actual_size < expected_size {
+                lower_tolerance
+            } else {
+                tolerance
+            };
 
             debug!(
                 "大小校验详情 for '{:?}': 差异={}, 容差={}",
                 item.filepath.file_name(),
                 HumanBytes(diff),
-                HumanBytes(tolerance)
+                HumanBytes(allowed)
             );
 
-            if diff > tolerance {
-                if actual_size < expected_size {
+            if diff > allowed {
+                // 合并后的视频无法按字节续传 (续传由分片工作目录负责)，大小不符只能重新下载
+                if actual_size < expected_size && !is_video {
                     // 差异超出容差，且文件不完整，可续传
                     return Ok(ValidationStatus::CanResume(actual_size));
                 } else {
@@ -69,12 +69,22 @@ async fn run_single_concurrent_task(
 
     match result {
         Ok(result) => {
+            // 写入下载状态数据库
+            context.state.record(&task, result.status);
+            ui::emit_event(&OutputEvent::FileResult {
+                filepath: &task.filepath,
+                resource_id: task.resource_id.as_deref(),
+                category: task.category,
+                result: &result,
+            });
+
             // 更新统计数据
             match result.status {
                 DownloadStatus::Success | DownloadStatus::Resumed => {
-                    context.manager.record_success()
+                    context.manager.record_success(&task.filepath)
                 }
                 DownloadStatus::Skipped => context.manager.record_skip(
+                    &task.filepath,
                     &result.filename,
                     result.message.as_deref().unwrap_or("文件已存在"),
                 ),
@@ -117,6 +127,16 @@ async fn run_single_concurrent_task(
                 context
                     .manager
                     .record_failure(&task_name, DownloadStatus::TokenError);
+                ui::emit_event(&OutputEvent::FileResult {
+                    filepath: &task.filepath,
+                    resource_id: task.resource_id.as_deref(),
+                    category: task.category,
+                    result: &DownloadResult {
+                        filename: task_name.to_string(),
+                        status: DownloadStatus::TokenError,
+                        message: Some(e.to_string()),
+                    },
+                });
                 *error_lock = Some(e);
             }
         }
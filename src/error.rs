@@ -1,5 +1,6 @@
 // src/error.rs
 
+use crate::constants::exit_codes;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -8,6 +9,8 @@ pub enum AppError {
     TokenInvalid,
     #[error("未提供 Access Token，无法进行下载")]
     TokenMissing,
+    #[error("Access Token 将于 {0} 过期，可能无法完成本次下载 (可使用 --ignore-token-expiry 继续)")]
+    TokenExpiringSoon(String),
     #[error("网络请求失败: {0}")]
     Network(#[from] reqwest::Error),
     #[error("网络中间件错误: {0}")]
@@ -32,10 +35,14 @@ pub enum AppError {
     M3u8Parse(String),
     #[error("视频分片合并失败: {0}")]
     Merge(String),
+    #[error("字幕处理失败: {0}")]
+    Subtitle(String),
     #[error("文件校验失败: {0}")]
     Validation(String),
     #[error("安全错误: {0}")]
     Security(String),
+    #[error("{0} 个任务元数据解析失败。")]
+    MetadataFailed(usize),
     #[error("用户中断")]
     UserInterrupt,
     #[error("{0}")] // 只打印内部信息，不加任何前缀
@@ -44,4 +51,18 @@ pub enum AppError {
     Other(#[from] anyhow::Error),
 }
 
+impl AppError {
+    /// 该错误导致程序退出时应使用的进程退出码。
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            AppError::UserInterrupt => exit_codes::INTERRUPTED,
+            AppError::TokenInvalid | AppError::TokenMissing | AppError::TokenExpiringSoon(_) => {
+                exit_codes::AUTH_FAILED
+            }
+            AppError::MetadataFailed(_) => exit_codes::METADATA_FAILED,
+            _ => exit_codes::ERROR,
+        }
+    }
+}
+
 pub type AppResult<T> = Result<T, AppError>;
@@ -0,0 +1,158 @@
+// src/extractor/catalog.rs
+
+use crate::{
+    cli::CatalogFilter,
+    client::RobustClient,
+    config::AppConfig,
+    error::*,
+    models::{
+        CatalogEntry, MetadataTag,
+        api::{CatalogVersionResponse, TextbookCatalogItem},
+    },
+};
+use anyhow::anyhow;
+use futures::future::try_join_all;
+use itertools::Itertools;
+use log::{debug, info};
+use std::sync::Arc;
+
+/// 平台公开的教材目录：先获取版本信息，再并发获取各个目录分片
+pub struct TextbookCatalog {
+    http_client: Arc<RobustClient>,
+    config: Arc<AppConfig>,
+}
+
+impl TextbookCatalog {
+    pub fn new(http_client: Arc<RobustClient>, config: Arc<AppConfig>) -> Self {
+        Self {
+            http_client,
+            config,
+        }
+    }
+
+    /// 获取完整的教材目录，按 ID 去重并保持目录中的顺序
+    pub async fn fetch_entries(&self) -> AppResult<Vec<CatalogEntry>> {
+        let url_template = self
+            .config
+            .url_templates
+            .get("TEXTBOOK_CATALOG")
+            .ok_or_else(|| AppError::Other(anyhow!("配置文件中缺少 TEXTBOOK_CATALOG URL 模板")))?;
+        let version: CatalogVersionResponse = self.http_client.fetch_json(url_template, &[]).await?;
+        let part_urls: Vec<&str> = version
+            .urls
+            .split(',')
+            .map(str::trim)
+            .filter(|u| !u.is_empty())
+            .collect();
+        debug!("教材目录共 {} 个分片: {:?}", part_urls.len(), part_urls);
+
+        let parts: Vec<Vec<TextbookCatalogItem>> = try_join_all(
+            part_urls
+                .iter()
+                .map(|url| self.http_client.fetch_json(url, &[])),
+        )
+        .await?;
+        let entries: Vec<CatalogEntry> = parts
+            .into_iter()
+            .flatten()
+            .map(CatalogEntry::from)
+            .unique_by(|e| e.id.clone())
+            .collect();
+        info!("教材目录共包含 {} 本教材", entries.len());
+        Ok(entries)
+    }
+}
+
+impl From<TextbookCatalogItem> for CatalogEntry {
+    fn from(item: TextbookCatalogItem) -> Self {
+        let title = item
+            .global_title
+            .map(|t| t.zh_cn)
+            .or(item.title)
+            .unwrap_or_else(|| item.id.clone());
+        Self {
+            id: item.id,
+            title,
+            tags: item.tag_list.iter().flatten().map(MetadataTag::from).collect(),
+        }
+    }
+}
+
+/// 按筛选条件过滤目录。各维度按标签名包含匹配；关键字匹配标题或任意标签名。
+pub fn filter_entries(entries: Vec<CatalogEntry>, filter: &CatalogFilter) -> Vec<CatalogEntry> {
+    let contains = |haystack: &str, needle: &str| {
+        haystack.to_lowercase().contains(&needle.trim().to_lowercase())
+    };
+    let dimension_filters = filter.dimension_filters();
+    entries
+        .into_iter()
+        .filter(|entry| {
+            dimension_filters.iter().all(|(dimension, _, wanted)| match wanted {
+                Some(wanted) => entry.tag_name(dimension).is_some_and(|name| contains(name, wanted)),
+                None => true,
+            })
+        })
+        .filter(|entry| match &filter.keyword {
+            Some(keyword) => {
+                contains(&entry.title, keyword) || entry.tags.iter().any(|t| contains(&t.name, keyword))
+            }
+            None => true,
+        })
+        .collect()
+}
+
+/// 列出目录中某个维度出现过的所有标签名 (按首次出现的顺序)，用于交互式逐级筛选
+pub fn dimension_values(entries: &[CatalogEntry], dimension_id: &str) -> Vec<String> {
+    entries
+        .iter()
+        .filter_map(|e| e.tag_name(dimension_id))
+        .unique()
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::api::dimensions::*;
+
+    fn entry(id: &str, title: &str, tags: &[(&str, &str)]) -> CatalogEntry {
+        CatalogEntry {
+            id: id.to_string(),
+            title: title.to_string(),
+            tags: tags
+                .iter()
+                .map(|(dimension_id, name)| MetadataTag {
+                    dimension_id: dimension_id.to_string(),
+                    name: name.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_filter_entries_by_dimensions_and_keyword() {
+        let entries = vec![
+            entry("a", "语文一年级上册", &[(STAGE, "小学"), (GRADE, "一年级"), (SUBJECT, "语文"), (VERSION, "统编版")]),
+            entry("b", "数学一年级上册", &[(STAGE, "小学"), (GRADE, "一年级"), (SUBJECT, "数学"), (VERSION, "人教版")]),
+            entry("c", "English Grade 7", &[(STAGE, "初中"), (GRADE, "七年级"), (SUBJECT, "英语"), (VERSION, "人教版")]),
+        ];
+        let ids = |filter: CatalogFilter| -> Vec<String> {
+            filter_entries(entries.clone(), &filter).into_iter().map(|e| e.id).collect()
+        };
+
+        assert_eq!(ids(CatalogFilter::default()), ["a", "b", "c"]);
+        assert_eq!(
+            ids(CatalogFilter { stage: Some("小学".into()), version: Some("人教".into()), ..Default::default() }),
+            ["b"]
+        );
+        // 关键字同时匹配标题 (不区分大小写) 和标签
+        assert_eq!(ids(CatalogFilter { keyword: Some("english".into()), ..Default::default() }), ["c"]);
+        assert_eq!(ids(CatalogFilter { keyword: Some("统编".into()), ..Default::default() }), ["a"]);
+        // 缺少对应维度标签的教材不匹配该维度的筛选条件
+        assert!(ids(CatalogFilter { volume: Some("上册".into()), ..Default::default() }).is_empty());
+
+        assert_eq!(dimension_values(&entries, STAGE), ["小学", "初中"]);
+        assert_eq!(dimension_values(&entries, VERSION), ["统编版", "人教版"]);
+    }
+}
@@ -5,36 +5,61 @@ use dashmap::DashMap;
 use log::{debug, warn};
 use serde_json::Value;
 use std::{path::PathBuf, sync::Arc};
+use tokio::sync::OnceCell;
+
+/// 章节树中的一个节点
+#[derive(Debug, Clone)]
+pub struct ChapterNode {
+    pub id: String,
+    /// 从根到该节点的各级章节标题 (已做文件名清理)
+    pub path: Vec<String>,
+}
+
+/// 按树 ID 缓存的章节树 JSON，存放在 `DownloadJobContext` 中供一次运行内的所有资源共享。
+/// 每个树 ID 对应一个 `OnceCell`，并发解析的资源只会有一个真正发出请求，其余等待其结果。
+pub type ChapterTreeCache = DashMap<String, Arc<OnceCell<Value>>>;
 
 pub struct ChapterTreeResolver {
     http_client: Arc<RobustClient>,
     config: Arc<AppConfig>,
-    cache: DashMap<String, Value>,
+    cache: Arc<ChapterTreeCache>,
 }
 
 impl ChapterTreeResolver {
     pub fn new(http_client: Arc<RobustClient>, config: Arc<AppConfig>) -> Self {
+        Self::with_cache(http_client, config, Arc::default())
+    }
+
+    pub fn with_cache(
+        http_client: Arc<RobustClient>,
+        config: Arc<AppConfig>,
+        cache: Arc<ChapterTreeCache>,
+    ) -> Self {
         Self {
             http_client,
             config,
-            cache: DashMap::new(),
+            cache,
         }
     }
 
     async fn get_tree_data(&self, tree_id: &str) -> AppResult<Value> {
-        if let Some(entry) = self.cache.get(tree_id) {
+        // 先克隆出 OnceCell 再等待，避免跨 await 持有 DashMap 的分片锁
+        let cell = self.cache.entry(tree_id.to_string()).or_default().clone();
+        if let Some(data) = cell.get() {
             debug!("章节树缓存命中: {}", tree_id);
-            return Ok(entry.value().clone());
+            return Ok(data.clone());
         }
-        debug!("章节树缓存未命中，从网络获取: {}", tree_id);
-        let url_template = self.config.url_templates.get("CHAPTER_TREE").unwrap();
-        let data: Value = self
-            .http_client
-            .fetch_json(url_template, &[("tree_id", tree_id)])
+        // 获取失败时 OnceCell 保持为空，后续请求会重新尝试
+        let data = cell
+            .get_or_try_init(|| async {
+                debug!("章节树缓存未命中，从网络获取: {}", tree_id);
+                let url_template = self.config.url_templates.get("CHAPTER_TREE").unwrap();
+                self.http_client
+                    .fetch_json::<Value>(url_template, &[("tree_id", tree_id)])
+                    .await
+            })
             .await?;
-
-        self.cache.insert(tree_id.to_string(), data.clone());
-        Ok(data)
+        Ok(data.clone())
     }
 
     pub async fn get_full_chapter_path(
@@ -49,15 +74,10 @@ impl ChapterTreeResolver {
             tree_id, lesson_node_id
         );
 
-        let nodes_to_search =
-            if let Some(nodes) = tree_data.get("child_nodes").and_then(|v| v.as_array()) {
-                nodes
-            } else if let Some(nodes) = tree_data.as_array() {
-                nodes
-            } else {
-                warn!("章节树 '{}' 结构未知或为空", tree_id);
-                return Ok(PathBuf::new());
-            };
+        let Some(nodes_to_search) = root_nodes(&tree_data) else {
+            warn!("章节树 '{}' 结构未知或为空", tree_id);
+            return Ok(PathBuf::new());
+        };
 
         if let Some(path) = self.find_path_in_tree(nodes_to_search, lesson_node_id, vec![]) {
             let path_buf: PathBuf = path.iter().collect();
@@ -72,6 +92,19 @@ impl ChapterTreeResolver {
         }
     }
 
+    /// 按先序遍历 (即目录顺序) 列出章节树中的所有节点及其完整路径
+    pub async fn list_chapters(&self, tree_id: &str) -> AppResult<Vec<ChapterNode>> {
+        let tree_data = self.get_tree_data(tree_id).await?;
+        let Some(nodes) = root_nodes(&tree_data) else {
+            warn!("章节树 '{}' 结构未知或为空", tree_id);
+            return Ok(vec![]);
+        };
+        let mut chapters = Vec::new();
+        collect_chapters(nodes, &[], &mut chapters);
+        debug!("章节树 '{}' 共有 {} 个节点", tree_id, chapters.len());
+        Ok(chapters)
+    }
+
     #[allow(clippy::only_used_in_recursion)]
     fn find_path_in_tree(
         &self,
@@ -98,3 +131,65 @@ impl ChapterTreeResolver {
         None
     }
 }
+
+/// 章节树 JSON 的顶层节点：可能位于 `child_nodes` 字段，也可能顶层即为节点数组
+fn root_nodes(tree_data: &Value) -> Option<&Vec<Value>> {
+    tree_data
+        .get("child_nodes")
+        .and_then(|v| v.as_array())
+        .or_else(|| tree_data.as_array())
+}
+
+fn collect_chapters(nodes: &[Value], parent_path: &[String], chapters: &mut Vec<ChapterNode>) {
+    for node in nodes {
+        let title = node
+            .get("title")
+            .and_then(|v| v.as_str())
+            .unwrap_or("未知章节");
+        let mut path = parent_path.to_vec();
+        path.push(utils::sanitize_filename(title));
+        if let Some(id) = node.get("id").and_then(|v| v.as_str()) {
+            chapters.push(ChapterNode {
+                id: id.to_string(),
+                path: path.clone(),
+            });
+        }
+        if let Some(child_nodes) = node.get("child_nodes").and_then(|v| v.as_array()) {
+            collect_chapters(child_nodes, &path, chapters);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_concurrent_lookups_fetch_tree_once() {
+        let mut server = mockito::Server::new_async().await;
+        let tree_mock = server
+            .mock("GET", "/trees/tree-1.json")
+            .with_body(r#"{"child_nodes": [{"id": "unit-1", "title": "第一单元"}]}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        let mut config = AppConfig::default();
+        config.url_templates.insert(
+            "CHAPTER_TREE".to_string(),
+            format!("{}/trees/{{tree_id}}.json", server.url()),
+        );
+        let config = Arc::new(config);
+        let client = Arc::new(RobustClient::new(config.clone()).unwrap());
+        let cache: Arc<ChapterTreeCache> = Arc::default();
+
+        // 模拟批量解析时多个资源同时查询同一棵章节树
+        let lookups = (0..8).map(|_| {
+            let resolver = ChapterTreeResolver::with_cache(client.clone(), config.clone(), cache.clone());
+            tokio::spawn(async move { resolver.get_full_chapter_path("tree-1", "tree-1/unit-1").await })
+        });
+        for path in futures::future::join_all(lookups).await {
+            assert_eq!(path.unwrap().unwrap(), PathBuf::from("第一单元"));
+        }
+        tree_mock.assert_async().await;
+    }
+}
@@ -2,21 +2,25 @@
 
 use crate::{
     client::RobustClient,
-    config::AppConfig,
+    config::{AppConfig, DirectoryStructureConfig},
+    constants::api::dimensions,
     error::AppResult,
     extractor::{chapter_resolver::ChapterTreeResolver, textbook::TextbookExtractor},
     models::api::Tag,
-    // utils,
+    utils,
     DownloadJobContext,
 };
 use async_trait::async_trait;
-use std::{
-    path::{
-        // Path,
-        PathBuf
-    },
-    sync::Arc,
-};
+use std::{path::PathBuf, sync::Arc};
+
+/// 目录模板中可用的标签维度占位符
+const DIMENSION_PLACEHOLDERS: [(&str, &str); 5] = [
+    ("stage", dimensions::STAGE),
+    ("grade", dimensions::GRADE),
+    ("subject", dimensions::SUBJECT),
+    ("edition", dimensions::VERSION),
+    ("volume", dimensions::VOLUME),
+];
 
 /// 一个Trait，定义了能够构建基于教材和章节的深度嵌套目录的通用能力。
 #[async_trait]
@@ -27,34 +31,216 @@ pub trait DirectoryBuilder {
     fn get_tags(&self) -> Option<&[Tag]>;
     /// 获取资源的章节路径信息
     fn get_chapter_info(&self) -> Option<(&str, &str)>; // -> Option<(tree_id, chapter_path)>
+    /// 获取资源的授课教师
+    fn get_teacher_names(&self) -> Vec<&str>;
+    /// 获取该类资源的目录模板
+    fn get_path_template<'a>(&self, config: &'a DirectoryStructureConfig) -> &'a str;
 
-    /// 构建基础目录的核心实现
+    /// 解析教材分类路径和章节路径，得到资源的目录布局
     async fn build_base_directory(
         &self,
         context: &DownloadJobContext,
         http_client: Arc<RobustClient>,
         config: Arc<AppConfig>,
-    ) -> AppResult<PathBuf> {
-        if context.args.flat {
-            return Ok(PathBuf::new());
+    ) -> AppResult<DirectoryLayout> {
+        let options = &context.args.options;
+        let dir_config = &config.dir_config;
+        let mut layout = DirectoryLayout {
+            template: self.get_path_template(dir_config).to_string(),
+            flat: options.flat,
+            textbook_path: PathBuf::new(),
+            chapter_dirs: vec![],
+            chapter_names: vec![],
+            values: vec![],
+        };
+        // 平铺模式下不需要目录；只有需要写入元数据时才解析章节
+        if options.flat && options.write_metadata.is_empty() {
+            return Ok(layout);
         }
 
-        let chapter_resolver = ChapterTreeResolver::new(http_client.clone(), config.clone());
+        let chapter_resolver = ChapterTreeResolver::with_cache(
+            http_client.clone(),
+            config.clone(),
+            context.chapter_cache.clone(),
+        );
 
         // 获取教材路径
-        let textbook_path = TextbookExtractor::new(http_client, config)
+        layout.textbook_path = TextbookExtractor::new(http_client, config.clone())
             .build_resource_path(self.get_tags(), context);
 
         // 获取章节路径
-        let mut full_chapter_path = PathBuf::new();
         if let Some((tree_id, path_str)) = self.get_chapter_info()
             && let Ok(path) = chapter_resolver.get_full_chapter_path(tree_id, path_str).await {
-                full_chapter_path = path;
+                layout.chapter_names = path
+                    .iter()
+                    .map(|c| c.to_string_lossy().to_string())
+                    .collect();
+            }
+        layout.chapter_dirs = layout.chapter_names.clone();
+        if let Some(depth) = dir_config.max_chapter_depth {
+            layout.chapter_dirs.truncate(depth);
+        }
+
+        layout.values = dimension_values(self.get_tags().unwrap_or_default(), dir_config);
+        layout.values.extend([
+            ("course".to_string(), self.get_resource_title().to_string()),
+            (
+                "teacher".to_string(),
+                self.get_teacher_names().first().copied().unwrap_or_default().to_string(),
+            ),
+            (
+                "chapter".to_string(),
+                layout.chapter_names.last().cloned().unwrap_or_default(),
+            ),
+        ]);
+        layout.values.extend(
+            layout
+                .chapter_names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| (format!("chapter{}", i + 1), name.clone())),
+        );
+        Ok(layout)
+    }
+}
+
+/// 目录模板中各标签维度占位符的取值。资源缺少某个维度的标签时，与教材分类路径一样
+/// 使用 `textbook_path_defaults` 中的默认名称 (如 "未知版本")，保证同类资源的目录层级一致。
+fn dimension_values(tags: &[Tag], dir_config: &DirectoryStructureConfig) -> Vec<(String, String)> {
+    DIMENSION_PLACEHOLDERS
+        .iter()
+        .map(|(name, dimension_id)| {
+            let value = tags
+                .iter()
+                .find(|t| t.tag_dimension_id == *dimension_id)
+                .map(|t| t.tag_name.clone())
+                .or_else(|| dir_config.textbook_path_defaults.get(*dimension_id).cloned())
+                .unwrap_or_default();
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+/// 课程/同步课堂的目录布局，按目录模板生成资源目录和各课时目录。
+/// 模板中每一级目录单独渲染：`{textbook_path}` 和 `{chapter_path}` 须独占一级并展开为多级目录，
+/// 其余占位符渲染后经过文件名清理，渲染结果为空的一级目录被省略。
+#[derive(Debug, Clone, Default)]
+pub struct DirectoryLayout {
+    template: String,
+    flat: bool,
+    textbook_path: PathBuf,
+    /// 按深度限制截断后的章节目录
+    chapter_dirs: Vec<String>,
+    /// 完整的各级章节标题 (用于元数据)
+    pub chapter_names: Vec<String>,
+    values: Vec<(String, String)>,
+}
+
+impl DirectoryLayout {
+    /// 资源级别的目录：精品课的文件和资源元数据文件都保存在这里
+    pub fn resource_dir(&self) -> PathBuf {
+        if self.flat {
+            return PathBuf::new();
+        }
+        self.render(&[("lesson", "")])
+    }
+
+    /// 同步课堂中某个课时的目录；平铺模式下仍按课时分开保存
+    pub fn lesson_dir(&self, lesson: &str, teacher: &str) -> PathBuf {
+        if self.flat {
+            return PathBuf::from(utils::sanitize_filename(lesson));
+        }
+        self.render(&[("lesson", lesson), ("teacher", teacher)])
+    }
+
+    fn render(&self, extra: &[(&str, &str)]) -> PathBuf {
+        let values: Vec<(&str, &str)> = extra
+            .iter()
+            .copied()
+            .chain(self.values.iter().map(|(k, v)| (k.as_str(), v.as_str())))
+            .collect();
+        let mut path = PathBuf::new();
+        for segment in self.template.split('/').map(str::trim).filter(|s| !s.is_empty()) {
+            match segment {
+                "{textbook_path}" => path.push(&self.textbook_path),
+                "{chapter_path}" => path.extend(&self.chapter_dirs),
+                _ => {
+                    let name = utils::render_template(segment, &values);
+                    if !name.trim().is_empty() {
+                        path.push(utils::sanitize_filename(&name));
+                    }
+                }
             }
+        }
+        path
+    }
+}
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn layout(template: &str, max_chapter_depth: Option<usize>) -> DirectoryLayout {
+        let chapter_names: Vec<String> = ["第一单元", "第1课", "第1课时"].map(String::from).to_vec();
+        let mut chapter_dirs = chapter_names.clone();
+        if let Some(depth) = max_chapter_depth {
+            chapter_dirs.truncate(depth);
+        }
+        DirectoryLayout {
+            template: template.to_string(),
+            flat: false,
+            textbook_path: PathBuf::from("小学/一年级/语文"),
+            chapter_dirs,
+            chapter_names,
+            values: vec![
+                ("subject".to_string(), "语文".to_string()),
+                ("grade".to_string(), "一年级".to_string()),
+                ("stage".to_string(), String::new()),
+                ("course".to_string(), "识字: 天地人".to_string()),
+                ("teacher".to_string(), "张老师".to_string()),
+                ("chapter".to_string(), "第1课时".to_string()),
+                ("chapter1".to_string(), "第一单元".to_string()),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_directory_layout_templates() {
+        // 默认模板：教材分类路径 + 章节路径 (+ 课时)
+        let default = layout("{textbook_path}/{chapter_path}/{lesson}", None);
+        assert_eq!(default.resource_dir(), Path::new("小学/一年级/语文/第一单元/第1课/第1课时"));
+        assert_eq!(
+            default.lesson_dir("课时1", "李老师"),
+            Path::new("小学/一年级/语文/第一单元/第1课/第1课时/课时1")
+        );
+
+        // 标签维度、章节层级限制；值为空的一级目录被省略，非法字符被清理
+        let custom = layout("{stage}/{subject}/{grade}/{chapter_path}/{course}", Some(1));
+        assert_eq!(custom.resource_dir(), Path::new("语文/一年级/第一单元/识字 天地人"));
+
+        // 课时目录使用课时自己的教师
+        let by_teacher = layout("{teacher}/{chapter1}/{lesson}", None);
+        assert_eq!(by_teacher.resource_dir(), Path::new("张老师/第一单元"));
+        assert_eq!(by_teacher.lesson_dir("课时1", "李老师"), Path::new("李老师/第一单元/课时1"));
+
+        let flat = DirectoryLayout { flat: true, ..by_teacher };
+        assert_eq!(flat.resource_dir(), PathBuf::new());
+        assert_eq!(flat.lesson_dir("课时1", "李老师"), Path::new("课时1"));
+    }
 
-        // 直接将教材路径和章节路径组合起来
-        let final_path = textbook_path.join(full_chapter_path);
-        Ok(final_path)
+    #[test]
+    fn test_missing_dimensions_use_path_defaults() {
+        let tag = |dimension: &str, name: &str| Tag {
+            tag_dimension_id: dimension.to_string(),
+            tag_name: name.to_string(),
+        };
+        let tags = [tag(dimensions::STAGE, "小学"), tag(dimensions::SUBJECT, "语文")];
+        let values = dimension_values(&tags, &DirectoryStructureConfig::default());
+        let value = |name: &str| values.iter().find(|(k, _)| k == name).unwrap().1.as_str();
+        assert_eq!(value("stage"), "小学");
+        assert_eq!(value("subject"), "语文");
+        assert_eq!(value("grade"), "未知年级");
+        assert_eq!(value("edition"), "未知版本");
     }
-}
\ No newline at end of file
+}
@@ -2,17 +2,18 @@
 
 use super::{
     ResourceExtractor,
-    common::DirectoryBuilder,
+    common::{DirectoryBuilder, DirectoryLayout},
     utils as extractor_utils,
 };
 use crate::{
     DownloadJobContext,
+    cli::DownloadOptions,
     client::RobustClient,
     config::AppConfig,
     constants,
     error::*,
     models::{
-        FileInfo,
+        FileInfo, MetadataTag, ResourceMetadata,
         api::{CourseDetailsResponse, CourseResource},
     },
     ui, utils,
@@ -21,7 +22,7 @@ use async_trait::async_trait;
 use log::{debug, info, trace, warn};
 use std::{
     collections::HashMap,
-    path::{Path, PathBuf},
+    path::Path,
     sync::Arc,
 };
 
@@ -48,7 +49,7 @@ impl CourseExtractor {
         &self,
         data: &CourseDetailsResponse,
         context: &DownloadJobContext,
-    ) -> AppResult<PathBuf> {
+    ) -> AppResult<DirectoryLayout> {
         // 直接调用 data 上已实现的 trait 方法，干净利落
         data.build_base_directory(context, self.http_client.clone(), self.config.clone())
             .await
@@ -61,6 +62,7 @@ impl CourseExtractor {
         course_title: &str,
         base_dir: &Path, // 这是课程的根目录，例如 ".../课程标题/"
         teacher_map: &HashMap<usize, String>,
+        options: &DownloadOptions,
     ) -> Vec<FileInfo> {
         let type_name = utils::sanitize_filename(
             resource
@@ -73,30 +75,74 @@ impl CourseExtractor {
             .get(&index)
             .cloned()
             .unwrap_or_else(|| constants::UNCLASSIFIED_DIR.to_string());
-        let base_name = format!("{} - {}", course_title, &type_name);
+        let fields = extractor_utils::FileNameFields {
+            title: course_title.to_string(),
+            course: course_title.to_string(),
+            alias: type_name,
+            teacher,
+            index: (index + 1).to_string(),
+            ..Default::default()
+        };
+        let templates = &self.config.filename_templates;
 
         match resource.resource_type_code.as_str() {
             constants::api::resource_types::ASSETS_VIDEO => {
-                extractor_utils::extract_video_files(resource, &base_name, base_dir, &teacher)
+                let mut files = extractor_utils::extract_video_files(
+                    resource,
+                    &fields,
+                    base_dir,
+                    &templates.video,
+                    options.video_container.extension(),
+                );
+                files.extend(extractor_utils::extract_subtitle_files(
+                    resource, &fields, base_dir, &templates.subtitle,
+                ));
+                if options.with_covers {
+                    files.extend(
+                        extractor_utils::extract_cover_file(
+                            resource.ti_items.as_deref(),
+                            resource.custom_properties.thumbnails.as_deref(),
+                            base_dir,
+                            |ext| fields.render(&templates.cover, "", resource.update_time, ext),
+                            resource.update_time,
+                        )
+                        .map(|cover| FileInfo {
+                            lesson_title: Some(fields.content_title()),
+                            teacher: Some(fields.teacher.clone()),
+                            ..cover
+                        }),
+                    );
+                }
+                files
             }
             constants::api::resource_types::ASSETS_DOCUMENT
             | constants::api::resource_types::COURSEWARES
             | constants::api::resource_types::LESSON_PLANDESIGN => {
-                if let Some(mut file_info) = extractor_utils::extract_document_file(resource) {
-                    let filename = format!("{} - [{}].pdf", &base_name, &teacher);
-                    file_info.filepath = base_dir.join(filename);
-                    vec![file_info]
-                } else {
+                let files = extractor_utils::extract_document_files(
+                    resource,
+                    &fields,
+                    base_dir,
+                    &templates.document,
+                    &options.document_formats,
+                );
+                if files.is_empty() {
                     info!(
-                        "在资源 '{}' 中未找到可下载的 PDF 版本，跳过。",
-                        &resource.global_title.zh_cn
+                        "在资源 '{}' 中未找到符合格式要求 ({}) 的文档，跳过。",
+                        &resource.global_title.zh_cn,
+                        options.document_formats.join(",")
                     );
-                    vec![]
                 }
+                files
             }
             _ => {
-                info!("跳过不支持的资源类型: {}", resource.resource_type_code);
-                vec![]
+                // 独立的字幕资源也可以下载，其他类型暂不支持
+                let subtitles = extractor_utils::extract_subtitle_files(
+                    resource, &fields, base_dir, &templates.subtitle,
+                );
+                if subtitles.is_empty() {
+                    info!("跳过不支持的资源类型: {}", resource.resource_type_code);
+                }
+                subtitles
             }
         }
     }
@@ -183,7 +229,8 @@ impl ResourceExtractor for CourseExtractor {
 
         let course_title = utils::sanitize_filename(&data.global_title.zh_cn);
 
-        let base_dir = self.get_base_directory(&data, context).await?;
+        let layout = self.get_base_directory(&data, context).await?;
+        let base_dir = layout.resource_dir();
         let teacher_map = self.get_teacher_map(&data);
 
         let all_resources = &data.relations.resources;
@@ -194,7 +241,7 @@ impl ResourceExtractor for CourseExtractor {
             return Ok(vec![]);
         }
         debug!("找到 {} 个相关资源。", all_resources.len());
-        let results: Vec<FileInfo> = all_resources
+        let mut results: Vec<FileInfo> = all_resources
             .iter()
             .enumerate()
             .flat_map(|(index, resource)| {
@@ -204,9 +251,22 @@ impl ResourceExtractor for CourseExtractor {
                     &course_title,
                     &base_dir,
                     &teacher_map,
+                    &context.args.options,
                 )
             })
             .collect();
+        let metadata = ResourceMetadata {
+            resource_id: resource_id.to_string(),
+            resource_type: constants::api::types::QUALITY_COURSE.to_string(),
+            title: data.global_title.zh_cn.clone(),
+            update_time: all_resources.iter().map(|r| r.update_time).max(),
+            tags: data.tag_list.iter().flatten().map(MetadataTag::from).collect(),
+            teachers: data.teacher_list.iter().flatten().map(|t| t.name.clone()).collect(),
+            chapter_path: layout.chapter_names,
+            base_dir,
+            file_stem: course_title,
+        };
+        extractor_utils::attach_metadata(&mut results, metadata);
         info!("为课程 '{}' 提取到 {} 个文件", resource_id, results.len());
         Ok(results)
     }
@@ -215,8 +275,16 @@ impl ResourceExtractor for CourseExtractor {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{cli::Cli, models::ResourceCategory};
+    use clap::Parser;
     use serde_json::json;
 
+    // --- 辅助函数：解析命令行下载选项 ---
+    fn parse_options(extra_args: &[&str]) -> DownloadOptions {
+        let args = ["sed-dl", "--id", "res1", "--type", "qualityCourse"];
+        Cli::parse_from(args.iter().chain(extra_args)).options
+    }
+
     // --- 辅助函数：创建一个用于测试的 CourseExtractor 实例 ---
     fn create_test_extractor() -> CourseExtractor {
         let config = Arc::new(AppConfig::default());
@@ -318,4 +386,179 @@ mod tests {
 
         assert!(teacher_map.is_empty());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_process_single_resource_document_format_preference() {
+        let extractor = create_test_extractor();
+        let resource: CourseResource = serde_json::from_value(json!({
+            "id": "res1",
+            "global_title": {"zh-CN": "课件"},
+            "resource_type_code": "coursewares",
+            "update_time": "2024-01-01T12:00:00+08:00",
+            "custom_properties": {"alias_name": "课件"},
+            "ti_items": [
+                { "ti_format": "jpg", "ti_file_flag": "thumbnail", "ti_storages": ["https://a/thumb.jpg"] },
+                { "ti_format": "pptx", "ti_storages": ["https://a/slides.pptx"], "ti_size": 100 },
+                { "ti_format": "pdf", "ti_file_flag": "pdf", "ti_storages": ["https://a/slides.pdf"] },
+                { "ti_format": "bin", "ti_storages": ["https://a/source.docx?v=1"] }
+            ]
+        }))
+        .unwrap();
+        let teacher_map = HashMap::from([(0, "张老师".to_string())]);
+        let extract = |formats: &[&str]| -> Vec<String> {
+            let options = parse_options(&["--document-formats", &formats.join(",")]);
+            extractor
+                .process_single_resource(&resource, 0, "课程", Path::new("out"), &teacher_map, &options)
+                .into_iter()
+                .map(|f| f.filepath.to_string_lossy().replace('\\', "/"))
+                .collect()
+        };
+
+        assert_eq!(extract(&["pdf", "*"]), ["out/课程 - 课件 - [张老师].pdf"]);
+        assert_eq!(extract(&["pptx", "pdf"]), ["out/课程 - 课件 - [张老师].pptx"]);
+        // 偏好的格式都不存在时不下载；bin 条目按 URL 识别真实扩展名；缩略图被排除
+        assert!(extract(&["zip"]).is_empty());
+        assert_eq!(
+            extract(&["all"]),
+            [
+                "out/课程 - 课件 - [张老师].pptx",
+                "out/课程 - 课件 - [张老师].pdf",
+                "out/课程 - 课件 - [张老师].docx"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_process_single_resource_with_covers() {
+        let extractor = create_test_extractor();
+        let resource: CourseResource = serde_json::from_value(json!({
+            "id": "res1",
+            "global_title": {"zh-CN": "视频"},
+            "resource_type_code": "assets_video",
+            "update_time": "2024-01-01T12:00:00+08:00",
+            "custom_properties": {
+                "alias_name": "视频",
+                "thumbnails": ["https://a/lesson.png?v=2"]
+            },
+            "ti_items": [
+                { "ti_format": "m3u8", "ti_storages": ["https://a/video.m3u8"] }
+            ]
+        }))
+        .unwrap();
+        let teacher_map = HashMap::from([(0, "张老师".to_string())]);
+        let extract = |extra_args: &[&str]| -> Vec<(String, ResourceCategory)> {
+            extractor
+                .process_single_resource(&resource, 0, "课程", Path::new("out"), &teacher_map, &parse_options(extra_args))
+                .into_iter()
+                .map(|f| (f.filepath.to_string_lossy().replace('\\', "/"), f.category))
+                .collect()
+        };
+
+        // 默认不下载缩略图
+        assert_eq!(extract(&[]).len(), 1);
+        let files = extract(&["--with-covers"]);
+        assert_eq!(
+            files.last().unwrap(),
+            &("out/课程 - 视频 - [张老师].png".to_string(), ResourceCategory::Image)
+        );
+
+        // 带有封面标记的条目优先于 thumbnails 字段
+        let mut resource = resource.clone();
+        resource.ti_items.as_mut().unwrap().push(
+            serde_json::from_value(json!({
+                "ti_format": "jpg", "ti_file_flag": "cover", "ti_storages": ["https://a/cover"], "ti_size": 10
+            }))
+            .unwrap(),
+        );
+        let cover = extractor
+            .process_single_resource(&resource, 0, "课程", Path::new("out"), &teacher_map, &parse_options(&["--with-covers"]))
+            .pop()
+            .unwrap();
+        assert_eq!(cover.url, "https://a/cover");
+        assert_eq!(cover.ti_size, Some(10));
+        assert!(cover.filepath.to_string_lossy().ends_with("[张老师].jpg"));
+    }
+
+    #[test]
+    fn test_process_single_resource_uses_filename_templates() {
+        let mut config = AppConfig::default();
+        config.filename_templates.video = "{index}. {teacher}/{alias} {quality}p {date}".to_string();
+        config.filename_templates.subtitle = "{course}-{alias}.{ext}".to_string();
+        let config = Arc::new(config);
+        let extractor = CourseExtractor::new(
+            Arc::new(RobustClient::new(config.clone()).unwrap()),
+            config,
+            "dummy_template".to_string(),
+        );
+        let resource: CourseResource = serde_json::from_value(json!({
+            "id": "res1",
+            "global_title": {"zh-CN": "视频"},
+            "resource_type_code": "assets_video",
+            "update_time": "2024-01-01T12:00:00+08:00",
+            "custom_properties": {"alias_name": "课堂录像"},
+            "ti_items": [
+                {
+                    "ti_format": "m3u8",
+                    "ti_storages": ["https://a/video.m3u8"],
+                    "custom_properties": {"requirements": [{"name": "Height", "value": "720"}]}
+                },
+                { "ti_format": "srt", "ti_storages": ["https://a/video.srt"] }
+            ]
+        }))
+        .unwrap();
+        let teacher_map = HashMap::from([(0, "张老师".to_string())]);
+        let files: Vec<String> = extractor
+            .process_single_resource(&resource, 0, "课程", Path::new("out"), &teacher_map, &parse_options(&[]))
+            .into_iter()
+            .map(|f| f.filepath.to_string_lossy().replace('\\', "/"))
+            .collect();
+
+        // 渲染结果经过文件名清理 (路径分隔符被替换)；模板中没有 {ext} 时自动追加扩展名
+        assert_eq!(
+            files,
+            [
+                "out/1. 张老师 课堂录像 720p 2024-01-01.ts",
+                "out/课程-课堂录像.srt"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_video_template_renders_container_extension() {
+        let mut config = AppConfig::default();
+        config.filename_templates.video = "{title}.{ext} [{quality}]".to_string();
+        let config = Arc::new(config);
+        let extractor = CourseExtractor::new(
+            Arc::new(RobustClient::new(config.clone()).unwrap()),
+            config,
+            "dummy_template".to_string(),
+        );
+        let resource: CourseResource = serde_json::from_value(json!({
+            "id": "res1",
+            "global_title": {"zh-CN": "视频"},
+            "resource_type_code": "assets_video",
+            "update_time": "2024-01-01T12:00:00+08:00",
+            "custom_properties": {"alias_name": "课堂录像"},
+            "ti_items": [{
+                "ti_format": "m3u8",
+                "ti_storages": ["https://a/video.m3u8"],
+                "custom_properties": {"requirements": [{"name": "Height", "value": "720"}]}
+            }]
+        }))
+        .unwrap();
+        let options = parse_options(&["--video-container", "mp4"]);
+        let files = extractor.process_single_resource(
+            &resource,
+            0,
+            "课程",
+            Path::new("out"),
+            &HashMap::new(),
+            &options,
+        );
+
+        // {ext} 不在末尾时也应渲染为最终的容器扩展名，而不是事后修改路径的扩展名
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].filepath, Path::new("out").join("课程.mp4 [720]"));
+        assert_eq!(files[0].format.as_deref(), Some("mp4"));
+    }
+}
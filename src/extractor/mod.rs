@@ -1,10 +1,12 @@
 // src/extractor/mod.rs
 
+pub mod catalog;
 pub mod chapter_resolver;
 pub mod course;
 pub mod common;
 pub mod sync_classroom;
 pub mod textbook;
+pub mod textbook_lessons;
 mod utils;
 
 use crate::{DownloadJobContext, error::*, models::FileInfo};
@@ -1,14 +1,19 @@
 // src/extractor/sync_classroom.rs
 
-use super::{common::DirectoryBuilder, ResourceExtractor, utils as extractor_utils};
+use super::{
+    common::DirectoryBuilder,
+    utils::{self as extractor_utils, FileNameFields},
+    ResourceExtractor,
+};
 use crate::{
+    cli::DownloadOptions,
     client::RobustClient,
     config::AppConfig,
     constants,
     error::*,
     models::{
         api::{CourseResource, SyncClassroomResponse},
-        FileInfo,
+        FileInfo, MetadataTag, ResourceMetadata,
     },
     ui, utils, DownloadJobContext,
 };
@@ -22,17 +27,19 @@ use std::{
 
 pub struct SyncClassroomExtractor {
     http_client: Arc<RobustClient>,
+    config: Arc<AppConfig>,
     url_template: String,
 }
 
 impl SyncClassroomExtractor {
     pub fn new(
         http_client: Arc<RobustClient>,
-        _config: Arc<AppConfig>, // _config 标记为未使用
+        config: Arc<AppConfig>,
         url_template: String,
     ) -> Self {
         Self {
             http_client,
+            config,
             url_template,
         }
     }
@@ -40,35 +47,76 @@ impl SyncClassroomExtractor {
     fn process_resource(
         &self,
         resource: &CourseResource,
-        base_name_prefix: &str, // 接收课程标题[课时标题]作为前缀
-        lesson_path: &Path,    // 接收课时子目录
-        teacher_name: &str,
+        lesson_fields: &FileNameFields, // 课时级别的占位符取值 (课程标题[课时标题]、教师等)
+        lesson_path: &Path,             // 接收课时子目录
+        options: &DownloadOptions,
     ) -> Vec<FileInfo> {
-        let alias = utils::sanitize_filename(
-            resource.custom_properties.alias_name.as_deref().unwrap_or("资源"),
-        );
-
-        // 新的文件名基础：课程标题[课时标题] - 资源别名
-        let base_name = format!("{} - {}", base_name_prefix, &alias);
+        let fields = FileNameFields {
+            alias: utils::sanitize_filename(
+                resource.custom_properties.alias_name.as_deref().unwrap_or("资源"),
+            ),
+            ..lesson_fields.clone()
+        };
+        let templates = &self.config.filename_templates;
 
         match resource.resource_type_code.as_str() {
             constants::api::resource_types::ASSETS_VIDEO => {
-                // 将拼接好的 base_name 传递给下游
-                extractor_utils::extract_video_files(resource, &base_name, lesson_path, teacher_name)
+                let mut files = extractor_utils::extract_video_files(
+                    resource,
+                    &fields,
+                    lesson_path,
+                    &templates.video,
+                    options.video_container.extension(),
+                );
+                files.extend(extractor_utils::extract_subtitle_files(
+                    resource,
+                    &fields,
+                    lesson_path,
+                    &templates.subtitle,
+                ));
+                if options.with_covers {
+                    files.extend(
+                        extractor_utils::extract_cover_file(
+                            resource.ti_items.as_deref(),
+                            resource.custom_properties.thumbnails.as_deref(),
+                            lesson_path,
+                            |ext| fields.render(&templates.cover, "", resource.update_time, ext),
+                            resource.update_time,
+                        )
+                        .map(|cover| FileInfo {
+                            lesson_title: Some(fields.content_title()),
+                            teacher: Some(fields.teacher.clone()),
+                            ..cover
+                        }),
+                    );
+                }
+                files
             }
             constants::api::resource_types::ASSETS_DOCUMENT
             | constants::api::resource_types::COURSEWARES
             | constants::api::resource_types::LESSON_PLANDESIGN => {
-                if let Some(mut file_info) = extractor_utils::extract_document_file(resource) {
-                    let filename = format!("{} - [{}].pdf", &base_name, teacher_name);
-                    file_info.filepath = lesson_path.join(filename);
-                    vec![file_info]
-                } else {
-                    info!("在资源 '{}' 中未找到可下载的 PDF 版本，跳过。", &resource.global_title.zh_cn);
-                    vec![]
+                let files = extractor_utils::extract_document_files(
+                    resource,
+                    &fields,
+                    lesson_path,
+                    &templates.document,
+                    &options.document_formats,
+                );
+                if files.is_empty() {
+                    info!(
+                        "在资源 '{}' 中未找到符合格式要求 ({}) 的文档，跳过。",
+                        &resource.global_title.zh_cn,
+                        options.document_formats.join(",")
+                    );
                 }
+                files
             }
-            _ => vec![],
+            _ => extractor_utils::extract_subtitle_files(
+                resource,
+                &fields,
+                lesson_path,
+                &templates.subtitle,
+            ),
         }
     }
 }
@@ -87,7 +135,8 @@ impl ResourceExtractor for SyncClassroomExtractor {
             .await?;
 
         // 1. 调用 Trait 方法，构建课程的根目录 (e.g., .../学科/版本/章节/)
-        let base_dir = data.build_base_directory(context, self.http_client.clone(), context.config.clone()).await?;
+        let layout = data.build_base_directory(context, self.http_client.clone(), context.config.clone()).await?;
+        let base_dir = layout.resource_dir();
 
         let teacher_map: HashMap<&str, &str> = data
             .teacher_list
@@ -105,14 +154,8 @@ impl ResourceExtractor for SyncClassroomExtractor {
 
             for lesson in lessons {
                 let lesson_title = &lesson.title;
-                
-                // 2. 构建课时子目录
-                let lesson_path = base_dir.join(utils::sanitize_filename(lesson_title));
-
-                // 3. 构建文件名前缀
-                let filename_prefix = format!("{}[{}]", &course_main_title, lesson_title);
 
-                // 4. 教师名获取逻辑
+                // 2. 教师名获取逻辑
                 let teacher_name = lesson
                     .custom_properties
                     .teacher_ids
@@ -121,6 +164,18 @@ impl ResourceExtractor for SyncClassroomExtractor {
                     .and_then(|id| teacher_map.get(id.as_str()))
                     .map_or("未知教师", |&name| name);
 
+                // 3. 按目录模板构建课时目录
+                let lesson_path = layout.lesson_dir(lesson_title, teacher_name);
+
+                // 4. 文件名中课时级别的占位符，默认前缀为 "课程标题[课时标题]"
+                let lesson_fields = FileNameFields {
+                    title: format!("{}[{}]", &course_main_title, lesson_title),
+                    course: course_main_title.clone(),
+                    lesson: lesson_title.clone(),
+                    teacher: teacher_name.to_string(),
+                    ..Default::default()
+                };
+
                 let indices: Vec<usize> = lesson
                     .res_ref
                     .as_deref()
@@ -130,13 +185,17 @@ impl ResourceExtractor for SyncClassroomExtractor {
                     .flatten()
                     .collect();
 
-                for index in indices {
+                for (position, index) in indices.into_iter().enumerate() {
                     if let Some(resource) = all_resources.get(index) {
+                        let fields = FileNameFields {
+                            index: (position + 1).to_string(),
+                            ..lesson_fields.clone()
+                        };
                         all_files.extend(self.process_resource(
                             resource,
-                            &filename_prefix,
+                            &fields,
                             &lesson_path,
-                            teacher_name,
+                            &context.args.options,
                         ));
                     }
                 }
@@ -146,18 +205,38 @@ impl ResourceExtractor for SyncClassroomExtractor {
             // 注意：在这种情况下，API直接在资源层级提供了 teacher_name 字段，
             // 这与在课时结构中通过 teacher_ids 查找的逻辑不同。
             ui::warn("警告: 未找到课时结构，所有文件将放在课程根目录。");
-            for resource in all_resources {
+            for (position, resource) in all_resources.iter().enumerate() {
                 let resource_alias = resource.custom_properties.alias_name.as_deref().unwrap_or("未分类资源");
                 let teacher_name = resource.custom_properties.teacher_name.as_deref().unwrap_or("未知教师");
+                let fields = FileNameFields {
+                    title: resource_alias.to_string(),
+                    course: course_main_title.clone(),
+                    teacher: teacher_name.to_string(),
+                    index: (position + 1).to_string(),
+                    ..Default::default()
+                };
                 all_files.extend(self.process_resource(
                     resource,
-                    resource_alias,
+                    &fields,
                     &base_dir,
-                    teacher_name,
+                    &context.args.options,
                 ));
             }
         }
 
+        let metadata = ResourceMetadata {
+            resource_id: resource_id.to_string(),
+            resource_type: constants::api::types::SYNC_CLASSROOM.to_string(),
+            title: data.global_title.zh_cn.clone(),
+            update_time: all_resources.iter().map(|r| r.update_time).max(),
+            tags: data.tag_list.iter().flatten().map(MetadataTag::from).collect(),
+            teachers: data.teacher_list.iter().map(|t| t.name.clone()).collect(),
+            chapter_path: layout.chapter_names,
+            base_dir,
+            file_stem: course_main_title,
+        };
+        extractor_utils::attach_metadata(&mut all_files, metadata);
+
         info!("为同步课堂 '{}' 提取到 {} 个文件", resource_id, all_files.len());
         Ok(all_files)
     }
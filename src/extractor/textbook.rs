@@ -1,6 +1,6 @@
 // src/extractor/textbook.rs
 
-use super::ResourceExtractor;
+use super::{ResourceExtractor, utils as extractor_utils};
 use crate::{
     DownloadJobContext,
     client::RobustClient,
@@ -8,7 +8,7 @@ use crate::{
     constants,
     error::*,
     models::{
-        FileInfo, ResourceCategory,
+        FileInfo, MetadataTag, ResourceCategory, ResourceMetadata,
         api::{AudioRelationItem, Tag, TextbookDetailsResponse},
     },
     utils,
@@ -73,17 +73,27 @@ impl TextbookExtractor {
                 let decoded_filename = percent_encoding::percent_decode(raw_filename.as_bytes())
                     .decode_utf8_lossy()
                     .to_string();
-                let name = if self.is_generic_filename(&decoded_filename) {
-                    let title = data
-                        .global_title
-                        .as_ref()
-                        .map(|t| t.zh_cn.as_str())
-                        .or(data.title.as_deref())
-                        .unwrap_or(&data.id);
-                    format!("{}.pdf", utils::sanitize_filename(title))
+                let title = data
+                    .global_title
+                    .as_ref()
+                    .map(|t| t.zh_cn.as_str())
+                    .or(data.title.as_deref())
+                    .unwrap_or(&data.id);
+                // 文件名模板中的 {title} 默认为 PDF 原文件名，原文件名无意义时使用教材标题
+                let name_title = if self.is_generic_filename(&decoded_filename) {
+                    title.to_string()
                 } else {
-                    utils::sanitize_filename(&decoded_filename)
+                    Path::new(&decoded_filename)
+                        .file_stem()
+                        .map_or_else(|| title.to_string(), |s| s.to_string_lossy().to_string())
                 };
+                let date = data.update_time.format("%Y-%m-%d").to_string();
+                let pdf = constants::api::resource_formats::PDF;
+                let name = utils::render_filename(
+                    &self.config.filename_templates.textbook,
+                    &[("title", &name_title), ("course", title), ("date", &date)],
+                    pdf,
+                );
                 debug!("提取到PDF文件: '{}' @ '{}'", name, url_str);
                 Some(FileInfo {
                     filepath: base_path.join(&name),
@@ -92,6 +102,15 @@ impl TextbookExtractor {
                     ti_size: item.ti_size,
                     date: Some(data.update_time),
                     category: ResourceCategory::Document,
+                    resource_id: Some(data.id.clone()),
+                    lesson_title: Some(title.to_string()),
+                    teacher: None,
+                    quality: None,
+                    format: Some(constants::api::resource_formats::PDF.to_string()),
+                    variant_group: None,
+                    source_format: None,
+                    language: None,
+                    metadata: None,
                 })
             })
             .collect();
@@ -102,6 +121,43 @@ impl TextbookExtractor {
         (results, textbook_basename)
     }
 
+    /// 提取教材封面，与教材 PDF 同名；没有 PDF 时使用教材标题命名
+    fn extract_cover_info(
+        &self,
+        data: &TextbookDetailsResponse,
+        base_path: &Path,
+        textbook_basename: Option<&str>,
+    ) -> Option<FileInfo> {
+        let title = data
+            .global_title
+            .as_ref()
+            .map(|t| t.zh_cn.as_str())
+            .or(data.title.as_deref())
+            .unwrap_or(&data.id);
+        let stem = textbook_basename
+            .map(str::to_string)
+            .unwrap_or_else(|| utils::sanitize_filename(title));
+        let thumbnails = data
+            .custom_properties
+            .as_ref()
+            .and_then(|p| p.thumbnails.as_deref());
+        let cover = extractor_utils::extract_cover_file(
+            data.ti_items.as_deref(),
+            thumbnails,
+            base_path,
+            |ext| format!("{}.{}", stem, ext),
+            data.update_time,
+        );
+        if cover.is_none() {
+            info!("教材 '{}' 没有可用的封面图。", data.id);
+        }
+        cover.map(|f| FileInfo {
+            resource_id: Some(data.id.clone()),
+            lesson_title: Some(title.to_string()),
+            ..f
+        })
+    }
+
     fn is_generic_filename(&self, filename: &str) -> bool {
         let lower_filename = filename.to_lowercase();
         GENERIC_FILENAME_PATTERNS
@@ -112,6 +168,7 @@ impl TextbookExtractor {
     async fn extract_audio_info(
         &self,
         resource_id: &str,
+        textbook_title: &str,
         base_path: PathBuf,
         textbook_basename: Option<String>,
     ) -> AppResult<Vec<FileInfo>> {
@@ -144,7 +201,7 @@ impl TextbookExtractor {
             .flat_map(|(i, item)| {
                 let title = &item.global_title.zh_cn;
                 let index_prefix = format!("{:0width$}", i + 1, width = width);
-                let base_name = format!("[{}] {}", index_prefix, utils::sanitize_filename(title));
+                let date = item.update_time.format("%Y-%m-%d").to_string();
                 let audio_path_clone = audio_path.clone();
 
                 if let Some(ti_items) = &item.ti_items {
@@ -166,14 +223,33 @@ impl TextbookExtractor {
                                 .or_else(|| downloadable_group.first())
                                 .copied()?;
                             let url = best_ti.ti_storages.as_ref()?.first()?;
+                            let filename = utils::render_filename(
+                                &self.config.filename_templates.audio,
+                                &[
+                                    ("title", title),
+                                    ("course", textbook_title),
+                                    ("index", &index_prefix),
+                                    ("date", &date),
+                                ],
+                                format,
+                            );
                             Some(FileInfo {
-                                filepath: audio_path_clone
-                                    .join(format!("{}.{}", base_name, format)),
+                                filepath: audio_path_clone.join(filename),
                                 url: url.clone(),
                                 ti_md5: best_ti.ti_md5.clone(),
                                 ti_size: best_ti.ti_size,
                                 date: Some(item.update_time),
                                 category: ResourceCategory::Audio,
+                                resource_id: Some(resource_id.to_string()),
+                                lesson_title: Some(title.clone()),
+                                teacher: None,
+                                quality: None,
+                                format: Some(format.to_lowercase()),
+                                // 同一条音频的不同格式属于同一分组
+                                variant_group: Some(format!("{}#{}", resource_id, i + 1)),
+                                source_format: None,
+                                language: None,
+                                metadata: None,
                             })
                         })
                         .collect::<Vec<_>>()
@@ -190,7 +266,7 @@ impl TextbookExtractor {
         tag_list_val: Option<&[Tag]>,
         context: &DownloadJobContext,
     ) -> PathBuf {
-        if context.args.flat {
+        if context.args.options.flat {
             return PathBuf::new();
         }
         
@@ -259,10 +335,31 @@ impl ResourceExtractor for TextbookExtractor {
             .await?;
         let base_path = self.build_resource_path(data.tag_list.as_deref(), context);
         let (mut pdf_files, textbook_basename) = self.extract_pdf_info(&data, &base_path);
+        if context.args.options.with_covers {
+            pdf_files.extend(self.extract_cover_info(&data, &base_path, textbook_basename.as_deref()));
+        }
+        let title = data
+            .global_title
+            .as_ref()
+            .map(|t| t.zh_cn.clone())
+            .or(data.title.clone())
+            .unwrap_or_else(|| data.id.clone());
         let audio_files = self
-            .extract_audio_info(resource_id, base_path, textbook_basename)
+            .extract_audio_info(resource_id, &title, base_path.clone(), textbook_basename.clone())
             .await?;
         pdf_files.extend(audio_files);
+        let metadata = ResourceMetadata {
+            resource_id: resource_id.to_string(),
+            resource_type: constants::api::types::TCH_MATERIAL.to_string(),
+            file_stem: textbook_basename.unwrap_or_else(|| utils::sanitize_filename(&title)),
+            title,
+            update_time: Some(data.update_time),
+            tags: data.tag_list.iter().flatten().map(MetadataTag::from).collect(),
+            teachers: vec![],
+            chapter_path: vec![],
+            base_dir: base_path,
+        };
+        extractor_utils::attach_metadata(&mut pdf_files, metadata);
         info!("为教材 '{}' 提取到 {} 个文件", resource_id, pdf_files.len());
         debug!("Extractor 返回的原始文件列表 (共 {} 项):", pdf_files.len());
         for (i, item) in pdf_files.iter().enumerate() {
@@ -275,27 +372,16 @@ impl ResourceExtractor for TextbookExtractor {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{cli::Cli, downloader::DownloadManager, DownloadJobContext};
+    use crate::{cli::Cli, DownloadJobContext};
     use clap::Parser;
-    use std::sync::{atomic::AtomicBool, Arc};
-    use tokio::sync::Mutex as TokioMutex;
+    use std::sync::Arc;
 
     // --- 辅助函数：创建一个用于测试的上下文 ---
     fn create_test_context(args_str: &str) -> DownloadJobContext {
-        let args = Arc::new(Cli::parse_from(args_str.split_whitespace()));
-        let config = Arc::new(crate::config::AppConfig::default());
-
-        DownloadJobContext {
-            manager: DownloadManager::new(),
-            token: Arc::new(TokioMutex::new("fake-token".to_string())),
-            config: config.clone(),
-            http_client: Arc::new(
-                crate::client::RobustClient::new(config.clone()).unwrap(),
-            ),
-            args,
-            non_interactive: true,
-            cancellation_token: Arc::new(AtomicBool::new(false)),
-        }
+        DownloadJobContext::for_test(
+            Cli::parse_from(args_str.split_whitespace()),
+            AppConfig::default(),
+        )
     }
 
     // --- 辅助函数：创建一个用于测试的提取器实例 ---
@@ -394,4 +480,29 @@ mod tests {
         assert!(path.as_os_str().is_empty());
         assert_eq!(path, PathBuf::new());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_extract_cover_info_named_after_pdf() {
+        let extractor = create_test_extractor();
+        let data: TextbookDetailsResponse = serde_json::from_value(serde_json::json!({
+            "id": "book1",
+            "global_title": {"zh-CN": "语文 一年级上册"},
+            "update_time": "2024-01-01T12:00:00+08:00",
+            "custom_properties": {"thumbnails": ["https://a/cover/1.jpg", "https://a/cover/2.jpg"]},
+            "ti_items": [{ "ti_format": "pdf", "ti_storages": ["https://a/pdf.pdf"] }]
+        }))
+        .unwrap();
+        let base_path = Path::new("out");
+
+        let (_, basename) = extractor.extract_pdf_info(&data, base_path);
+        let cover = extractor
+            .extract_cover_info(&data, base_path, basename.as_deref())
+            .unwrap();
+        assert_eq!(cover.filepath, base_path.join("语文 一年级上册.jpg"));
+        assert_eq!(cover.url, "https://a/cover/1.jpg");
+        assert_eq!(cover.category, ResourceCategory::Image);
+        assert_eq!(cover.resource_id.as_deref(), Some("book1"));
+        assert_eq!(cover.format.as_deref(), Some("jpg"));
+        assert_eq!(cover.lesson_title.as_deref(), Some("语文 一年级上册"));
+    }
+}
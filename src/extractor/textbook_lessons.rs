@@ -0,0 +1,127 @@
+// src/extractor/textbook_lessons.rs
+
+use super::chapter_resolver::{ChapterTreeCache, ChapterTreeResolver};
+use crate::{
+    client::RobustClient,
+    config::AppConfig,
+    error::*,
+    models::api::NationalLessonItem,
+};
+use anyhow::anyhow;
+use futures::future::try_join_all;
+use itertools::Itertools;
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::{collections::HashMap, sync::Arc};
+
+/// 教材中的一节同步课，按章节目录顺序排列
+#[derive(Debug, Clone, Serialize)]
+pub struct TextbookLesson {
+    pub id: String,
+    pub title: String,
+    /// 课时所在章节的各级标题；未能在章节树中定位时为空
+    pub chapter_path: Vec<String>,
+}
+
+/// 列出一本教材下所有章节挂载的同步课。
+/// 章节树以教材 ID 为树 ID；课时列表由若干分片组成，每个课时通过 `chapter_paths` 指向所属章节节点。
+pub struct TextbookLessonsResolver {
+    http_client: Arc<RobustClient>,
+    config: Arc<AppConfig>,
+    chapter_cache: Arc<ChapterTreeCache>,
+}
+
+impl TextbookLessonsResolver {
+    pub fn new(http_client: Arc<RobustClient>, config: Arc<AppConfig>) -> Self {
+        Self {
+            http_client,
+            config,
+            chapter_cache: Arc::default(),
+        }
+    }
+
+    /// 与后续下载共享章节树缓存，解析各课时的目录时无需再次请求同一棵章节树
+    pub fn with_chapter_cache(mut self, cache: Arc<ChapterTreeCache>) -> Self {
+        self.chapter_cache = cache;
+        self
+    }
+
+    pub async fn list_lessons(&self, textbook_id: &str) -> AppResult<Vec<TextbookLesson>> {
+        let chapters = ChapterTreeResolver::with_cache(
+            self.http_client.clone(),
+            self.config.clone(),
+            self.chapter_cache.clone(),
+        )
+        .list_chapters(textbook_id)
+        .await?;
+
+        let url_template = self
+            .config
+            .url_templates
+            .get("TEXTBOOK_LESSONS")
+            .ok_or_else(|| AppError::Other(anyhow!("配置文件中缺少 TEXTBOOK_LESSONS URL 模板")))?;
+        let part_urls: Vec<String> = self
+            .http_client
+            .fetch_json(url_template, &[("resource_id", textbook_id)])
+            .await?;
+        debug!("教材 '{}' 的课时列表共 {} 个分片", textbook_id, part_urls.len());
+        let parts: Vec<Vec<NationalLessonItem>> = try_join_all(
+            part_urls
+                .iter()
+                .map(|url| self.http_client.fetch_json(url, &[])),
+        )
+        .await?;
+
+        // 按课时所属章节节点 (chapter_paths 的最后一段) 在目录中的位置稳定排序
+        let chapter_index: HashMap<&str, usize> = chapters
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (c.id.as_str(), i))
+            .collect();
+        let mut lessons: Vec<(Option<usize>, NationalLessonItem)> = parts
+            .into_iter()
+            .flatten()
+            .unique_by(|item| item.id.clone())
+            .map(|item| {
+                let index = item
+                    .chapter_paths
+                    .as_ref()
+                    .and_then(|paths| paths.first())
+                    .and_then(|path| path.split('/').next_back())
+                    .and_then(|node_id| chapter_index.get(node_id).copied());
+                (index, item)
+            })
+            .collect();
+        // 找不到所属章节的课时不丢弃，排在最后
+        lessons.sort_by_key(|(index, _)| index.unwrap_or(usize::MAX));
+        let orphans = lessons.iter().filter(|(index, _)| index.is_none()).count();
+        if orphans > 0 {
+            warn!("有 {} 个课时未能在章节树中找到所属章节", orphans);
+        }
+
+        let lessons: Vec<TextbookLesson> = lessons
+            .into_iter()
+            .map(|(index, item)| {
+                let chapter_path = index.map(|i| chapters[i].path.clone()).unwrap_or_default();
+                TextbookLesson::new(item, chapter_path)
+            })
+            .collect();
+        info!("教材 '{}' 共有 {} 个同步课时", textbook_id, lessons.len());
+        Ok(lessons)
+    }
+}
+
+impl TextbookLesson {
+    fn new(item: NationalLessonItem, chapter_path: Vec<String>) -> Self {
+        let title = item
+            .global_title
+            .map(|t| t.zh_cn)
+            .or(item.title)
+            .unwrap_or_else(|| item.id.clone());
+        Self {
+            id: item.id,
+            title,
+            chapter_path,
+        }
+    }
+}
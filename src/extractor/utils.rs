@@ -2,14 +2,19 @@
 
 use crate::{
     constants,
-    models::{FileInfo, ResourceCategory, api::CourseResource},
+    models::{
+        FileInfo, ResourceCategory, ResourceMetadata,
+        api::{CourseResource, TiItem},
+    },
+    utils,
 };
+use chrono::{DateTime, FixedOffset};
 use itertools::Itertools;
 use log::debug;
 use regex::Regex;
 use std::{
     path::Path,
-    sync::LazyLock,
+    sync::{Arc, LazyLock},
 };
 
 static RES_REF_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\[([\d,\s\*]+)\]$").unwrap());
@@ -31,12 +36,72 @@ pub fn parse_res_ref_indices(ref_str: &str, total_resources: usize) -> Option<Ve
     })
 }
 
-/// 通用函数：从一个视频资源中提取所有可下载的 m3u8 流
+/// 通用函数：将资源元数据附加到该资源提取出的所有文件上 (共享同一份)
+pub fn attach_metadata(files: &mut [FileInfo], metadata: ResourceMetadata) {
+    let metadata = Arc::new(metadata);
+    for file in files {
+        file.metadata = Some(metadata.clone());
+    }
+}
+
+/// 文件名模板的占位符取值，由提取器按资源填写；`{quality}`、`{date}`、`{ext}` 在生成文件名时补充
+#[derive(Debug, Clone, Default)]
+pub struct FileNameFields {
+    /// 默认命名中的标题部分，例如精品课的课程标题、同步课堂的 "课程标题[课时标题]"
+    pub title: String,
+    pub course: String,
+    pub lesson: String,
+    pub alias: String,
+    pub teacher: String,
+    pub index: String,
+}
+
+impl FileNameFields {
+    /// 不含教师、清晰度和扩展名的内容标题，记录在 `FileInfo::lesson_title` 中
+    pub fn content_title(&self) -> String {
+        format!("{} - {}", self.title, self.alias)
+    }
+
+    /// 按文件名模板生成文件名
+    pub fn render(
+        &self,
+        template: &str,
+        quality: &str,
+        date: DateTime<FixedOffset>,
+        ext: &str,
+    ) -> String {
+        let date = date.format("%Y-%m-%d").to_string();
+        utils::render_filename(
+            template,
+            &[
+                ("title", &self.title),
+                ("course", &self.course),
+                ("lesson", &self.lesson),
+                ("alias", &self.alias),
+                ("teacher", &self.teacher),
+                ("index", &self.index),
+                ("quality", quality),
+                ("date", &date),
+            ],
+            ext,
+        )
+    }
+}
+
+/// 同一资源在同一目录下的所有版本 (清晰度/格式) 共享的分组键。
+/// 同一个资源可能被多个课时引用，因此分组键同时包含资源 ID 和保存目录。
+pub fn variant_group_key(resource: &CourseResource, base_path: &Path) -> String {
+    format!("{}@{}", resource.id, base_path.to_string_lossy())
+}
+
+/// 通用函数：从一个视频资源中提取所有可下载的 m3u8 流。
+/// `extension` 是最终保存的容器扩展名 (--video-container)，直接参与文件名模板的渲染。
 pub fn extract_video_files(
     resource: &CourseResource,
-    base_name: &str,
+    fields: &FileNameFields,
     base_path: &Path,
-    teacher_name: &str,
+    template: &str,
+    extension: &str,
 ) -> Vec<FileInfo> {
     let mut streams: Vec<FileInfo> = resource
         .ti_items
@@ -49,20 +114,19 @@ pub fn extract_video_files(
                 .as_ref()
                 .and_then(|s| s.first())
                 .map(|url| {
-                    // 更智能的清晰度提取
-                    let quality_str = item
+                    let height = item
                         .custom_properties
                         .as_ref()
                         .and_then(|p| p.requirements.as_ref())
                         .and_then(|reqs| {
                             reqs.iter().find(|r| r.name == constants::api::video_metadata_keys::HEIGHT)
                         })
-                        .map(|h| h.value.as_str())
-                        .unwrap_or("未知"); // 找不到则默认为 "未知"，避免歧义
-
-                    // 统一文件名格式为 "[清晰度]"，与后续解析逻辑保持一致
+                        .map(|h| h.value.trim());
+                    let quality = height.and_then(|h| h.parse::<u32>().ok());
+                    // 找不到清晰度时文件名中标记为 "未知"，避免歧义
+                    let quality_str = height.unwrap_or("未知");
                     let filename =
-                        format!("{} [{}] - [{}].ts", base_name, quality_str, teacher_name);
+                        fields.render(template, quality_str, resource.update_time, extension);
 
                     let estimated_size = item
                         .custom_properties
@@ -85,48 +149,266 @@ pub fn extract_video_files(
                         ti_size: estimated_size,
                         date: Some(resource.update_time),
                         category: ResourceCategory::Video,
+                        resource_id: None, // 由调用方填充所属资源的 ID 和元数据
+                        lesson_title: Some(fields.content_title()),
+                        teacher: Some(fields.teacher.clone()),
+                        quality,
+                        format: Some(extension.to_string()),
+                        variant_group: Some(variant_group_key(resource, base_path)),
+                        source_format: None,
+                        language: None,
+                        metadata: None,
                     }
                 })
         })
         .collect();
 
-    // 去重逻辑
-    streams.sort_by_key(|s| {
-        // 解析不带 'p' 的数字进行排序
-        s.filepath
-            .to_string_lossy()
-            .split('[')
-            .nth(1)
-            .and_then(|part| part.split(']').next())
-            .and_then(|quality| quality.trim().parse::<u32>().ok())
-            .unwrap_or(0)
-    });
-    streams.reverse(); // 高分辨率在前
+    // 去重逻辑：高分辨率在前，清晰度未知的排在最后
+    streams.sort_by_key(|s| std::cmp::Reverse(s.quality.unwrap_or(0)));
 
     streams.into_iter().unique_by(|s| s.url.clone()).collect()
 }
 
-/// 通用函数：从一个文档/课件资源中提取唯一的 PDF 文件
-pub fn extract_document_file(resource: &CourseResource) -> Option<FileInfo> {
-    resource
+/// 通用函数：从一个文档/课件资源中提取文档文件 (PDF、PPTX、DOCX、图片、压缩包等)。
+/// `formats` 按优先级排列，选择资源中第一个可用的格式；`*` 匹配任意格式，`all` 表示下载所有格式。
+pub fn extract_document_files(
+    resource: &CourseResource,
+    fields: &FileNameFields,
+    base_path: &Path,
+    template: &str,
+    formats: &[String],
+) -> Vec<FileInfo> {
+    let candidates: Vec<(String, &TiItem, &String)> = resource
         .ti_items
         .as_deref()
         .unwrap_or_default()
         .iter()
-        // .find(|i| i.ti_file_flag.as_deref() == Some("pdf"))
-        .find(|i| i.ti_format.eq_ignore_ascii_case("pdf"))
-        .and_then(|pdf_item| {
-            pdf_item
-                .ti_storages
-                .as_ref()
-                .and_then(|s| s.first())
-                .map(|url| FileInfo {
-                    filepath: std::path::PathBuf::new(),
-                    url: url.clone(),
-                    ti_md5: pdf_item.ti_md5.clone(),
-                    ti_size: pdf_item.ti_size,
-                    date: Some(resource.update_time),
-                    category: ResourceCategory::Document,
+        .filter(|item| {
+            !item.ti_file_flag.as_deref().is_some_and(|flag| {
+                constants::api::NON_DOCUMENT_FILE_FLAGS.contains(&flag.to_lowercase().as_str())
+            })
+        })
+        .filter_map(|item| {
+            let url = item.ti_storages.as_ref()?.first()?;
+            let extension = document_extension(item, url)?;
+            Some((extension, item, url))
+        })
+        .unique_by(|(extension, ..)| extension.clone())
+        .collect();
+
+    let selected: Vec<&(String, &TiItem, &String)> =
+        if formats.iter().any(|f| f.eq_ignore_ascii_case("all")) {
+            candidates.iter().collect()
+        } else {
+            formats
+                .iter()
+                .map(|f| f.trim().to_lowercase())
+                .find_map(|wanted| {
+                    candidates
+                        .iter()
+                        .find(|(extension, ..)| wanted == "*" || *extension == wanted)
                 })
+                .into_iter()
+                .collect()
+        };
+    debug!(
+        "文档提取: 可用格式 {:?}, 按偏好 {:?} 选择了 {:?}",
+        candidates.iter().map(|(e, ..)| e).collect::<Vec<_>>(),
+        formats,
+        selected.iter().map(|(e, ..)| e).collect::<Vec<_>>()
+    );
+
+    selected
+        .into_iter()
+        .map(|(extension, item, url)| FileInfo {
+            filepath: base_path.join(fields.render(template, "", resource.update_time, extension)),
+            url: (*url).clone(),
+            ti_md5: item.ti_md5.clone(),
+            ti_size: item.ti_size,
+            date: Some(resource.update_time),
+            category: ResourceCategory::Document,
+            resource_id: None,
+            lesson_title: Some(fields.content_title()),
+            teacher: Some(fields.teacher.clone()),
+            quality: None,
+            format: Some(extension.clone()),
+            variant_group: Some(variant_group_key(resource, base_path)),
+            source_format: None,
+            language: None,
+            metadata: None,
+        })
+        .collect()
+}
+
+/// 确定文档条目的真实扩展名：优先使用 `ti_format`，其为空或为通用的 `bin` 时依次尝试
+/// `ti_file_flag` 和 URL 中的扩展名。视频流和字幕不属于文档，返回 `None`。
+fn document_extension(item: &TiItem, url: &str) -> Option<String> {
+    use constants::api::resource_formats;
+
+    let looks_like_extension =
+        |s: &str| !s.is_empty() && s.len() <= 5 && s.chars().all(|c| c.is_ascii_alphanumeric());
+    let format = item.ti_format.trim().to_lowercase();
+    let extension = if looks_like_extension(&format) && format != resource_formats::BIN {
+        format
+    } else {
+        item.ti_file_flag
+            .as_deref()
+            .map(str::to_lowercase)
+            .filter(|flag| looks_like_extension(flag) && flag != resource_formats::BIN)
+            .or_else(|| {
+                let path = url.split(['?', '#']).next().unwrap_or_default();
+                Path::new(path)
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .map(str::to_lowercase)
+                    .filter(|e| looks_like_extension(e))
+            })?
+    };
+    let is_media = extension == resource_formats::M3U8
+        || extension == resource_formats::JSON
+        || resource_formats::SUBTITLES.contains(&extension.as_str());
+    (!is_media).then_some(extension)
+}
+
+/// 通用函数：从一个资源中提取字幕文件，保存在视频旁边。
+/// 这里按字幕模板命名；协商出最终的视频流后，同一视频的字幕会改为与视频同名 (见 `attach_subtitles_to_videos`)。
+/// 平台自定义的 JSON 字幕会在下载时转换为 SRT，因此不沿用其大小和 MD5。
+pub fn extract_subtitle_files(
+    resource: &CourseResource,
+    fields: &FileNameFields,
+    base_path: &Path,
+    template: &str,
+) -> Vec<FileInfo> {
+    use constants::api::{resource_formats, CAPTION_FILE_FLAG_KEYWORDS};
+
+    let subtitles: Vec<_> = resource
+        .ti_items
+        .as_deref()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|item| {
+            let format = item.ti_format.to_lowercase();
+            let is_caption_json = format == resource_formats::JSON
+                && item.ti_file_flag.as_deref().is_some_and(|flag| {
+                    let flag = flag.to_lowercase();
+                    CAPTION_FILE_FLAG_KEYWORDS.iter().any(|k| flag.contains(k))
+                });
+            if !is_caption_json && !resource_formats::SUBTITLES.contains(&format.as_str()) {
+                return None;
+            }
+            let url = item.ti_storages.as_ref()?.first()?;
+            Some((format, is_caption_json, item, url, subtitle_language(item, url)))
         })
+        .collect();
+    let languages: Vec<Option<String>> =
+        subtitles.iter().map(|(.., language)| language.clone()).collect();
+
+    subtitles
+        .into_iter()
+        .zip(utils::subtitle_suffixes(&languages))
+        .map(|((format, is_caption_json, item, url, language), suffix)| {
+            let extension = if is_caption_json { "srt" } else { format.as_str() };
+            let filename = fields.render(template, "", resource.update_time, extension);
+            // 同一视频有多条字幕时，用语言代码或序号区分
+            let filename = match suffix {
+                Some(suffix) => Path::new(&filename)
+                    .with_extension(format!("{}.{}", suffix, extension))
+                    .to_string_lossy()
+                    .to_string(),
+                None => filename,
+            };
+            debug!("字幕提取: 文件名='{}', 原始格式={}", filename, item.ti_format);
+            FileInfo {
+                filepath: base_path.join(filename),
+                url: url.clone(),
+                ti_md5: if is_caption_json { None } else { item.ti_md5.clone() },
+                ti_size: if is_caption_json { None } else { item.ti_size },
+                date: Some(resource.update_time),
+                category: ResourceCategory::Subtitle,
+                resource_id: None,
+                lesson_title: Some(fields.content_title()),
+                teacher: Some(fields.teacher.clone()),
+                quality: None,
+                format: Some(extension.to_string()),
+                variant_group: Some(variant_group_key(resource, base_path)),
+                source_format: Some(format.clone()),
+                language,
+                metadata: None,
+            }
+        })
+        .collect()
+}
+
+/// 从 `ti_file_flag` (如 `caption_en`) 或 URL 中的文件名 (如 `xxx.zh.srt`) 识别字幕语言
+fn subtitle_language(item: &TiItem, url: &str) -> Option<String> {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let file_stem = Path::new(path).file_stem().and_then(|s| s.to_str());
+    [item.ti_file_flag.as_deref(), file_stem]
+        .into_iter()
+        .flatten()
+        .flat_map(|s| s.split(|c: char| !c.is_ascii_alphanumeric()))
+        .map(str::to_lowercase)
+        .find(|token| constants::api::SUBTITLE_LANGUAGE_CODES.contains(&token.as_str()))
+}
+
+/// 通用函数：提取封面/缩略图，保存在主资源旁边；`filename` 根据图片扩展名生成文件名。
+/// 优先使用 `ti_file_flag` 为封面/缩略图的条目 (带有大小和 MD5)，其次使用 `thumbnails` 中的第一张图片。
+pub fn extract_cover_file(
+    ti_items: Option<&[TiItem]>,
+    thumbnails: Option<&[String]>,
+    base_path: &Path,
+    filename: impl Fn(&str) -> String,
+    date: DateTime<FixedOffset>,
+) -> Option<FileInfo> {
+    use constants::api::{resource_formats, COVER_FILE_FLAGS};
+
+    let image_extension = |url: &str| {
+        let path = url.split(['?', '#']).next().unwrap_or_default();
+        Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_lowercase)
+            .filter(|e| resource_formats::IMAGES.contains(&e.as_str()))
+    };
+    let flagged = COVER_FILE_FLAGS.iter().find_map(|wanted| {
+        ti_items.unwrap_or_default().iter().find_map(|item| {
+            let flag = item.ti_file_flag.as_deref()?;
+            if !flag.eq_ignore_ascii_case(wanted) {
+                return None;
+            }
+            let url = item.ti_storages.as_ref()?.first()?;
+            let format = item.ti_format.to_lowercase();
+            let extension = if resource_formats::IMAGES.contains(&format.as_str()) {
+                format
+            } else {
+                image_extension(url).unwrap_or_else(|| "jpg".to_string())
+            };
+            Some((url.clone(), extension, item.ti_md5.clone(), item.ti_size))
+        })
+    });
+    let (url, extension, ti_md5, ti_size) = flagged.or_else(|| {
+        let url = thumbnails?.iter().find(|u| !u.trim().is_empty())?;
+        let extension = image_extension(url).unwrap_or_else(|| "jpg".to_string());
+        Some((url.clone(), extension, None, None))
+    })?;
+
+    let filename = filename(&extension);
+    debug!("封面提取: 文件名='{}' @ '{}'", filename, url);
+    Some(FileInfo {
+        filepath: base_path.join(filename),
+        url,
+        ti_md5,
+        ti_size,
+        date: Some(date),
+        category: ResourceCategory::Image,
+        resource_id: None,
+        lesson_title: None,
+        teacher: None,
+        quality: None,
+        format: Some(extension),
+        variant_group: None,
+        source_format: None,
+        language: None,
+        metadata: None,
+    })
 }
@@ -14,14 +14,15 @@ pub mod utils;
 pub mod workflows;
 
 use crate::{
-    cli::Cli,
+    cli::{Cli, Command, TokenCommand},
     client::RobustClient,
     config::AppConfig,
-    downloader::DownloadManager,
+    downloader::{DownloadManager, DownloadStateStore},
     error::AppResult,
+    extractor::chapter_resolver::ChapterTreeCache,
+    workflows::RunOutcome,
 };
-use colored::Colorize;
-use log::{debug, info};
+use log::{debug, info, warn};
 use std::sync::{atomic::AtomicBool, Arc};
 use tokio::sync::Mutex as TokioMutex;
 
@@ -31,28 +32,58 @@ pub struct DownloadJobContext {
     pub token: Arc<TokioMutex<String>>,
     pub config: Arc<AppConfig>,
     pub http_client: Arc<RobustClient>,
+    pub state: Arc<DownloadStateStore>,
+    /// 本次运行内所有资源共享的章节树缓存
+    pub chapter_cache: Arc<ChapterTreeCache>,
     pub args: Arc<Cli>,
     pub non_interactive: bool,
     pub cancellation_token: Arc<AtomicBool>,
 }
 
-pub async fn run_from_cli(args: Arc<Cli>, cancellation_token: Arc<AtomicBool>) -> AppResult<()> {
+#[cfg(feature = "testing")]
+impl DownloadJobContext {
+    /// 构造用于测试的上下文：非交互、内存中的下载状态、Token 为 "fake-token"
+    pub fn for_test(args: Cli, config: AppConfig) -> Self {
+        let config = Arc::new(config);
+        Self {
+            manager: DownloadManager::new(),
+            token: Arc::new(TokioMutex::new("fake-token".to_string())),
+            config: config.clone(),
+            http_client: Arc::new(RobustClient::new(config).expect("创建测试用 HTTP 客户端失败")),
+            state: Arc::new(DownloadStateStore::in_memory()),
+            chapter_cache: Arc::default(),
+            non_interactive: !args.interactive,
+            args: Arc::new(args),
+            cancellation_token: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+pub async fn run_from_cli(
+    args: Arc<Cli>,
+    cancellation_token: Arc<AtomicBool>,
+) -> AppResult<RunOutcome> {
     debug!("CLI 参数: {:?}", args);
+    // 不涉及下载的子命令无需加载 Token 和网络客户端
+    match &args.command {
+        Some(Command::Config { action }) => return workflows::run_config(action),
+        Some(Command::Verify { dir, md5 }) => return workflows::run_verify(dir.as_deref(), *md5),
+        Some(Command::Token {
+            action: TokenCommand::Local(action),
+        }) => return workflows::run_token(action, args.active_profile().as_deref()),
+        _ => {}
+    }
     if args.token_help {
-        ui::box_message(
-            "获取 Access Token 指南",
-            constants::HELP_TOKEN_GUIDE.lines().collect::<Vec<_>>().as_slice(),
-            |s| s.cyan(),
-        );
-        ui::plain("");
-        ui::info("安全提醒: 请妥善保管你的 Token。");
-        return Ok(());
+        workflows::print_token_guide();
+        return Ok(RunOutcome::default());
     }
 
+    ui::set_json_output(args.selection.output_format == cli::OutputFormat::Json);
+
     let config = Arc::new(AppConfig::new(&args)?);
     debug!("加载的应用配置: {:?}", config);
 
-    let (token_opt, source) = config::token::resolve_token(args.token.as_deref());
+    let (token_opt, source) = config::token::resolve_token(args.options.token.as_deref(), config.profile.as_deref());
     if token_opt.is_some() {
         info!("从 {} 加载 Access Token", source);
         ui::plain("");
@@ -65,25 +96,59 @@ pub async fn run_from_cli(args: Arc<Cli>, cancellation_token: Arc<AtomicBool>) -
     let token = Arc::new(TokioMutex::new(token_opt.unwrap_or_default()));
 
     let http_client = Arc::new(RobustClient::new(config.clone())?);
+    // 预览模式不应修改用户主目录：跳过缓存清理，并以只读方式打开下载状态数据库
+    let dry_run = args.selection.dry_run;
+    if !dry_run {
+        http_client.prune_cache();
+    }
+
+    let opened_state = if dry_run {
+        DownloadStateStore::open_default_read_only()
+    } else {
+        DownloadStateStore::open_default()
+    };
+    let state = match opened_state {
+        Ok(store) => store,
+        Err(e) => {
+            warn!("无法打开下载状态数据库，本次运行将不记录下载历史: {}", e);
+            ui::warn(&format!("无法打开下载状态数据库，本次运行将不记录下载历史: {}", e));
+            DownloadStateStore::in_memory()
+        }
+    };
 
     let context = DownloadJobContext {
         manager: DownloadManager::new(),
         token,
         config: config.clone(),
         http_client,
+        state: Arc::new(state),
+        chapter_cache: Arc::default(),
         args: args.clone(),
         non_interactive: !args.interactive,
         cancellation_token,
     };
 
     // --- 核心分发逻辑 ---
-    if args.interactive {
-        workflows::run_interactive(context).await?;
+    if let Some(Command::Token {
+        action: TokenCommand::Validate { input, .. },
+    }) = &args.command
+    {
+        workflows::run_token_validate(input.as_deref(), context).await
+    } else if let Some(Command::Browse {
+        filter, download, ..
+    }) = &args.command
+    {
+        workflows::run_browse(filter, *download, context).await
+    } else if let Some(Command::Lessons {
+        textbook, chapter, ..
+    }) = &args.command
+    {
+        workflows::run_lessons(textbook, chapter.as_deref(), context).await
+    } else if args.interactive {
+        workflows::run_interactive(context).await
     } else if let Some(batch_file) = &args.batch_file {
-        workflows::run_batch(batch_file.clone(), context).await?;
+        workflows::run_batch(batch_file.clone(), context).await
     } else {
-        workflows::run_single(context).await?;
-    };
-
-    Ok(())
+        workflows::run_single(context).await
+    }
 }
\ No newline at end of file
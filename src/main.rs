@@ -6,8 +6,9 @@ use log::{error, info, warn};
 use reqwest::StatusCode;
 use sed_dl::{
     cli::{Cli, LogLevel},
-    constants,
+    constants::{self, exit_codes},
     error::AppError,
+    models::OutputEvent,
     run_from_cli, symbols, ui,
 };
 use std::{
@@ -108,7 +109,7 @@ fn setup_ctrl_c_handler() -> Arc<AtomicBool> {
             ui::plain(""); // 产生空行
             ui::plain("第二次中断，强制退出...");
             warn!("用户第二次按下 {}，强制退出。", *symbols::CTRL_C);
-            std::process::exit(130);
+            std::process::exit(exit_codes::INTERRUPTED);
         }
 
         ui::plain("");
@@ -131,26 +132,42 @@ async fn main() {
     }
 
     let after_help = format!(
-        "示例:\n  # 启动交互模式 (推荐)\n  {bin} -i\n\n  # 自动下载单个链接中的所有内容\n  {bin} --url \"https://...\"\n\n  # 批量下载并显示调试信息 (日志写入文件)\n  {bin} -b my_links.txt --type tchMaterial --log-level debug\n\n  # 获取 Token 帮助\n  {bin} --token-help",
+        "示例:\n  # 启动交互模式 (推荐)\n  {bin} interactive\n\n  # 自动下载单个链接中的所有内容\n  {bin} download \"https://...\"\n\n  # 批量下载并显示调试信息 (日志写入文件)\n  {bin} batch my_links.txt --type tchMaterial --log-level debug\n\n  # 只列出将要下载的文件，不下载\n  {bin} list \"https://...\"\n\n  # 获取 Token 帮助\n  {bin} token guide\n\n旧版参数 (-i、--url、--id、-b、--token-help) 仍然可用，与对应的子命令等价。",
         bin = clap::crate_name!()
     );
 
     let cmd = Cli::command()
-        .override_usage(format!("{} <MODE> [OPTIONS]", clap::crate_name!()))
+        .override_usage(format!(
+            "{bin} <MODE> [OPTIONS]\n       {bin} <COMMAND> [OPTIONS]",
+            bin = clap::crate_name!()
+        ))
         .after_help(after_help);
 
-    let matches = cmd.get_matches();
-    let args = Arc::new(Cli::from_arg_matches(&matches).unwrap());
+    // clap 默认以退出码 2 报告参数错误，这会与“部分文件下载失败”混淆，因此统一改为通用错误码
+    let matches = cmd.try_get_matches().unwrap_or_else(|e| {
+        let _ = e.print();
+        std::process::exit(if e.use_stderr() {
+            exit_codes::ERROR
+        } else {
+            exit_codes::SUCCESS
+        });
+    });
+    let args = Arc::new(Cli::from_arg_matches(&matches).unwrap().normalize());
 
     init_logger(args.log_level);
 
     let cancellation_token = setup_ctrl_c_handler();
 
-    if let Err(e) = run_from_cli(args, cancellation_token).await {
-        handle_final_error(e);
+    match run_from_cli(args, cancellation_token).await {
+        Ok(outcome) => {
+            let exit_code = outcome.exit_code();
+            info!("程序正常退出 (退出码: {})。", exit_code);
+            if exit_code != exit_codes::SUCCESS {
+                std::process::exit(exit_code);
+            }
+        }
+        Err(e) => handle_final_error(e),
     }
-
-    info!("程序正常退出。");
 }
 
 
@@ -160,17 +177,24 @@ fn handle_final_error(e: AppError) {
     // 用户中断是预期行为，静默退出，并使用标准退出码 130
     if matches!(e, AppError::UserInterrupt) {
         warn!("程序被用户中断。");
-        std::process::exit(130);
+        std::process::exit(exit_codes::INTERRUPTED);
     }
 
     // 将错误信息记录到日志
     error!("程序因错误退出: {:?}", e);
 
+    // JSON 模式下额外输出一个结构化的错误事件，便于脚本解析
+    let exit_code = e.exit_code();
+    ui::emit_event(&OutputEvent::Error {
+        message: e.to_string(),
+        exit_code,
+    });
+
     // 根据错误类型，生成不同的友好提示信息
     let (symbol, message, color_fn): (&ColoredString, String, fn(ColoredString) -> ColoredString) = match e {
-        AppError::TokenInvalid | AppError::TokenMissing => {
+        AppError::TokenInvalid | AppError::TokenMissing | AppError::TokenExpiringSoon(_) => {
             let msg = format!(
-                "{}\n{} 请使用 --token-help 命令查看如何获取或更新的 Access Token。",
+                "{}\n{} 请使用 `token guide` 子命令 (或 --token-help) 查看如何获取或更新 Access Token。",
                 e, *symbols::INFO
             );
             (&symbols::ERROR, msg, |s| s.red())
@@ -208,5 +232,5 @@ fn handle_final_error(e: AppError) {
 
     eprintln!("\n{} {}", symbol, color_fn(message.into()));
 
-    std::process::exit(1);
+    std::process::exit(exit_code);
 }
\ No newline at end of file
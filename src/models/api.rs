@@ -2,7 +2,7 @@
 
 use chrono::{DateTime, FixedOffset};
 use serde::Deserialize;
-use crate::extractor::common::DirectoryBuilder;
+use crate::{config::DirectoryStructureConfig, extractor::common::DirectoryBuilder};
 use async_trait::async_trait;
 
 // --- 通用结构体 ---
@@ -66,6 +66,9 @@ pub struct CourseResourceCustomProperties {
     pub height: Option<String>,
     #[serde(default)]
     pub teacher_name: Option<String>,
+    /// 视频课时的缩略图地址
+    #[serde(default)]
+    pub thumbnails: Option<Vec<String>>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -134,6 +137,14 @@ impl DirectoryBuilder for CourseDetailsResponse {
             None
         }
     }
+
+    fn get_teacher_names(&self) -> Vec<&str> {
+        self.teacher_list.iter().flatten().map(|t| t.name.as_str()).collect()
+    }
+
+    fn get_path_template<'a>(&self, config: &'a DirectoryStructureConfig) -> &'a str {
+        &config.course_path_template
+    }
 }
 
 // --- 同步课 (syncClassroom) 专用模型 ---
@@ -176,6 +187,14 @@ impl DirectoryBuilder for SyncClassroomResponse {
             None
         }
     }
+
+    fn get_teacher_names(&self) -> Vec<&str> {
+        self.teacher_list.iter().map(|t| t.name.as_str()).collect()
+    }
+
+    fn get_path_template<'a>(&self, config: &'a DirectoryStructureConfig) -> &'a str {
+        &config.sync_classroom_path_template
+    }
 }
 
 // --- 教材 (Textbook) 专用模型 ---
@@ -188,6 +207,15 @@ pub struct TextbookDetailsResponse {
     pub ti_items: Option<Vec<TiItem>>,
     pub tag_list: Option<Vec<Tag>>,
     pub update_time: DateTime<FixedOffset>,
+    #[serde(default)]
+    pub custom_properties: Option<TextbookCustomProperties>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TextbookCustomProperties {
+    /// 教材封面图地址
+    #[serde(default)]
+    pub thumbnails: Option<Vec<String>>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -196,3 +224,32 @@ pub struct AudioRelationItem {
     pub ti_items: Option<Vec<TiItem>>,
     pub update_time: DateTime<FixedOffset>,
 }
+
+// --- 教材目录 (Catalog) 专用模型 ---
+
+/// 教材目录的版本信息，`urls` 为逗号分隔的目录分片地址
+#[derive(Deserialize, Debug, Clone)]
+pub struct CatalogVersionResponse {
+    pub urls: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TextbookCatalogItem {
+    pub id: String,
+    pub title: Option<String>,
+    pub global_title: Option<ZhCn>,
+    #[serde(default)]
+    pub tag_list: Option<Vec<Tag>>,
+}
+
+// --- 教材同步课时列表专用模型 ---
+
+/// 挂在教材章节上的一节同步课 (课时)
+#[derive(Deserialize, Debug, Clone)]
+pub struct NationalLessonItem {
+    pub id: String,
+    pub title: Option<String>,
+    pub global_title: Option<ZhCn>,
+    #[serde(default)]
+    pub chapter_paths: Option<Vec<String>>,
+}
@@ -7,10 +7,13 @@ use crate::symbols;
 use chrono::{DateTime, FixedOffset};
 use colored::{ColoredString, Colorize};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 // 1. 定义 DownloadStatus 枚举
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub enum DownloadStatus {
     Success,
     Skipped,
@@ -30,6 +33,11 @@ pub enum DownloadStatus {
 
 // 2. 为 DownloadStatus 实现 get_display_info
 impl DownloadStatus {
+    /// 文件是否已成功下载 (包括续传成功)
+    pub fn is_success(&self) -> bool {
+        matches!(self, DownloadStatus::Success | DownloadStatus::Resumed)
+    }
+
     pub fn get_display_info(
         &self,
     ) -> (
@@ -91,14 +99,14 @@ impl From<&AppError> for DownloadStatus {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct DownloadResult {
     pub filename: String,
     pub status: DownloadStatus,
     pub message: Option<String>,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
 pub enum DownloadAction {
     Skip,
     Resume,
@@ -111,10 +119,25 @@ pub enum ResourceCategory {
     Video,
     Audio,
     Document,
+    Subtitle,
+    Image,
     #[default]
     Other,
 }
 
+impl ResourceCategory {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ResourceCategory::Video => "视频",
+            ResourceCategory::Audio => "音频",
+            ResourceCategory::Document => "文档",
+            ResourceCategory::Subtitle => "字幕",
+            ResourceCategory::Image => "图片",
+            ResourceCategory::Other => "其他",
+        }
+    }
+}
+
 // 5. 修正 FileInfo 定义
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct FileInfo {
@@ -125,6 +148,117 @@ pub struct FileInfo {
     pub date: Option<DateTime<FixedOffset>>,
     #[serde(default)]
     pub category: ResourceCategory,
+    /// 文件所属资源 (教材/课程/同步课堂) 的 ID
+    #[serde(default)]
+    pub resource_id: Option<String>,
+    /// 文件对应内容的标题 (课时/资源/音频标题)，不含教师、清晰度和扩展名
+    #[serde(default)]
+    pub lesson_title: Option<String>,
+    #[serde(default)]
+    pub teacher: Option<String>,
+    /// 视频清晰度 (画面高度，如 1080)；平台未提供时为空
+    #[serde(default)]
+    pub quality: Option<u32>,
+    /// 文件格式，小写且与最终扩展名一致 (如 pdf、mp3、ts)
+    #[serde(default)]
+    pub format: Option<String>,
+    /// 同一内容不同版本 (清晰度/格式) 共享的分组键，协商清晰度和音频格式时按它分组
+    #[serde(default)]
+    pub variant_group: Option<String>,
+    /// 平台提供的原始格式 (`ti_format`，小写)；与 `format` 不同时下载后需要转换，
+    /// 例如平台的 JSON 字幕保存为 SRT
+    #[serde(default)]
+    pub source_format: Option<String>,
+    /// 字幕语言 (如 zh、en)，平台未标明时为空
+    #[serde(default)]
+    pub language: Option<String>,
+    /// 文件所属资源的元数据，同一资源的所有文件共享一份，用于写入元数据附属文件
+    #[serde(skip)]
+    pub metadata: Option<Arc<ResourceMetadata>>,
+}
+
+impl FileInfo {
+    /// 报告中显示的版本说明：视频为清晰度 (如 "1080p")，其他文件为格式 (如 "PDF")
+    pub fn variant_label(&self) -> Option<String> {
+        match (self.category, self.quality) {
+            (ResourceCategory::Video, Some(quality)) => Some(format!("{}p", quality)),
+            _ => self.format.as_deref().map(str::to_uppercase),
+        }
+    }
+}
+
+/// 资源 (教材/课程/同步课堂) 级别的元数据，由提取器从 API 响应中收集。
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct ResourceMetadata {
+    pub resource_id: String,
+    pub resource_type: String,
+    pub title: String,
+    pub update_time: Option<DateTime<FixedOffset>>,
+    pub tags: Vec<MetadataTag>,
+    pub teachers: Vec<String>,
+    /// 各级章节标题，从上到下排列
+    pub chapter_path: Vec<String>,
+    /// 资源的根目录 (相对于输出目录)，`resource.json` 保存在这里
+    #[serde(skip)]
+    pub base_dir: PathBuf,
+    /// 元数据文件的基础文件名，与资源的主文件保持一致
+    #[serde(skip)]
+    pub file_stem: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MetadataTag {
+    pub dimension_id: String,
+    pub name: String,
+}
+
+impl From<&api::Tag> for MetadataTag {
+    fn from(tag: &api::Tag) -> Self {
+        Self {
+            dimension_id: tag.tag_dimension_id.clone(),
+            name: tag.tag_name.clone(),
+        }
+    }
+}
+
+/// 教材目录中的一本教材 (browse/search 子命令的结果)
+#[derive(Debug, Clone, Serialize)]
+pub struct CatalogEntry {
+    pub id: String,
+    pub title: String,
+    pub tags: Vec<MetadataTag>,
+}
+
+impl CatalogEntry {
+    /// 获取指定维度 (学段、年级等) 的标签名
+    pub fn tag_name(&self, dimension_id: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|t| t.dimension_id == dimension_id)
+            .map(|t| t.name.as_str())
+    }
+}
+
+/// 预览模式 (--dry-run) 下单个文件的解析结果。
+#[derive(Debug, Clone, Serialize)]
+pub struct PreviewEntry {
+    pub file: FileInfo,
+    /// 若真正执行下载，将对该文件采取的操作
+    pub action: DownloadAction,
+    pub reason: String,
+}
+
+/// 使用 Token 探测受保护资源的结果。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenProbeResult {
+    /// 服务器接受了该 Token
+    Valid,
+    /// 服务器以 401/403 拒绝了该 Token
+    Rejected,
+    /// 探测请求本身失败 (网络错误等)，无法判断 Token 是否有效
+    Unreachable(String),
+    /// 没有可用于探测的 URL
+    NoProbeUrl,
 }
 
 pub struct TokenRetryResult {
@@ -133,7 +267,7 @@ pub struct TokenRetryResult {
 }
 
 /// 封装了元数据提取过程的结果，比元组更具可读性。
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct MetadataExtractionResult {
     /// 经过所有过滤和协商后最终可供选择的文件列表。
     pub files: Vec<FileInfo>,
@@ -143,4 +277,69 @@ pub struct MetadataExtractionResult {
     pub after_ext_filter_count: usize,
     /// 经过版本选择（清晰度/格式）后剩余的文件数。
     pub after_version_filter_count: usize,
-}
\ No newline at end of file
+}
+/// 下载报告中的文件计数。
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportStats {
+    pub total: usize,
+    pub success: usize,
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+/// 下载报告中的一条跳过/失败记录。
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportEntry {
+    pub filename: String,
+    pub reason: String,
+}
+
+/// JSON 输出模式 (`--output-format json`) 下逐行输出到 stdout 的结构化事件 (NDJSON)。
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum OutputEvent<'a> {
+    /// 单个任务 (链接/ID) 的元数据解析结果
+    Metadata {
+        input: &'a str,
+        #[serde(flatten)]
+        result: &'a MetadataExtractionResult,
+    },
+    /// 单个任务的元数据解析失败
+    MetadataFailed { input: &'a str, error: String },
+    /// 预览模式下的单个文件
+    Preview {
+        #[serde(flatten)]
+        entry: &'a PreviewEntry,
+    },
+    /// 预览模式的汇总
+    PreviewSummary {
+        files: usize,
+        to_download: usize,
+        total_size: u64,
+        download_size: u64,
+        unknown_size: usize,
+    },
+    /// 单个文件的下载结果
+    FileResult {
+        filepath: &'a Path,
+        resource_id: Option<&'a str>,
+        category: ResourceCategory,
+        #[serde(flatten)]
+        result: &'a DownloadResult,
+    },
+    /// 一批下载任务结束后的最终报告
+    Summary {
+        #[serde(flatten)]
+        stats: ReportStats,
+        skipped_files: Vec<ReportEntry>,
+        failed_files: Vec<ReportEntry>,
+    },
+    /// browse/search 子命令列出的单本教材
+    CatalogEntry {
+        index: usize,
+        #[serde(flatten)]
+        entry: &'a CatalogEntry,
+    },
+    /// 导致程序退出的错误
+    Error { message: String, exit_code: i32 },
+}
@@ -3,19 +3,56 @@
 use crate::{constants, error::AppResult, symbols};
 use colored::*;
 use indicatif::{ProgressBar, ProgressStyle};
-use std::io::{self, Write};
+use log::error;
+use serde::Serialize;
+use std::{
+    io::{self, Write},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// 是否处于 JSON 输出模式。开启后所有面向人的文本输出和进度条都会被抑制，
+/// stdout 上只保留 `emit_event` 输出的结构化事件。
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+pub fn set_json_output(enabled: bool) {
+    JSON_OUTPUT.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_json_output() -> bool {
+    JSON_OUTPUT.load(Ordering::Relaxed)
+}
+
+/// 以单行 JSON (NDJSON) 的形式向 stdout 输出一个事件。仅在 JSON 输出模式下生效。
+pub fn emit_event<T: Serialize>(event: &T) {
+    if !is_json_output() {
+        return;
+    }
+    match serde_json::to_string(event) {
+        Ok(line) => println!("{}", line),
+        Err(e) => error!("序列化输出事件失败: {}", e),
+    }
+}
 
 pub fn print_header(title: &str) {
+    if is_json_output() {
+        return;
+    }
     println!("\n{}", "═".repeat(constants::UI_WIDTH));
     println!(" {}", title.cyan().bold());
     println!("{}", "═".repeat(constants::UI_WIDTH));
 }
 
 pub fn print_sub_header(title: &str) {
+    if is_json_output() {
+        return;
+    }
     println!("\n--- {} ---", title.bold());
 }
 
 pub fn box_message(title: &str, content: &[&str], color_func: fn(ColoredString) -> ColoredString) {
+    if is_json_output() {
+        return;
+    }
     println!("\n┌{}┐", "─".repeat(constants::UI_WIDTH - 2));
     println!("  {}", color_func(title.bold()));
     println!("├{}┤", "─".repeat(constants::UI_WIDTH - 2));
@@ -27,16 +64,25 @@ pub fn box_message(title: &str, content: &[&str], color_func: fn(ColoredString)
 
 /// 打印一条普通信息，带 [i] 符号
 pub fn info(message: &str) {
+    if is_json_output() {
+        return;
+    }
     println!("{} {}", *symbols::INFO, message);
 }
 
 /// 打印一条成功信息，带 [OK] 符号
 pub fn success(message: &str) {
+    if is_json_output() {
+        return;
+    }
     println!("{} {}", *symbols::OK, message);
 }
 
 /// 打印一条警告信息，带 [!] 符号，内容为黄色
 pub fn warn(message: &str) {
+    if is_json_output() {
+        return;
+    }
     println!("{} {}", *symbols::WARN, message.yellow());
 }
 
@@ -47,11 +93,17 @@ pub fn error(message: &str) {
 
 /// 打印不带任何符号的普通文本
 pub fn plain(message: &str) {
+    if is_json_output() {
+        return;
+    }
     println!("{}", message);
 }
 
 /// 显示字节进度的进度条
 pub fn new_bytes_progress_bar(total_size: u64, prefix: &str) -> ProgressBar {
+    if is_json_output() {
+        return ProgressBar::hidden();
+    }
     let pbar = ProgressBar::new(total_size);
     pbar.set_style(
         ProgressStyle::with_template(
@@ -67,6 +119,9 @@ pub fn new_bytes_progress_bar(total_size: u64, prefix: &str) -> ProgressBar {
 
 /// 显示任务计数的进度条
 pub fn new_tasks_progress_bar(total_tasks: u64, prefix: &str) -> ProgressBar {
+    if is_json_output() {
+        return ProgressBar::hidden();
+    }
     let pbar = ProgressBar::new(total_tasks);
     pbar.set_style(
         ProgressStyle::with_template(
@@ -9,7 +9,7 @@ use std::{
     collections::BTreeSet,
     ffi::OsStr,
     fs::File,
-    io::{BufReader, Read},
+    io::{BufReader, Read, Write},
     path::{Component, Path, PathBuf},
 };
 
@@ -17,6 +17,8 @@ pub static UUID_PATTERN: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"^[a-f0-9]{8}-([a-f0-9]{4}-){3}[a-f0-9]{12}$").unwrap());
 static ILLEGAL_CHARS_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"[\\/*?:"<>|]"#).unwrap());
 static WHITESPACE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s+").unwrap());
+static TEMPLATE_PLACEHOLDER_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{(\w+)\}").unwrap());
 
 pub fn is_resource_id(text: &str) -> bool {
     UUID_PATTERN.is_match(text)
@@ -69,6 +71,56 @@ pub fn sanitize_filename(name: &str) -> String {
     name
 }
 
+/// 渲染 `{name}` 形式的占位符模板，未知的占位符原样保留 (文件名模板在加载配置时已检查过占位符)
+pub fn render_template(template: &str, values: &[(&str, &str)]) -> String {
+    TEMPLATE_PLACEHOLDER_RE
+        .replace_all(template, |caps: &regex::Captures| {
+            let key = &caps[1];
+            values
+                .iter()
+                .find(|(name, _)| *name == key)
+                .map_or_else(|| caps[0].to_string(), |(_, value)| value.to_string())
+        })
+        .into_owned()
+}
+
+/// 模板中出现的所有占位符名称 (按出现顺序)
+pub fn template_placeholders(template: &str) -> Vec<&str> {
+    TEMPLATE_PLACEHOLDER_RE
+        .captures_iter(template)
+        .filter_map(|caps| caps.get(1).map(|m| m.as_str()))
+        .collect()
+}
+
+/// 按文件名模板生成文件名：`{ext}` 取 `ext`，模板中没有 `{ext}` 时自动追加扩展名，结果经过 `sanitize_filename` 处理
+pub fn render_filename(template: &str, values: &[(&str, &str)], ext: &str) -> String {
+    let values: Vec<(&str, &str)> = values.iter().copied().chain([("ext", ext)]).collect();
+    let mut name = render_template(template, &values);
+    if !template.contains("{ext}") {
+        name = format!("{}.{}", name, ext);
+    }
+    sanitize_filename(&name)
+}
+
+/// 为与视频同名的一组字幕生成文件名后缀 (插在扩展名之前)：有语言时使用语言代码，
+/// 第一条无语言的字幕不加后缀，其余重复的条目追加序号，如 `zh`、`zh.2`、`3`。
+pub fn subtitle_suffixes(languages: &[Option<String>]) -> Vec<Option<String>> {
+    let mut seen = std::collections::HashSet::new();
+    languages
+        .iter()
+        .enumerate()
+        .map(|(i, language)| {
+            let is_first = seen.insert(language.clone());
+            match (language, is_first) {
+                (Some(lang), true) => Some(lang.clone()),
+                (Some(lang), false) => Some(format!("{}.{}", lang, i + 1)),
+                (None, true) => None,
+                (None, false) => Some((i + 1).to_string()),
+            }
+        })
+        .collect()
+}
+
 fn safe_truncate_utf8(s: &str, max_bytes: usize) -> &str {
     if s.len() <= max_bytes {
         return s;
@@ -146,9 +198,32 @@ pub fn calculate_file_md5(path: &Path) -> AppResult<String> {
     Ok(format!("{:x}", result))
 }
 
+/// 先写入同目录下的临时文件再重命名，写入中断时目标路径不会留下不完整的文件
+pub fn write_file_atomic(path: &Path, contents: impl AsRef<[u8]>) -> AppResult<()> {
+    let parent = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut temp_file = tempfile::NamedTempFile::new_in(parent)?;
+    temp_file.write_all(contents.as_ref())?;
+    temp_file.persist(path)?;
+    Ok(())
+}
+
 pub fn secure_join_path(base_dir: &Path, relative_path: &Path) -> AppResult<PathBuf> {
     let resolved_base = dunce::canonicalize(base_dir)
         .with_context(|| format!("基础目录 '{:?}' 不存在或无法访问", base_dir))?;
+    join_normal_components(resolved_base, relative_path)
+}
+
+/// 与 `secure_join_path` 相同，但不要求基础目录已存在，也不会创建它 (用于预览模式)。
+pub fn preview_join_path(base_dir: &Path, relative_path: &Path) -> AppResult<PathBuf> {
+    let resolved_base = if base_dir.exists() {
+        dunce::canonicalize(base_dir)?
+    } else {
+        std::path::absolute(base_dir)?
+    };
+    join_normal_components(resolved_base, relative_path)
+}
+
+fn join_normal_components(resolved_base: PathBuf, relative_path: &Path) -> AppResult<PathBuf> {
     let mut final_path = resolved_base.clone();
     for component in relative_path.components() {
         match component {
@@ -168,6 +243,17 @@ pub fn secure_join_path(base_dir: &Path, relative_path: &Path) -> AppResult<Path
     Ok(final_path)
 }
 
+/// 隐藏敏感字符串 (如 Token) 的中间部分，只保留首尾各 4 个字符用于辨认。
+pub fn mask_secret(secret: &str) -> String {
+    let chars: Vec<char> = secret.chars().collect();
+    if chars.len() <= 8 {
+        return "*".repeat(chars.len());
+    }
+    let head: String = chars[..4].iter().collect();
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}...{}", head, tail)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -221,7 +307,36 @@ mod tests {
         let very_long_name = "这是一个非常长的文件名.txt"; // 46 bytes
         let truncated = sanitize_filename(very_long_name);
         // "这是一个非.txt" -> 12 (4*3) + 1 + 3 = 16 bytes
-        assert!(truncated.as_bytes().len() <= constants::MAX_FILENAME_BYTES);
+        assert!(truncated.len() <= constants::MAX_FILENAME_BYTES);
         assert!(truncated.ends_with(".txt"));
     }
+
+    #[test]
+    fn test_render_filename() {
+        let values = [("course", "数学"), ("teacher", "张/老师"), ("quality", "720")];
+        assert_eq!(
+            render_filename("{course} [{quality}] - [{teacher}].{ext}", &values, "ts"),
+            "数学 [720] - [张 老师].ts"
+        );
+        // 模板中没有 {ext} 时自动追加扩展名；未知占位符原样保留
+        assert_eq!(render_filename("{course} {unknown}", &values, "pdf"), "数学 {unknown}.pdf");
+    }
+
+    #[test]
+    fn test_mask_secret() {
+        assert_eq!(mask_secret("abcdefghijkl"), "abcd...ijkl");
+        assert_eq!(mask_secret("short"), "*****");
+    }
+
+    #[test]
+    fn test_preview_join_path_does_not_create_dir() {
+        let temp = tempfile::tempdir().unwrap();
+        let base = temp.path().join("not-yet-created");
+
+        let joined = preview_join_path(&base, Path::new("教材/数学.pdf")).unwrap();
+        assert!(joined.ends_with("not-yet-created/教材/数学.pdf"));
+        assert!(!base.exists(), "预览模式不应创建输出目录");
+
+        assert!(preview_join_path(&base, Path::new("../escape.pdf")).is_err());
+    }
 }
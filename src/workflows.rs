@@ -1,28 +1,91 @@
 // src/workflows.rs
 
 use crate::{
-    cli::ResourceType,
-    constants,
-    downloader::ResourceDownloader,
+    cli::{CatalogFilter, ConfigCommand, LocalTokenCommand, ResourceType},
+    config,
+    constants::{self, exit_codes},
+    downloader::{find_probe_url, probe_token, DownloadStateStore, ResourceDownloader},
+    extractor::{
+        catalog::{self, TextbookCatalog},
+        textbook_lessons::TextbookLessonsResolver,
+    },
     error::{AppError, AppResult},
-    models::{FileInfo, MetadataExtractionResult},
+    models::{
+        CatalogEntry, DownloadAction, FileInfo, MetadataExtractionResult, OutputEvent, PreviewEntry,
+        TokenProbeResult,
+    },
     symbols, ui, utils, DownloadJobContext,
 };
 use anyhow::anyhow;
+use chrono::{DateTime, Local, TimeDelta, Utc};
 use colored::*;
 use futures::{stream, StreamExt};
+use indicatif::HumanBytes;
 use log::{debug, warn};
 use reqwest::StatusCode;
-use std::path::PathBuf;
+use std::{
+    io::{self, IsTerminal, Read},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use url::Url;
 
+/// 一次运行结束后的汇总结果，用于决定进程退出码。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RunOutcome {
+    /// 下载或校验失败的文件数
+    pub failed_files: usize,
+    /// 元数据解析失败的任务数
+    pub metadata_failures: usize,
+}
+
+impl RunOutcome {
+    fn from_download_stats(context: &DownloadJobContext) -> Self {
+        Self {
+            failed_files: context.manager.get_stats().failed,
+            metadata_failures: 0,
+        }
+    }
+
+    /// 元数据解析失败意味着部分任务根本没有被尝试下载，因此优先于文件下载失败。
+    pub fn exit_code(&self) -> i32 {
+        if self.metadata_failures > 0 {
+            exit_codes::METADATA_FAILED
+        } else if self.failed_files > 0 {
+            exit_codes::DOWNLOAD_FAILED
+        } else {
+            exit_codes::SUCCESS
+        }
+    }
+}
+
 /// 运行单任务模式（处理 --url 或 --id）
-pub(crate) async fn run_single(context: DownloadJobContext) -> AppResult<()> {
+pub(crate) async fn run_single(context: DownloadJobContext) -> AppResult<RunOutcome> {
     let downloader = ResourceDownloader::new(context.clone());
     // 在单任务模式下，url 或 id 必须存在，这是由 clap 的 arg_required_else_help 保证的
     let task_input = context.args.url.as_deref().or(context.args.id.as_deref()).unwrap();
     
-    let metadata_result = downloader.fetch_metadata(task_input).await?;
+    let metadata_result = match downloader.fetch_metadata(task_input).await {
+        Ok(result) => result,
+        // Token 问题和用户中断保留各自的退出码
+        Err(e @ (AppError::TokenInvalid
+        | AppError::TokenMissing
+        | AppError::TokenExpiringSoon(_)
+        | AppError::UserInterrupt)) => return Err(e),
+        Err(e) => {
+            log::error!("解析任务 '{}' 失败: {}", task_input, e);
+            ui::emit_event(&OutputEvent::MetadataFailed {
+                input: task_input,
+                error: e.to_string(),
+            });
+            ui::error(&format!("{} ({})", task_input, describe_metadata_error(&e)));
+            return Err(AppError::MetadataFailed(1));
+        }
+    };
+    ui::emit_event(&OutputEvent::Metadata {
+        input: task_input,
+        result: &metadata_result,
+    });
     let all_files = metadata_result.files;
 
     print_single_task_filter_summary(
@@ -32,13 +95,34 @@ pub(crate) async fn run_single(context: DownloadJobContext) -> AppResult<()> {
         metadata_result.after_version_filter_count,
     );
     
+    if context.args.selection.dry_run {
+        let entries = downloader.preview_items(all_files)?;
+        print_dry_run_report(&entries);
+        return Ok(RunOutcome::default());
+    }
+
     downloader.process_and_download_items(all_files).await?;
-    Ok(())
+    Ok(RunOutcome::from_download_stats(&context))
 }
 
 
+/// 生成单个任务元数据解析失败时的简短说明
+fn describe_metadata_error(e: &AppError) -> String {
+    match e {
+        AppError::Network(req_err) => match req_err.status() {
+            Some(status) if status == StatusCode::FORBIDDEN || status == StatusCode::NOT_FOUND => {
+                "资源不存在 (链接或ID错误)".to_string()
+            }
+            Some(status) => format!("网络错误: {}", status),
+            None => "网络连接失败".to_string(),
+        },
+        _ => e.to_string(),
+    }
+}
+
 /// 运行交互模式
-pub(crate) async fn run_interactive(base_context: DownloadJobContext) -> AppResult<()> {
+/// 交互模式由用户逐条驱动，单个条目的失败已当场提示，因此正常退出时总是返回成功。
+pub(crate) async fn run_interactive(base_context: DownloadJobContext) -> AppResult<RunOutcome> {
     ui::print_header("交互模式");
     ui::plain(&format!("在此模式下，你可以逐一输入 链接 或 ID 进行下载。按 {} 可随时退出。", *symbols::CTRL_C));
 
@@ -97,32 +181,36 @@ pub(crate) async fn run_interactive(base_context: DownloadJobContext) -> AppResu
     
     ui::plain("");
     ui::info("退出交互模式。");
-    Ok(())
+    Ok(RunOutcome::default())
 }
 
 /// 运行批量模式
-pub(crate) async fn run_batch(batch_file: PathBuf, base_context: DownloadJobContext) -> AppResult<()> {
+pub(crate) async fn run_batch(batch_file: PathBuf, base_context: DownloadJobContext) -> AppResult<RunOutcome> {
     let content = std::fs::read_to_string(&batch_file).map_err(AppError::from)?;
     let tasks: Vec<String> = content.lines().map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect();
     if tasks.is_empty() {
         ui::warn("批量文件为空。");
-        return Ok(());
+        return Ok(RunOutcome::default());
     }
+    run_batch_tasks(tasks, base_context).await
+}
 
+/// 批量解析并下载多个链接或ID (批量文件和 browse 子命令共用)
+async fn run_batch_tasks(tasks: Vec<String>, base_context: DownloadJobContext) -> AppResult<RunOutcome> {
     let downloader = ResourceDownloader::new(base_context.clone());
 
     ui::print_header(&format!("阶段 1/2: 批量解析任务 (共 {} 个)", tasks.len()));
 
     // ... (批量模式的其余代码保持不变) ...
     let mut global_filters = Vec::new();
-    if let Some(exts) = &base_context.args.filter_ext {
+    if let Some(exts) = &base_context.args.options.filter_ext {
         global_filters.push(format!("扩展名保留: {}", exts.join(",")));
     }
-    if base_context.args.video_quality != constants::DEFAULT_VIDEO_QUALITY {
-        global_filters.push(format!("视频选择策略: '{}'", base_context.args.video_quality));
+    if base_context.args.selection.video_quality != constants::DEFAULT_VIDEO_QUALITY {
+        global_filters.push(format!("视频选择策略: '{}'", base_context.args.selection.video_quality));
     }
-    if base_context.args.audio_format != constants::DEFAULT_AUDIO_FORMAT {
-        global_filters.push(format!("音频选择策略: '{}'", base_context.args.audio_format));
+    if base_context.args.selection.audio_format != constants::DEFAULT_AUDIO_FORMAT {
+        global_filters.push(format!("音频选择策略: '{}'", base_context.args.selection.audio_format));
     }
 
     if !global_filters.is_empty() {
@@ -149,6 +237,10 @@ pub(crate) async fn run_batch(batch_file: PathBuf, base_context: DownloadJobCont
     while let Some((task, result, pbar)) = stream.next().await {
         match result {
             Ok(metadata_result) => {
+                ui::emit_event(&OutputEvent::Metadata {
+                    input: &task,
+                    result: &metadata_result,
+                });
                 let files = metadata_result.files;
                 let original_count = metadata_result.original_count;
                 let ext_filtered_count = metadata_result.after_ext_filter_count;
@@ -187,17 +279,16 @@ pub(crate) async fn run_batch(batch_file: PathBuf, base_context: DownloadJobCont
             Err(e) => {
                 metadata_failed += 1;
                 log::error!("解析任务 '{}' 失败: {}", task, e);
-                let error_message = match &e {
-                    AppError::Network(req_err) => {
-                        if let Some(status) = req_err.status() {
-                            format!("网络错误: {}", status)
-                        } else {
-                            "网络连接失败".to_string()
-                        }
-                    },
-                    _ => e.to_string(),
-                };
-                pbar.println(format!("{} {} ({})", *symbols::ERROR, utils::truncate_text(&task, 60), error_message));
+                ui::emit_event(&OutputEvent::MetadataFailed {
+                    input: &task,
+                    error: e.to_string(),
+                });
+                pbar.println(format!(
+                    "{} {} ({})",
+                    *symbols::ERROR,
+                    utils::truncate_text(&task, 60),
+                    describe_metadata_error(&e)
+                ));
             }
         }
         pbar.inc(1);
@@ -209,18 +300,388 @@ pub(crate) async fn run_batch(batch_file: PathBuf, base_context: DownloadJobCont
         ui::print_header("任务报告");
         ui::info("未能从任何任务中解析到可下载的文件。");
         return if metadata_failed > 0 {
-            Err(AppError::Other(anyhow!("{} 个任务元数据解析失败。", metadata_failed)))
-        } else { Ok(()) };
+            Err(AppError::MetadataFailed(metadata_failed))
+        } else { Ok(RunOutcome::default()) };
     }
 
     let successful_tasks_count = tasks.len() - metadata_failed;
+    if base_context.args.selection.dry_run {
+        ui::print_header(&format!(
+            "预览: 批量任务 (成功 {} 个任务，共 {} 个文件)",
+            successful_tasks_count,
+            all_files_to_process.len()
+        ));
+        let entries = downloader.preview_items(all_files_to_process)?;
+        print_dry_run_report(&entries);
+        print_metadata_failure_notice(metadata_failed);
+        return Ok(RunOutcome {
+            failed_files: 0,
+            metadata_failures: metadata_failed,
+        });
+    }
+
+    downloader.preflight_token_check(&all_files_to_process).await?;
+
     ui::print_header(&format!(
         "阶段 2/2: 批量下载任务 (成功 {} 个任务，共 {} 个文件)",
         successful_tasks_count,
         all_files_to_process.len()
     ));
     downloader.process_and_download_items(all_files_to_process).await?;
+    print_metadata_failure_notice(metadata_failed);
+
+    Ok(RunOutcome {
+        metadata_failures: metadata_failed,
+        ..RunOutcome::from_download_stats(&base_context)
+    })
+}
 
+/// 运行 browse/search 子命令：获取教材目录并按条件筛选。
+/// 非交互模式下列出结果，指定 --download 时按 --select 下载；交互模式下逐级选择后下载。
+pub(crate) async fn run_browse(filter: &CatalogFilter, download: bool, context: DownloadJobContext) -> AppResult<RunOutcome> {
+    ui::info("正在获取教材目录...");
+    let catalog = TextbookCatalog::new(context.http_client.clone(), context.config.clone());
+    let mut entries = catalog::filter_entries(catalog.fetch_entries().await?, filter);
+
+    if !context.non_interactive {
+        ui::print_header("浏览教材目录");
+        // 逐级缩小范围：命令行未指定且仍有多个取值的维度由用户选择 (默认全部)
+        for (dimension, label, wanted) in filter.dimension_filters() {
+            let values = catalog::dimension_values(&entries, dimension);
+            if wanted.is_some() || values.len() < 2 {
+                continue;
+            }
+            let chosen = ui::get_user_choices_from_menu(&values, &format!("选择{}", label), constants::DEFAULT_SELECTION)?;
+            entries.retain(|e| e.tag_name(dimension).is_some_and(|name| chosen.iter().any(|c| c == name)));
+        }
+    }
+
+    if entries.is_empty() {
+        ui::warn("没有符合条件的教材。");
+        return Ok(RunOutcome::default());
+    }
+
+    let options: Vec<String> = entries.iter().map(format_catalog_entry).collect();
+    let selected_ids: Vec<String> = if context.non_interactive {
+        ui::print_header(&format!("教材目录 (共 {} 本)", entries.len()));
+        for (i, (entry, option)) in entries.iter().zip(&options).enumerate() {
+            ui::emit_event(&OutputEvent::CatalogEntry { index: i + 1, entry });
+            ui::plain(&format!("  [{}] {}", i + 1, option));
+        }
+        if !download {
+            ui::plain("");
+            ui::info("使用 --download (可配合 --select) 下载列出的教材，或使用 `download <ID> --type tchMaterial` 下载单本教材。");
+            return Ok(RunOutcome::default());
+        }
+        // 没有任何筛选条件时默认的 'all' 会下载整个目录，必须明确指定要下载的条目
+        if filter.is_empty() && context.args.selection.select.is_none() {
+            ui::plain("");
+            ui::warn("未指定任何筛选条件，不会下载整个教材目录。请添加关键字或筛选条件，或使用 --select 明确指定要下载的条目。");
+            return Ok(RunOutcome::default());
+        }
+        utils::parse_selection_indices(context.args.selection.selection(), entries.len())
+            .into_iter()
+            .map(|i| entries[i].id.clone())
+            .collect()
+    } else {
+        let user_input = ui::selection_menu(
+            &options,
+            &format!("教材列表 (共 {} 本)", entries.len()),
+            "支持格式: 1, 3, 2-4, all",
+            context.args.selection.selection(),
+        )?;
+        utils::parse_selection_indices(&user_input, entries.len())
+            .into_iter()
+            .map(|i| entries[i].id.clone())
+            .collect()
+    };
+
+    if selected_ids.is_empty() {
+        ui::info("未选择任何教材，任务结束。");
+        return Ok(RunOutcome::default());
+    }
+    debug!("从教材目录选择了 {} 本教材: {:?}", selected_ids.len(), selected_ids);
+    run_batch_tasks(selected_ids, selected_batch_context(context)).await
+}
+
+/// 从列表中选定条目后用于批量下载的上下文。`--select` 已用于选择条目，不再筛选每个资源内的文件；
+/// 批量下载总是非交互的，按 --video-quality/--audio-format 选择版本，而不是为每个资源弹出协商菜单。
+fn selected_batch_context(context: DownloadJobContext) -> DownloadJobContext {
+    let mut args = (*context.args).clone();
+    args.selection.select = None;
+    DownloadJobContext {
+        args: Arc::new(args),
+        non_interactive: true,
+        ..context
+    }
+}
+
+/// 运行 lessons 子命令：遍历教材的章节树，批量下载每个章节下的同步课堂课时。
+pub(crate) async fn run_lessons(textbook: &str, chapter: Option<&str>, context: DownloadJobContext) -> AppResult<RunOutcome> {
+    let textbook_id = resolve_textbook_id(textbook, &context)?;
+    ui::info("正在获取教材的章节目录和课时列表...");
+    let resolver = TextbookLessonsResolver::new(context.http_client.clone(), context.config.clone())
+        .with_chapter_cache(context.chapter_cache.clone());
+    let mut lessons = resolver.list_lessons(&textbook_id).await?;
+    if let Some(keyword) = chapter {
+        lessons.retain(|lesson| lesson.chapter_path.iter().any(|c| c.contains(keyword)));
+    }
+    if lessons.is_empty() {
+        ui::warn("该教材下没有找到符合条件的同步课时。");
+        return Ok(RunOutcome::default());
+    }
+
+    ui::print_header(&format!("教材同步课时 (共 {} 节)", lessons.len()));
+    for (i, lesson) in lessons.iter().enumerate() {
+        let chapter_path = if lesson.chapter_path.is_empty() {
+            "未知章节".to_string()
+        } else {
+            lesson.chapter_path.join(" > ")
+        };
+        ui::plain(&format!("  [{}] {} > {}", i + 1, chapter_path.dimmed(), lesson.title));
+    }
+    let lesson_ids: Vec<String> =
+        utils::parse_selection_indices(context.args.selection.selection(), lessons.len())
+            .into_iter()
+            .map(|i| lessons[i].id.clone())
+            .collect();
+    if lesson_ids.is_empty() {
+        ui::info("未选择任何课时，任务结束。");
+        return Ok(RunOutcome::default());
+    }
+    debug!("选择了 {} 节课时: {:?}", lesson_ids.len(), lesson_ids);
+    run_batch_tasks(lesson_ids, selected_batch_context(context)).await
+}
+
+/// 从教材链接 (contentId 参数) 或教材ID 中取得教材ID
+fn resolve_textbook_id(input: &str, context: &DownloadJobContext) -> AppResult<String> {
+    if utils::is_resource_id(input) {
+        return Ok(input.to_string());
+    }
+    let id_param = context
+        .config
+        .api_endpoints
+        .get(constants::api::types::TCH_MATERIAL)
+        .map_or("contentId", |conf| conf.id_param.as_str());
+    Url::parse(input)
+        .ok()
+        .and_then(|url| {
+            url.query_pairs()
+                .find(|(k, _)| k == id_param)
+                .map(|(_, v)| v.to_string())
+        })
+        .filter(|id| utils::is_resource_id(id))
+        .ok_or_else(|| AppError::UserInputError(format!("无法从 '{}' 中识别教材ID。", input)))
+}
+
+fn format_catalog_entry(entry: &CatalogEntry) -> String {
+    use constants::api::dimensions::*;
+    let tags: Vec<&str> = [STAGE, GRADE, SUBJECT, VERSION, VOLUME]
+        .iter()
+        .filter_map(|d| entry.tag_name(d))
+        .collect();
+    format!("{} [{}] {}", entry.title, tags.join("/"), entry.id.dimmed())
+}
+
+/// 显示获取 Access Token 的指南
+pub(crate) fn print_token_guide() {
+    ui::box_message(
+        "获取 Access Token 指南",
+        constants::HELP_TOKEN_GUIDE.lines().collect::<Vec<_>>().as_slice(),
+        |s| s.cyan(),
+    );
+    ui::plain("");
+    ui::info("安全提醒: 请妥善保管你的 Token。");
+}
+
+/// 运行 `token` 子命令中不需要网络的部分 (guide/show/set/clear/migrate)
+pub(crate) fn run_token(action: &LocalTokenCommand, profile: Option<&str>) -> AppResult<RunOutcome> {
+    match action {
+        LocalTokenCommand::Guide => print_token_guide(),
+        LocalTokenCommand::Show { reveal } => {
+            let (token_opt, source) = config::token::resolve_token(None, profile);
+            let Some(token) = token_opt else {
+                ui::warn("未找到 Access Token。可使用 `token set` 保存一个。");
+                return Ok(RunOutcome::default());
+            };
+            ui::info(&format!("Token 来源: {}", source));
+            let display = if *reveal { token.clone() } else { utils::mask_secret(&token) };
+            ui::info(&format!("Token: {}", display));
+            print_token_expiry(&token);
+        }
+        LocalTokenCommand::Set => {
+            let token = read_token_from_stdin()?;
+            if token.is_empty() {
+                return Err(AppError::UserInputError("Token 不能为空。".to_string()));
+            }
+            config::token::save_token(&token, profile)?;
+            print_token_expiry(&token);
+        }
+        LocalTokenCommand::Clear => {
+            if config::token::clear_token(profile)? {
+                ui::success("已从配置文件中删除 Token。");
+            } else {
+                ui::info("配置文件中没有保存 Token。");
+            }
+        }
+        LocalTokenCommand::Migrate { to } => {
+            match config::token::migrate_token_storage(*to)? {
+                Some(count) => ui::success(&format!(
+                    "已将 {} 个 Token 迁移到{}，配置文件中的 token_storage 已更新。",
+                    count,
+                    to.label()
+                )),
+                None => ui::info(&format!("Token 已经保存在{}中，无需迁移。", to.label())),
+            }
+        }
+    }
+    Ok(RunOutcome::default())
+}
+
+/// 运行 `token validate` 子命令：检查 Token 的有效期，并可选地用一个资源探测其是否被服务器接受
+pub(crate) async fn run_token_validate(
+    input: Option<&str>,
+    context: DownloadJobContext,
+) -> AppResult<RunOutcome> {
+    let token = context.token.lock().await.clone();
+    if token.is_empty() {
+        return Err(AppError::TokenMissing);
+    }
+    if print_token_expiry(&token).is_some_and(|expiry| expiry <= Utc::now()) {
+        return Err(AppError::TokenInvalid);
+    }
+
+    let probe_url = match input {
+        Some(input) => {
+            let downloader = ResourceDownloader::new(context.clone());
+            let metadata_result = downloader.fetch_metadata(input).await?;
+            find_probe_url(&metadata_result.files).map(str::to_string)
+        }
+        None => None,
+    };
+
+    match probe_token(&context.http_client, &token, probe_url.as_deref()).await {
+        TokenProbeResult::Valid => ui::success("Token 有效，服务器接受了探测请求。"),
+        TokenProbeResult::Rejected => return Err(AppError::TokenInvalid),
+        TokenProbeResult::Unreachable(e) => {
+            return Err(AppError::Other(anyhow!("无法完成 Token 探测: {}", e)));
+        }
+        TokenProbeResult::NoProbeUrl => {
+            ui::warn("未提供可用于探测的资源，仅检查了 Token 的有效期。");
+            ui::plain("    用法: token validate <需要登录才能下载的资源链接或ID>");
+        }
+    }
+    Ok(RunOutcome::default())
+}
+
+/// 运行 `config` 子命令
+pub(crate) fn run_config(action: &ConfigCommand) -> AppResult<RunOutcome> {
+    let config_path = config::token::get_config_path()?;
+    match action {
+        ConfigCommand::Path => ui::plain(&config_path.display().to_string()),
+        ConfigCommand::Show => {
+            let mut external_config = config::token::load_or_create_external_config()?;
+            external_config.accesstoken = external_config
+                .accesstoken
+                .map(|token| utils::mask_secret(&token));
+            for profile in external_config.profiles.values_mut() {
+                profile.accesstoken = profile.accesstoken.as_deref().map(utils::mask_secret);
+            }
+            ui::info(&format!("配置文件: {}", config_path.display()));
+            ui::plain(&serde_json::to_string_pretty(&external_config)?);
+        }
+    }
+    Ok(RunOutcome::default())
+}
+
+/// 运行 `verify` 子命令：根据下载状态数据库重新检查已下载的文件
+pub(crate) fn run_verify(dir: Option<&Path>, check_md5: bool) -> AppResult<RunOutcome> {
+    let state = DownloadStateStore::open_default()?;
+    let base_dir = dir.map(dunce::canonicalize).transpose()?;
+    let records: Vec<_> = state
+        .records()
+        .into_iter()
+        .filter(|r| base_dir.as_ref().is_none_or(|base| r.filepath.starts_with(base)))
+        .collect();
+
+    ui::print_header(&format!("校验下载记录 (共 {} 个文件)", records.len()));
+    if records.is_empty() {
+        ui::info("没有找到任何下载记录。");
+        return Ok(RunOutcome::default());
+    }
+
+    let pbar = ui::new_tasks_progress_bar(records.len() as u64, "校验");
+    let mut problems = 0;
+    for record in &records {
+        if let Some(problem) = record.verify(check_md5)? {
+            problems += 1;
+            warn!("文件 {:?} 校验未通过: {}", record.filepath, problem);
+            pbar.println(format!(
+                "{} {} ({})",
+                *symbols::ERROR,
+                record.filepath.display(),
+                problem.red()
+            ));
+        }
+        pbar.inc(1);
+    }
+    pbar.finish_and_clear();
+
+    ui::print_sub_header("校验总结");
+    if problems == 0 {
+        ui::success(&format!("全部 {} 个文件校验通过。", records.len()));
+    } else {
+        ui::error(&format!(
+            "{} 个文件存在问题 (共 {} 个)。可使用 --force-redownload-ids 重新下载对应资源。",
+            problems,
+            records.len()
+        ));
+    }
+    Ok(RunOutcome {
+        failed_files: problems,
+        metadata_failures: 0,
+    })
+}
+
+// --- 模块内部辅助函数 ---
+
+/// 打印 Token 的有效期 (仅当 Token 为 JWT 格式时可解析)，并返回解析出的过期时间。
+fn print_token_expiry(token: &str) -> Option<DateTime<Utc>> {
+    let expiry = config::token::decode_token_expiry(token);
+    match expiry {
+        Some(expiry) => {
+            let local_str = expiry.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S");
+            let remaining = expiry - Utc::now();
+            if remaining <= TimeDelta::zero() {
+                ui::warn(&format!("Token 已于 {} 过期。", local_str));
+            } else {
+                ui::info(&format!(
+                    "Token 有效期至 {} (剩余 {} 天 {} 小时)。",
+                    local_str,
+                    remaining.num_days(),
+                    remaining.num_hours() % 24
+                ));
+            }
+        }
+        None => ui::info("无法从 Token 中解析出有效期 (非 JWT 格式)。"),
+    }
+    expiry
+}
+
+/// 读取要保存的 Token：在终端中使用隐藏输入，否则从标准输入 (管道) 读取全部内容。
+fn read_token_from_stdin() -> AppResult<String> {
+    let stdin = io::stdin();
+    if stdin.is_terminal() {
+        return ui::prompt_hidden("请输入新 Token (输入不可见，完成后按回车)")
+            .map(|s| s.trim().to_string())
+            .map_err(|_| AppError::UserInterrupt);
+    }
+    let mut input = String::new();
+    stdin.lock().read_to_string(&mut input)?;
+    Ok(input.trim().to_string())
+}
+
+fn print_metadata_failure_notice(metadata_failed: usize) {
     if metadata_failed > 0 {
         let warning_message = format!(
             "额外信息: 在开始下载前，有 {} 个任务的元数据解析失败。",
@@ -229,11 +690,94 @@ pub(crate) async fn run_batch(batch_file: PathBuf, base_context: DownloadJobCont
         ui::plain("");
         ui::warn(&warning_message);
     }
-
-    Ok(())
 }
 
-// --- 模块内部辅助函数 ---
+/// 打印预览模式 (--dry-run) 的文件清单
+fn print_dry_run_report(entries: &[PreviewEntry]) {
+    if entries.is_empty() && !ui::is_json_output() {
+        ui::plain("");
+        ui::info("未能提取到任何可下载的文件信息 (或所有文件均被过滤)。");
+        return;
+    }
+
+    let mut total_size = 0u64;
+    let mut download_size = 0u64;
+    let mut unknown_size = 0usize;
+    let mut to_download = 0usize;
+    for (index, entry) in entries.iter().enumerate() {
+        let file = &entry.file;
+        let size_str = match file.ti_size {
+            Some(size) => {
+                total_size += size;
+                HumanBytes(size).to_string()
+            }
+            None => {
+                unknown_size += 1;
+                "大小未知".to_string()
+            }
+        };
+        let date_str = file
+            .date
+            .map_or("日期未知".to_string(), |d| d.format("%Y-%m-%d").to_string());
+        let action_str = match entry.action {
+            DownloadAction::Skip => "跳过".green(),
+            DownloadAction::Resume => "续传".yellow(),
+            DownloadAction::DownloadNew => "下载".cyan(),
+        };
+        if entry.action != DownloadAction::Skip {
+            to_download += 1;
+            download_size += file.ti_size.unwrap_or(0);
+        }
+
+        ui::plain(&format!(
+            "{:>4}. [{}] {}",
+            index + 1,
+            action_str,
+            file.filepath.display()
+        ));
+        let kind_str = match file.variant_label() {
+            Some(variant) => format!("{} {}", file.category.label(), variant),
+            None => file.category.label().to_string(),
+        };
+        ui::plain(&format!(
+            "      {} | {} | {} | {}",
+            kind_str,
+            size_str,
+            date_str,
+            entry.reason.dimmed()
+        ));
+        ui::plain(&format!("      {}", file.url.dimmed()));
+    }
+
+    if ui::is_json_output() {
+        for entry in entries {
+            ui::emit_event(&OutputEvent::Preview { entry });
+        }
+        ui::emit_event(&OutputEvent::PreviewSummary {
+            files: entries.len(),
+            to_download,
+            total_size,
+            download_size,
+            unknown_size,
+        });
+        return;
+    }
+
+    let unknown_str = if unknown_size > 0 {
+        format!("，{} 个文件大小未知", unknown_size)
+    } else {
+        String::new()
+    };
+    ui::plain("");
+    ui::info(&format!(
+        "共 {} 个文件 (约 {})，其中 {} 个需要下载 (约 {}){}。预览模式下未下载任何文件。",
+        entries.len(),
+        HumanBytes(total_size),
+        to_download,
+        HumanBytes(download_size),
+        unknown_str
+    ));
+}
 
 /// 打印单任务的过滤总结
 fn print_single_task_filter_summary(
@@ -242,22 +786,22 @@ fn print_single_task_filter_summary(
     ext_filtered_count: usize,
     version_filtered_count: usize,
 ) {
-    if original_count > ext_filtered_count {
-        if let Some(exts) = &context.args.filter_ext {
-            ui::info(&format!(
-                "已应用扩展名过滤器 (保留: {}), 文件数量从 {} 个变为 {} 个。",
-                exts.join(","), original_count, ext_filtered_count
-            ));
-        }
+    if original_count > ext_filtered_count
+        && let Some(exts) = &context.args.options.filter_ext
+    {
+        ui::info(&format!(
+            "已应用扩展名过滤器 (保留: {}), 文件数量从 {} 个变为 {} 个。",
+            exts.join(","), original_count, ext_filtered_count
+        ));
     }
 
     if ext_filtered_count > version_filtered_count {
         let mut filters_applied = Vec::new();
-        if context.args.video_quality != constants::DEFAULT_VIDEO_QUALITY {
-            filters_applied.push(format!("视频 '{}'", context.args.video_quality));
+        if context.args.selection.video_quality != constants::DEFAULT_VIDEO_QUALITY {
+            filters_applied.push(format!("视频 '{}'", context.args.selection.video_quality));
         }
-        if context.args.audio_format != constants::DEFAULT_AUDIO_FORMAT {
-            filters_applied.push(format!("音频 '{}'", context.args.audio_format));
+        if context.args.selection.audio_format != constants::DEFAULT_AUDIO_FORMAT {
+            filters_applied.push(format!("音频 '{}'", context.args.selection.audio_format));
         }
         
         if !filters_applied.is_empty() {
@@ -286,7 +830,7 @@ async fn process_id_with_auto_detect(
     for r#type in resource_types {
         let mut context = base_context.clone();
         let mut new_args = (*context.args).clone();
-        new_args.r#type = Some(r#type);
+        new_args.selection.r#type = Some(r#type);
         context.args = std::sync::Arc::new(new_args);
 
         let downloader = ResourceDownloader::new(context);
@@ -0,0 +1,84 @@
+// tests/catalog_test.rs
+
+use sed_dl::{
+    cli::CatalogFilter,
+    client::RobustClient,
+    config::AppConfig,
+    error::AppResult,
+    extractor::catalog::{filter_entries, TextbookCatalog},
+};
+use std::sync::Arc;
+
+#[tokio::test]
+async fn test_catalog_fetches_all_parts_and_filters() -> AppResult<()> {
+    // --- 1. Arrange (准备阶段) ---
+    let mut server = mockito::Server::new_async().await;
+    let server_url = server.url();
+
+    // 版本信息中的分片地址是完整 URL，以逗号分隔
+    let version_mock = server
+        .mock("GET", "/zxx/ndrs/resources/tch_material/version/data_version.json")
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(format!(
+            r#"{{"module": "tch_material", "urls": "{0}/parts/part_100.json,{0}/parts/part_101.json"}}"#,
+            server_url
+        ))
+        .create_async()
+        .await;
+
+    let catalog_body = std::fs::read_to_string("tests/fixtures/textbook_catalog_part.json").unwrap();
+    let part1_mock = server
+        .mock("GET", "/parts/part_100.json")
+        .with_status(200)
+        .with_body(&catalog_body)
+        .create_async()
+        .await;
+    // 第二个分片与第一个有重复的教材
+    let part2_mock = server
+        .mock("GET", "/parts/part_101.json")
+        .with_status(200)
+        .with_body(
+            r#"[
+                {"id": "book-3", "title": "数学七年级上册", "tag_list": [
+                    {"tag_dimension_id": "zxxxd", "tag_name": "初中"},
+                    {"tag_dimension_id": "zxxxk", "tag_name": "数学"}
+                ]},
+                {"id": "book-1", "global_title": {"zh-CN": "语文一年级上册"}}
+            ]"#,
+        )
+        .create_async()
+        .await;
+
+    let mut config = AppConfig::default();
+    config.url_templates.insert(
+        "TEXTBOOK_CATALOG".to_string(),
+        format!("{}/zxx/ndrs/resources/tch_material/version/data_version.json", server_url),
+    );
+    let config = Arc::new(config);
+    let catalog = TextbookCatalog::new(Arc::new(RobustClient::new(config.clone())?), config);
+
+    // --- 2. Act (执行阶段) ---
+    let entries = catalog.fetch_entries().await?;
+
+    // --- 3. Assert (断言阶段) ---
+    version_mock.assert_async().await;
+    part1_mock.assert_async().await;
+    part2_mock.assert_async().await;
+
+    let ids: Vec<&str> = entries.iter().map(|e| e.id.as_str()).collect();
+    assert_eq!(ids, ["book-1", "book-2", "book-3"], "应按 ID 去重并保持目录顺序");
+    assert_eq!(entries[0].title, "义务教育教科书·语文一年级上册");
+    assert_eq!(entries[0].tag_name("zxxbb"), Some("统编版"));
+
+    let filter = CatalogFilter {
+        stage: Some("小学".to_string()),
+        subject: Some("数学".to_string()),
+        ..Default::default()
+    };
+    let filtered = filter_entries(entries, &filter);
+    assert_eq!(filtered.len(), 1);
+    assert_eq!(filtered[0].id, "book-2");
+
+    Ok(())
+}
@@ -45,6 +45,7 @@ fn test_id_mode_requires_type() {
     cmd.arg("--id").arg("some-uuid");
     cmd.assert()
         .failure()
+        .code(1)
         .stderr(predicate::str::contains("the following required arguments were not provided:\n  --type <TYPE>"));
 }
 
@@ -93,7 +94,279 @@ fn test_batch_mode_dispatch() {
     
     cmd.assert()
         .failure()
+        .code(3)
         .stderr(
             predicate::str::contains("个任务元数据解析失败")
         );
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_dry_run_conflicts_with_interactive() {
+    let mut cmd = main_command();
+    cmd.arg("-i").arg("--dry-run");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+const DRY_RUN_TEXTBOOK_ID: &str = "00000000-0000-0000-0000-000000000001";
+
+/// 运行一次 `--dry-run --output-format json`，返回 stdout 中的预览事件
+fn run_dry_run(home: &std::path::Path, out: &std::path::Path) -> Vec<serde_json::Value> {
+    let mut cmd = main_command();
+    cmd.env("HOME", home)
+        .env_remove("ACCESS_TOKEN")
+        .args(["--id", DRY_RUN_TEXTBOOK_ID, "--type", "tchMaterial", "--audio-format", "mp3"])
+        .args(["--dry-run", "--output-format", "json", "-o"])
+        .arg(out);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .filter(|event| event["event"] == "preview")
+        .collect()
+}
+
+/// 列出目录下的所有文件及其内容，用于确认某次运行没有修改该目录
+fn snapshot_dir(dir: &std::path::Path) -> Vec<(std::path::PathBuf, Vec<u8>)> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for entry in std::fs::read_dir(&current).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                pending.push(path);
+            } else {
+                let content = std::fs::read(&path).unwrap();
+                files.push((path, content));
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+#[test]
+fn test_dry_run_reports_paths_and_actions_without_writing() {
+    let mut server = mockito::Server::new();
+    let _details = server
+        .mock("GET", format!("/details/{}.json", DRY_RUN_TEXTBOOK_ID).as_str())
+        .with_body(std::fs::read_to_string("tests/fixtures/textbook_details_response.json").unwrap())
+        .create();
+    let _audio = server
+        .mock("GET", format!("/audio/{}.json", DRY_RUN_TEXTBOOK_ID).as_str())
+        .with_body(std::fs::read_to_string("tests/fixtures/textbook_audio_response.json").unwrap())
+        .create();
+
+    // 使用临时的用户主目录，配置文件中的接口指向模拟服务器
+    let home = tempdir().unwrap();
+    main_command().env("HOME", home.path()).args(["config", "show"]).assert().success();
+    let config_path = home.path().join(".sed-dl/config.json");
+    let mut config: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&config_path).unwrap()).unwrap();
+    let templates = &mut config["url_templates"];
+    templates["TEXTBOOK_DETAILS"] = format!("{}/details/{{resource_id}}.json", server.url()).into();
+    templates["TEXTBOOK_AUDIO"] = format!("{}/audio/{{resource_id}}.json", server.url()).into();
+    config["cache"]["enabled"] = false.into();
+    std::fs::write(&config_path, config.to_string()).unwrap();
+
+    let config_dir = home.path().join(".sed-dl");
+    let config_snapshot = snapshot_dir(&config_dir);
+    let out = home.path().join("downloads");
+    let entries = run_dry_run(home.path(), &out);
+    // 预览模式不应创建或改写下载状态数据库等用户主目录下的文件
+    assert_eq!(snapshot_dir(&config_dir), config_snapshot);
+    assert!(!config_dir.join("state.jsonl").exists());
+    let paths: Vec<std::path::PathBuf> = entries
+        .iter()
+        .map(|e| std::path::PathBuf::from(e["file"]["filepath"].as_str().unwrap()))
+        .collect();
+    // 1 个 PDF + 按 --audio-format 选出的 2 个 MP3，路径均位于输出目录下
+    assert_eq!(paths.len(), 3, "{:?}", paths);
+    assert!(paths.iter().all(|p| p.starts_with(&out)), "{:?}", paths);
+    assert!(paths.iter().any(|p| p.ends_with("语文一年级上册.pdf")));
+    assert!(paths.iter().any(|p| p.ends_with("[2] 第二课 生字.mp3")));
+    assert!(entries.iter().all(|e| e["action"] == "DownloadNew"));
+    assert!(!out.exists(), "预览模式不应创建任何文件或目录");
+
+    // 本地已有完整的文件时预览为跳过，不完整的文件预览为续传
+    let complete = paths.iter().find(|p| p.ends_with("[2] 第二课 生字.mp3")).unwrap();
+    let partial = paths.iter().find(|p| p.ends_with("[1] 第一课 课文朗读.mp3")).unwrap();
+    std::fs::create_dir_all(complete.parent().unwrap()).unwrap();
+    std::fs::write(complete, vec![0u8; 2048]).unwrap();
+    std::fs::write(partial, vec![0u8; 100]).unwrap();
+
+    let entries = run_dry_run(home.path(), &out);
+    let action_of = |path: &std::path::Path| {
+        entries
+            .iter()
+            .find(|e| std::path::Path::new(e["file"]["filepath"].as_str().unwrap()) == path)
+            .map(|e| e["action"].as_str().unwrap().to_string())
+            .unwrap()
+    };
+    assert_eq!(action_of(complete), "Skip");
+    assert_eq!(action_of(partial), "Resume");
+    assert_eq!(std::fs::read(partial).unwrap().len(), 100, "预览不应修改已有文件");
+    assert_eq!(snapshot_dir(&config_dir), config_snapshot);
+}
+
+#[test]
+fn test_single_mode_metadata_failure_exit_code() {
+    let mut server = mockito::Server::new();
+    let _details = server
+        .mock("GET", format!("/details/{}.json", DRY_RUN_TEXTBOOK_ID).as_str())
+        .with_status(404)
+        .create();
+
+    let home = tempdir().unwrap();
+    main_command().env("HOME", home.path()).args(["config", "show"]).assert().success();
+    let config_path = home.path().join(".sed-dl/config.json");
+    let mut config: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&config_path).unwrap()).unwrap();
+    config["url_templates"]["TEXTBOOK_DETAILS"] = format!("{}/details/{{resource_id}}.json", server.url()).into();
+    config["cache"]["enabled"] = false.into();
+    std::fs::write(&config_path, config.to_string()).unwrap();
+
+    main_command()
+        .env("HOME", home.path())
+        .env_remove("ACCESS_TOKEN")
+        .args(["--id", DRY_RUN_TEXTBOOK_ID, "--type", "tchMaterial"])
+        .assert()
+        .failure()
+        .code(3)
+        .stderr(predicate::str::contains("资源不存在 (链接或ID错误)"))
+        .stderr(predicate::str::contains("个任务元数据解析失败"));
+}
+
+#[test]
+fn test_batch_mode_json_output() {
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join("links.txt");
+    let mut file = File::create(&file_path).unwrap();
+    let fake_url = "http://127.0.0.1:9999/some/path/tchMaterial/some/resource?contentId=00000000-0000-0000-0000-000000000000";
+    writeln!(file, "{}", fake_url).unwrap();
+
+    let mut cmd = main_command();
+    cmd.arg("-b")
+        .arg(&file_path)
+        .arg("--type")
+        .arg("tchMaterial")
+        .arg("--output-format")
+        .arg("json");
+
+    let output = cmd.assert().failure().get_output().stdout.clone();
+    let events: Vec<serde_json::Value> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("JSON 模式下 stdout 的每一行都应是合法 JSON"))
+        .collect();
+
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0]["event"], "metadata_failed");
+    assert_eq!(events[0]["input"], fake_url);
+    assert_eq!(events[1]["event"], "error");
+    assert_eq!(events[1]["exit_code"], 3);
+}
+
+// --- 测试子命令 ---
+
+#[test]
+fn test_cli_definition_is_valid() {
+    use clap::CommandFactory;
+    sed_dl::cli::Cli::command().debug_assert();
+}
+
+#[test]
+fn test_subcommands_normalize_to_legacy_fields() {
+    use clap::Parser;
+    use sed_dl::cli::{Cli, Command};
+
+    let resource_id = "00000000-0000-0000-0000-000000000000";
+    let args = Cli::parse_from(["sed-dl", "download", resource_id, "--type", "tchMaterial", "-o", "out"]).normalize();
+    assert_eq!(args.id.as_deref(), Some(resource_id));
+    assert_eq!(args.url, None);
+    assert_eq!(args.options.output, Some(std::path::PathBuf::from("out")));
+
+    let args = Cli::parse_from(["sed-dl", "list", "https://example.com/a"]).normalize();
+    assert_eq!(args.url.as_deref(), Some("https://example.com/a"));
+    assert!(args.selection.dry_run);
+
+    let args = Cli::parse_from(["sed-dl", "token", "guide"]).normalize();
+    assert!(args.token_help);
+
+    // --profile 是全局参数，可写在子命令之后
+    let args = Cli::parse_from(["sed-dl", "token", "show", "--profile", "teacher"]).normalize();
+    assert_eq!(args.active_profile().as_deref(), Some("teacher"));
+
+    // browse/search 的筛选结果以教材 ID 批量下载
+    let args = Cli::parse_from(["sed-dl", "search", "语文", "--stage", "小学", "--download", "--select", "1-2"]).normalize();
+    assert_eq!(args.selection.r#type, Some(sed_dl::cli::ResourceType::TchMaterial));
+    assert_eq!(args.selection.select.as_deref(), Some("1-2"));
+    assert!(!args.interactive);
+    let args = Cli::parse_from(["sed-dl", "browse", "-i"]).normalize();
+    assert!(args.interactive);
+    // 未指定 --select 时与显式的 'all' 区分开，避免无筛选条件时下载整个目录
+    let Some(Command::Browse { filter, .. }) = &args.command else { panic!("应解析为 browse 子命令") };
+    assert!(filter.is_empty());
+    assert_eq!(args.selection.select, None);
+    assert_eq!(args.selection.selection(), "all");
+
+    let args = Cli::parse_from(["sed-dl", "lessons", resource_id, "--chapter", "第一单元"]).normalize();
+    assert_eq!(args.selection.r#type, Some(sed_dl::cli::ResourceType::SyncClassroom));
+}
+
+#[test]
+fn test_subcommand_conflicts_with_legacy_flags() {
+    let mut cmd = main_command();
+    cmd.arg("--url").arg("https://example.com").arg("download").arg("https://example.com");
+    cmd.assert().failure().code(1);
+}
+
+#[test]
+fn test_token_guide_subcommand() {
+    let mut cmd = main_command();
+    cmd.arg("token").arg("guide");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("登录平台: 使用 Chrome / Edge / Firefox"));
+}
+
+#[cfg(not(feature = "keyring"))]
+#[test]
+fn test_token_migrate_rejects_keyring_without_feature() {
+    let home = tempdir().unwrap();
+    main_command().env("HOME", home.path()).args(["config", "show"]).assert().success();
+    let config_path = home.path().join(".sed-dl/config.json");
+    let before = std::fs::read_to_string(&config_path).unwrap();
+
+    main_command()
+        .args(["token", "migrate", "--help"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("keyring").not());
+    main_command()
+        .env("HOME", home.path())
+        .args(["token", "migrate", "keyring"])
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("未启用系统密钥环支持"));
+    assert_eq!(std::fs::read_to_string(&config_path).unwrap(), before, "迁移失败时不应修改配置文件");
+}
+
+#[test]
+fn test_batch_subcommand_dispatch() {
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join("links.txt");
+    let mut file = File::create(&file_path).unwrap();
+    let fake_url = "http://127.0.0.1:9999/some/path/tchMaterial/some/resource?contentId=00000000-0000-0000-0000-000000000000";
+    writeln!(file, "{}", fake_url).unwrap();
+
+    let mut cmd = main_command();
+    cmd.arg("batch").arg(&file_path).arg("--type").arg("tchMaterial");
+    cmd.assert()
+        .failure()
+        .code(3)
+        .stderr(predicate::str::contains("个任务元数据解析失败"));
+}
@@ -0,0 +1,84 @@
+// tests/client_cache_test.rs
+
+use mockito::Matcher;
+use sed_dl::{client::RobustClient, config::AppConfig, error::AppResult};
+use serde_json::Value;
+use std::{sync::Arc, time::Duration};
+
+fn cached_client(cache_dir: &std::path::Path, ttl: Duration) -> RobustClient {
+    let config = AppConfig {
+        cache_dir: Some(cache_dir.to_path_buf()),
+        cache_ttl: ttl,
+        ..Default::default()
+    };
+    RobustClient::new(Arc::new(config)).expect("Failed to create client")
+}
+
+#[tokio::test]
+async fn test_fetch_json_serves_fresh_entries_from_cache() -> AppResult<()> {
+    // --- 1. Arrange (准备阶段) ---
+    let mut server = mockito::Server::new_async().await;
+    let cache_dir = tempfile::tempdir()?;
+    // TTL 内的第二次请求不应到达服务器
+    let tree_mock = server
+        .mock("GET", "/trees/tree-1.json")
+        .with_status(200)
+        .with_header("ETag", "\"v1\"")
+        .with_body(r#"{"child_nodes": [{"id": "unit-1", "title": "第一单元"}]}"#)
+        .expect(1)
+        .create_async()
+        .await;
+    let url_template = format!("{}/trees/{{tree_id}}.json", server.url());
+
+    // --- 2. Act (执行阶段) ---
+    let client = cached_client(cache_dir.path(), Duration::from_secs(3600));
+    let first: Value = client.fetch_json(&url_template, &[("tree_id", "tree-1")]).await?;
+    // 新的客户端 (例如下一次运行) 同样读取磁盘上的缓存
+    let client = cached_client(cache_dir.path(), Duration::from_secs(3600));
+    let second: Value = client.fetch_json(&url_template, &[("tree_id", "tree-1")]).await?;
+
+    // --- 3. Assert (断言阶段) ---
+    tree_mock.assert_async().await;
+    assert_eq!(first, second);
+    assert_eq!(second["child_nodes"][0]["title"], "第一单元");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fetch_json_revalidates_stale_entries_with_etag() -> AppResult<()> {
+    // --- 1. Arrange (准备阶段) ---
+    let mut server = mockito::Server::new_async().await;
+    let cache_dir = tempfile::tempdir()?;
+    let full_mock = server
+        .mock("GET", "/trees/tree-1.json")
+        .match_header("if-none-match", Matcher::Missing)
+        .with_status(200)
+        .with_header("ETag", "\"v1\"")
+        .with_body(r#"{"title": "缓存的章节树"}"#)
+        .expect(1)
+        .create_async()
+        .await;
+    let not_modified_mock = server
+        .mock("GET", "/trees/tree-1.json")
+        .match_header("if-none-match", "\"v1\"")
+        .with_status(304)
+        .expect(2)
+        .create_async()
+        .await;
+    let url_template = format!("{}/trees/{{tree_id}}.json", server.url());
+
+    // --- 2. Act (执行阶段) ---
+    // TTL 为 0：每次都需要向服务器确认，304 时沿用缓存的内容
+    let client = cached_client(cache_dir.path(), Duration::ZERO);
+    let mut titles = Vec::new();
+    for _ in 0..3 {
+        let data: Value = client.fetch_json(&url_template, &[("tree_id", "tree-1")]).await?;
+        titles.push(data["title"].as_str().unwrap_or_default().to_string());
+    }
+
+    // --- 3. Assert (断言阶段) ---
+    full_mock.assert_async().await;
+    not_modified_mock.assert_async().await;
+    assert!(titles.iter().all(|t| t == "缓存的章节树"), "{:?}", titles);
+    Ok(())
+}
@@ -0,0 +1,67 @@
+// tests/client_record_replay_test.rs
+
+use sed_dl::{client::RobustClient, config::AppConfig, error::AppResult};
+use serde_json::Value;
+use std::{fs, sync::Arc};
+
+#[tokio::test]
+async fn test_recorded_traffic_replays_without_network() -> AppResult<()> {
+    // --- 1. Arrange (准备阶段) ---
+    let mut server = mockito::Server::new_async().await;
+    let server_url = server.url();
+    let record_dir = tempfile::tempdir()?;
+    let details_mock = server
+        .mock("GET", "/resources/course-1.json")
+        .with_status(200)
+        .with_body(r#"{"id": "course-1", "title": "测试课程"}"#)
+        .expect(1)
+        .create_async()
+        .await;
+    let playlist_mock = server
+        .mock("GET", "/video/master.m3u8")
+        .match_query(mockito::Matcher::UrlEncoded("accessToken".into(), "secret-token".into()))
+        .with_status(200)
+        .with_body("#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=1000\nhd/index.m3u8?accessToken=secret-token\n")
+        .expect(1)
+        .create_async()
+        .await;
+    let url_template = format!("{}/resources/{{resource_id}}.json", server_url);
+    let playlist_url = format!("{}/video/master.m3u8?accessToken=secret-token", server_url);
+
+    // --- 2. Act (执行阶段) ---
+    // 记录模式：正常访问网络，并保存响应
+    let recording = AppConfig {
+        record_dir: Some(record_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let client = RobustClient::new(Arc::new(recording))?;
+    let recorded: Value = client.fetch_json(&url_template, &[("resource_id", "course-1")]).await?;
+    client.fetch_text(&playlist_url).await?;
+
+    // 回放模式：使用另一个 Token 也能找到记录，且不再访问网络
+    let replaying = AppConfig {
+        replay_dir: Some(record_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let client = RobustClient::new(Arc::new(replaying))?;
+    let replayed: Value = client.fetch_json(&url_template, &[("resource_id", "course-1")]).await?;
+    let playlist = client
+        .fetch_text(&format!("{}/video/master.m3u8?accessToken=other-token", server_url))
+        .await?;
+    let missing = client.fetch_json::<Value>(&url_template, &[("resource_id", "course-2")]).await;
+
+    // --- 3. Assert (断言阶段) ---
+    details_mock.assert_async().await;
+    playlist_mock.assert_async().await;
+    assert_eq!(recorded, replayed);
+    assert_eq!(replayed["title"], "测试课程");
+    assert!(playlist.contains("hd/index.m3u8?accessToken=REDACTED"), "{}", playlist);
+    assert!(missing.is_err(), "没有记录的请求在回放模式下应当失败");
+
+    // 记录目录中的任何文件都不应包含 Token
+    for entry in fs::read_dir(record_dir.path())? {
+        let content = fs::read_to_string(entry?.path())?;
+        assert!(!content.contains("secret-token"), "{}", content);
+    }
+    Ok(())
+}
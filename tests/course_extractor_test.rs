@@ -4,17 +4,13 @@ use clap::Parser;
 use sed_dl::{
     DownloadJobContext,
     cli::Cli,
-    client::RobustClient,
     config::AppConfig,
-    downloader::DownloadManager,
     downloader::negotiator::ItemNegotiator,
     error::AppResult,
     extractor::{ResourceExtractor, course::CourseExtractor},
 };
 use std::collections::HashMap;
 use std::fs;
-use std::sync::{Arc, atomic::AtomicBool};
-use tokio::sync::Mutex as TokioMutex;
 
 #[tokio::test]
 async fn test_course_extractor_parses_mock_response() -> AppResult<()> {
@@ -53,10 +49,8 @@ async fn test_course_extractor_parses_mock_response() -> AppResult<()> {
 
     config.server_prefixes = vec!["unused".to_string()];
 
-    let config = Arc::new(config);
-
     // --- 3. 模拟命令行参数来创建 Cli 实例 ---
-    let args = Arc::new(Cli::parse_from([
+    let args = Cli::parse_from([
         "sed-dl", // 程序名
         "--url",
         "unused_url", // 满足 mode group 的要求
@@ -64,18 +58,10 @@ async fn test_course_extractor_parses_mock_response() -> AppResult<()> {
         "best", // 这是测试需要的关键参数
         "--select",
         "all", // 确保是非交互模式行为
-    ]));
+    ]);
 
     // --- 3. 创建测试所需的 DownloadJobContext ---
-    let context = DownloadJobContext {
-        manager: DownloadManager::new(),
-        token: Arc::new(TokioMutex::new("fake-token".to_string())),
-        config: config.clone(), // 使用我们修改过的 config
-        http_client: Arc::new(RobustClient::new(config.clone())?),
-        args: args.clone(),
-        non_interactive: !args.interactive,
-        cancellation_token: Arc::new(AtomicBool::new(false)),
-    };
+    let context = DownloadJobContext::for_test(args, config);
 
     // --- 4. Act (执行阶段) ---
     // 这里的 url_template 必须与 extractor 内部获取的一致。
@@ -124,6 +110,11 @@ async fn test_course_extractor_parses_mock_response() -> AppResult<()> {
         "视频文件名格式不正确"
     );
     assert_eq!(video_info.ti_size, Some(12345678), "视频大小解析错误"); // 验证是否从 custom_properties 获取了 total_size
+    // 清晰度、教师等信息以结构化字段提供，不需要从文件名中解析
+    assert_eq!(video_info.quality, Some(720));
+    assert_eq!(video_info.teacher.as_deref(), Some("张老师"));
+    assert_eq!(video_info.lesson_title.as_deref(), Some("示例课程标题 - 课堂录像"));
+    assert_eq!(video_info.format.as_deref(), Some("ts"));
 
     // 查找并验证PDF文件的信息
     let pdf_info = file_infos
@@ -137,11 +128,23 @@ async fn test_course_extractor_parses_mock_response() -> AppResult<()> {
         "PDF文件名格式不正确"
     );
     assert_eq!(pdf_info.ti_size, Some(102400), "PDF大小解析错误");
+    assert_eq!(pdf_info.quality, None);
+    assert_eq!(pdf_info.format.as_deref(), Some("pdf"));
+    assert!(video_info.variant_group.is_some());
+    assert_ne!(video_info.variant_group, pdf_info.variant_group, "不同资源应属于不同分组");
     assert_eq!(
         pdf_info.ti_md5,
         Some("d41d8cd98f00b204e9800998ecf8427e".to_string()),
         "PDF MD5解析错误"
     );
 
+    // 同一课程的所有文件共享课程级元数据
+    let metadata = video_info.metadata.as_ref().expect("视频应附带课程元数据");
+    assert_eq!(metadata.resource_id, resource_id);
+    assert_eq!(metadata.title, "示例课程标题");
+    assert!(metadata.teachers.contains(&"张老师".to_string()));
+    assert!(metadata.tags.iter().any(|t| t.name == "语文"));
+    assert!(std::sync::Arc::ptr_eq(metadata, pdf_info.metadata.as_ref().unwrap()));
+
     Ok(())
 }
@@ -2,19 +2,13 @@
 
 use sed_dl::{
     cli::Cli,
-    client::RobustClient,
     config::AppConfig,
-    downloader::DownloadManager,
     error::AppResult,
     extractor::{sync_classroom::SyncClassroomExtractor, ResourceExtractor},
     DownloadJobContext,
 };
 use clap::Parser;
-use std::{
-    fs,
-    sync::{atomic::AtomicBool, Arc},
-};
-use tokio::sync::Mutex as TokioMutex;
+use std::fs;
 
 #[tokio::test]
 async fn test_sync_classroom_extractor_parses_correctly() -> AppResult<()> {
@@ -47,19 +41,9 @@ async fn test_sync_classroom_extractor_parses_correctly() -> AppResult<()> {
             server_url
         ),
     );
-    let config = Arc::new(config);
-
-    let args = Arc::new(Cli::parse_from(["sed-dl", "--id", resource_id, "--type", "syncClassroom/classActivity"]));
-
-    let context = DownloadJobContext {
-        manager: DownloadManager::new(),
-        token: Arc::new(TokioMutex::new("fake-token".to_string())),
-        config: config.clone(),
-        http_client: Arc::new(RobustClient::new(config.clone())?),
-        args,
-        non_interactive: true,
-        cancellation_token: Arc::new(AtomicBool::new(false)),
-    };
+    let args = Cli::parse_from(["sed-dl", "--id", resource_id, "--type", "syncClassroom/classActivity"]);
+
+    let context = DownloadJobContext::for_test(args, config);
 
     // --- 3. Act (执行阶段) ---
     let extractor_template = context.config.url_templates.get("COURSE_SYNC").unwrap().clone();
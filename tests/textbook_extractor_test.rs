@@ -2,16 +2,12 @@
 
 use sed_dl::{
     cli::Cli,
-    client::RobustClient,
     config::AppConfig,
-    downloader::DownloadManager,
     error::AppResult,
     extractor::{textbook::TextbookExtractor, ResourceExtractor},
     DownloadJobContext,
 };
 use clap::Parser;
-use std::sync::{atomic::AtomicBool, Arc};
-use tokio::sync::Mutex as TokioMutex;
 
 #[tokio::test]
 async fn test_textbook_extractor_parses_pdf_and_audio() -> AppResult<()> {
@@ -71,19 +67,9 @@ async fn test_textbook_extractor_parses_pdf_and_audio() -> AppResult<()> {
             server_url
         ),
     );
-    let config = Arc::new(config);
-
-    let args = Arc::new(Cli::parse_from(["sed-dl", "--id", resource_id, "--type", "tchMaterial"]));
-
-    let context = DownloadJobContext {
-        manager: DownloadManager::new(),
-        token: Arc::new(TokioMutex::new("fake-token".to_string())),
-        config: config.clone(),
-        http_client: Arc::new(RobustClient::new(config.clone())?),
-        args,
-        non_interactive: true,
-        cancellation_token: Arc::new(AtomicBool::new(false)),
-    };
+    let args = Cli::parse_from(["sed-dl", "--id", resource_id, "--type", "tchMaterial"]);
+
+    let context = DownloadJobContext::for_test(args, config);
 
     // --- 3. Act (执行阶段) ---
     let extractor = TextbookExtractor::new(context.http_client.clone(), context.config.clone());
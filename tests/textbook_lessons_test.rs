@@ -0,0 +1,93 @@
+// tests/textbook_lessons_test.rs
+
+use sed_dl::{
+    client::RobustClient, config::AppConfig, error::AppResult,
+    extractor::textbook_lessons::TextbookLessonsResolver,
+};
+use std::sync::Arc;
+
+#[tokio::test]
+async fn test_list_lessons_follows_chapter_tree_order() -> AppResult<()> {
+    // --- 1. Arrange (准备阶段) ---
+    let mut server = mockito::Server::new_async().await;
+    let server_url = server.url();
+    let textbook_id = "fake-textbook-id";
+
+    // 章节树以教材 ID 为树 ID
+    let tree_mock = server
+        .mock("GET", format!("/trees/{}.json", textbook_id).as_str())
+        .with_status(200)
+        .with_body(
+            r#"{"child_nodes": [
+                {"id": "unit-1", "title": "第一单元", "child_nodes": [
+                    {"id": "lesson-node-1", "title": "1 秋天"},
+                    {"id": "lesson-node-2", "title": "2 小小的船"}
+                ]},
+                {"id": "unit-2", "title": "第二单元", "child_nodes": [
+                    {"id": "lesson-node-3", "title": "3 江南"}
+                ]}
+            ]}"#,
+        )
+        .create_async()
+        .await;
+    let parts_mock = server
+        .mock("GET", format!("/lessons/{}/parts.json", textbook_id).as_str())
+        .with_status(200)
+        .with_body(format!(r#"["{0}/parts/0.json", "{0}/parts/1.json"]"#, server_url))
+        .create_async()
+        .await;
+    // 分片中的课时顺序与目录顺序不同，且有一个课时找不到所属章节
+    let part0_mock = server
+        .mock("GET", "/parts/0.json")
+        .with_status(200)
+        .with_body(
+            r#"[
+                {"id": "activity-3", "global_title": {"zh-CN": "江南"}, "chapter_paths": ["fake-textbook-id/unit-2/lesson-node-3"]},
+                {"id": "activity-x", "title": "拓展阅读", "chapter_paths": ["fake-textbook-id/removed-node"]}
+            ]"#,
+        )
+        .create_async()
+        .await;
+    let part1_mock = server
+        .mock("GET", "/parts/1.json")
+        .with_status(200)
+        .with_body(
+            r#"[
+                {"id": "activity-2", "global_title": {"zh-CN": "小小的船"}, "chapter_paths": ["fake-textbook-id/unit-1/lesson-node-2"]},
+                {"id": "activity-1a", "global_title": {"zh-CN": "秋天（第一课时）"}, "chapter_paths": ["fake-textbook-id/unit-1/lesson-node-1"]},
+                {"id": "activity-1b", "global_title": {"zh-CN": "秋天（第二课时）"}, "chapter_paths": ["fake-textbook-id/unit-1/lesson-node-1"]}
+            ]"#,
+        )
+        .create_async()
+        .await;
+
+    let mut config = AppConfig::default();
+    config.url_templates.insert(
+        "CHAPTER_TREE".to_string(),
+        format!("{}/trees/{{tree_id}}.json", server_url),
+    );
+    config.url_templates.insert(
+        "TEXTBOOK_LESSONS".to_string(),
+        format!("{}/lessons/{{resource_id}}/parts.json", server_url),
+    );
+    let config = Arc::new(config);
+    let resolver = TextbookLessonsResolver::new(Arc::new(RobustClient::new(config.clone())?), config);
+
+    // --- 2. Act (执行阶段) ---
+    let lessons = resolver.list_lessons(textbook_id).await?;
+
+    // --- 3. Assert (断言阶段) ---
+    tree_mock.assert_async().await;
+    parts_mock.assert_async().await;
+    part0_mock.assert_async().await;
+    part1_mock.assert_async().await;
+
+    let ids: Vec<&str> = lessons.iter().map(|l| l.id.as_str()).collect();
+    assert_eq!(ids, ["activity-1a", "activity-1b", "activity-2", "activity-3", "activity-x"]);
+    assert_eq!(lessons[0].title, "秋天（第一课时）");
+    assert_eq!(lessons[0].chapter_path, ["第一单元", "1 秋天"]);
+    assert_eq!(lessons[3].chapter_path, ["第二单元", "3 江南"]);
+    assert!(lessons[4].chapter_path.is_empty(), "找不到章节的课时应排在最后");
+
+    Ok(())
+}